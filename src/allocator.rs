@@ -0,0 +1,76 @@
+use std::fs;
+#[cfg(feature = "allocator_stats")]
+use std::alloc::{GlobalAlloc, Layout, System};
+#[cfg(feature = "allocator_stats")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "allocator_stats")]
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+// System allocator wrapper that keeps track of the number of bytes currently allocated
+#[cfg(feature = "allocator_stats")]
+pub struct CountingAllocator;
+
+#[cfg(feature = "allocator_stats")]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            if new_size > layout.size() {
+                ALLOCATED.fetch_add(new_size - layout.size(), Ordering::Relaxed);
+            } else {
+                ALLOCATED.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+            }
+        }
+        new_ptr
+    }
+}
+
+#[cfg(feature = "allocator_stats")]
+pub fn allocated_memory() -> Option<usize> {
+    Some(ALLOCATED.load(Ordering::Relaxed))
+}
+
+#[cfg(not(feature = "allocator_stats"))]
+pub fn allocated_memory() -> Option<usize> {
+    None
+}
+
+pub fn allocator_name() -> &'static str {
+    if cfg!(feature = "allocator_stats") {
+        "counting"
+    } else {
+        "libc"
+    }
+}
+
+// resident set size from /proc/self/status (linux only)
+pub fn resident_memory() -> Option<usize> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status.lines()
+        .find(|l| l.starts_with("VmRSS:"))
+        .and_then(|l| l.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<usize>().ok())
+        .map(|kb| kb * 1024)
+}
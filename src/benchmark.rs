@@ -1,78 +1,516 @@
-use std::io::{Error, Read, Write};
-use std::net::TcpStream;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
 use std::sync::{Arc, Barrier};
-use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 use rand::Rng;
-use crate::resp_encoder::resp_encode_strings;
+use crate::client::Connection;
+use crate::latency::LatencyStats;
+use crate::resp_codec::Encoder;
+use crate::resp_codec::RespToken::RespError;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum BenchmarkCommand {
     Get,
     Set,
     SetPX,
-    Ping
+    Ping,
+    HSet,
+    HGet,
+    SAdd,
+    LPush,
+    ZAdd,
+}
+
+impl BenchmarkCommand {
+    pub fn parse(name: &str) -> Option<BenchmarkCommand> {
+        match name {
+            "get" => Some(BenchmarkCommand::Get),
+            "set" => Some(BenchmarkCommand::Set),
+            "setpx" => Some(BenchmarkCommand::SetPX),
+            "ping" => Some(BenchmarkCommand::Ping),
+            "hset" => Some(BenchmarkCommand::HSet),
+            "hget" => Some(BenchmarkCommand::HGet),
+            "sadd" => Some(BenchmarkCommand::SAdd),
+            "lpush" => Some(BenchmarkCommand::LPush),
+            "zadd" => Some(BenchmarkCommand::ZAdd),
+            _ => None
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            BenchmarkCommand::Get => "get",
+            BenchmarkCommand::Set => "set",
+            BenchmarkCommand::SetPX => "setpx",
+            BenchmarkCommand::Ping => "ping",
+            BenchmarkCommand::HSet => "hset",
+            BenchmarkCommand::HGet => "hget",
+            BenchmarkCommand::SAdd => "sadd",
+            BenchmarkCommand::LPush => "lpush",
+            BenchmarkCommand::ZAdd => "zadd"
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Payload {
+    // random bytes, values cannot be compressed
+    Random,
+    // the key repeated up to the value size
+    Compressible,
+}
+
+impl Payload {
+    pub fn parse(name: &str) -> Option<Payload> {
+        match name {
+            "random" => Some(Payload::Random),
+            "compressible" => Some(Payload::Compressible),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ResultFormat {
+    Csv,
+    Json,
+}
+
+impl ResultFormat {
+    pub fn parse(name: &str) -> Option<ResultFormat> {
+        match name {
+            "csv" => Some(ResultFormat::Csv),
+            "json" => Some(ResultFormat::Json),
+            _ => None
+        }
+    }
+}
+
+// comma separated command[:weight] list, the default weight is 1, a command can be listed more than once
+pub fn parse_command_mix(types: &str) -> Option<Vec<(BenchmarkCommand, usize)>> {
+    let mut result = Vec::new();
+    for t in types.split(',') {
+        let (name, weight) = match t.split_once(':') {
+            Some((name, weight)) => (name, weight.parse::<usize>().ok()?),
+            None => (t, 1)
+        };
+        let command = BenchmarkCommand::parse(name)?;
+        if weight > 0 {
+            result.push((command, weight));
+        }
+    }
+    if result.is_empty() { None } else { Some(result) }
+}
+
+#[derive(Clone)]
+pub struct BenchmarkSettings {
+    // host:port addresses, worker connections are spread across them
+    pub addresses: Vec<String>,
+    pub keys: usize,
+    // requests per thread
+    pub requests: usize,
+    pub threads: usize,
+    // key expiration in ms for SetPX
+    pub expiration: usize,
+    // commands with weights, see parse_command_mix
+    pub types: Vec<(BenchmarkCommand, usize)>,
+    // number of commands sent in one write, 1 - no pipelining
+    pub pipeline: usize,
+    // 0 - the value is the key
+    pub value_size: usize,
+    // number of distinct fields or members per key for hash, set and sorted set commands
+    pub fields: usize,
+    pub payload: Payload,
+    // results file format and name
+    pub output: Option<(ResultFormat, String)>,
+    // baseline name to save results to
+    pub save_baseline: Option<String>,
+    // baseline name to compare results with, a regression is returned as an error
+    pub compare_baseline: Option<String>,
+    // allowed throughput decrease and p99 latency increase in percent
+    pub regression_threshold: f64,
+    // requests are sent before the measured run and are not counted, 0 - no warmup
+    pub warmup: Duration,
+    // the measured run time, requests are repeated until it elapses, 0 - every request is sent once
+    pub duration: Duration,
+}
+
+#[derive(Default)]
+struct CommandResults {
+    requests: usize,
+    errors: usize,
+    latency: LatencyStats,
 }
 
-pub fn benchmark_mode(port: u16, host: String, keys: usize, requests: usize, threads: usize,
-                      expiration: usize, types: [BenchmarkCommand; 4]) -> Result<(), Error> {
+impl CommandResults {
+    fn merge(&mut self, other: &CommandResults) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.latency.merge(&other.latency);
+    }
+}
+
+pub fn benchmark_mode(settings: BenchmarkSettings) -> Result<(), Error> {
     let mut tasks = Vec::new();
-    let error_count = Arc::new(AtomicUsize::new(0));
-    let barrier = Arc::new(Barrier::new(threads + 1));
-    for _i in 0..threads {
-        let h = host.clone();
-        let t: [BenchmarkCommand; 4] = [types[0].clone(), types[1].clone(), types[2].clone(), types[3].clone()];
-        let ec = error_count.clone();
+    let barrier = Arc::new(Barrier::new(settings.threads + 1));
+    let warmup_barrier = Arc::new(Barrier::new(settings.threads + 1));
+    let settings = Arc::new(settings);
+    for i in 0..settings.threads {
+        let s = settings.clone();
         let b = barrier.clone();
+        let wb = warmup_barrier.clone();
         tasks.push(thread::spawn(move ||{
-            if let Err(e) = benchmark_worker(port, h, keys, requests, expiration, t,
-                                             ec, b) {
-                println!("{}", e);
-            }
+            benchmark_worker(s, i, b, wb)
+                .unwrap_or_else(|e| {
+                    println!("{}", e);
+                    BTreeMap::new()
+                })
         }))
     }
     println!("Preparing data for tests...");
     let start = SystemTime::now();
     barrier.wait();
     println!("{} ms Starting tests...", start.elapsed().unwrap().as_millis());
+    if !settings.warmup.is_zero() {
+        println!("Warming up for {:?}...", settings.warmup);
+    }
+    warmup_barrier.wait();
+    // data preparation and warmup time is not included
+    let start = SystemTime::now();
+    let mut results: BTreeMap<&'static str, CommandResults> = BTreeMap::new();
     for task in tasks {
-        task.join().unwrap();
+        for (name, r) in task.join().unwrap() {
+            results.entry(name).or_default().merge(&r);
+        }
     }
-    let elapsed = start.elapsed().unwrap().as_millis() as usize;
+    let elapsed = (start.elapsed().unwrap().as_millis() as usize).max(1);
+    let mut total = CommandResults::default();
+    results.values().for_each(|r| total.merge(r));
     println!("Elapsed: {} ms, {} requests per second {} errors",
-             elapsed, requests * threads * 1000 / elapsed, error_count.load(Ordering::Relaxed));
+             elapsed, total.requests * 1000 / elapsed, total.errors);
+    print_latency(&mut total.latency);
+    // commands in the mix use different code paths: read lock, write lock, expiration index
+    if results.len() > 1 {
+        println!("Per command type:");
+        for (name, r) in results.iter_mut() {
+            println!("  {}: {} requests, {} requests per second, {} errors, latency (ms): {}", name, r.requests,
+                     r.requests * 1000 / elapsed, r.errors, format_percentiles(&mut r.latency));
+        }
+    }
+    if let Some((format, file_name)) = &settings.output {
+        fs::write(file_name, format_results(&mut total, &mut results, elapsed, *format))?;
+        println!("Results are saved to {}", file_name);
+    }
+    let csv = format_results(&mut total, &mut results, elapsed, ResultFormat::Csv);
+    if let Some(name) = &settings.save_baseline {
+        fs::write(baseline_file_name(name), &csv)?;
+        println!("Baseline {} is saved", name);
+    }
+    if let Some(name) = &settings.compare_baseline {
+        let baseline = fs::read_to_string(baseline_file_name(name))?;
+        let (report, regression) = compare_results(&baseline, &csv, settings.regression_threshold)?;
+        println!("Comparison with baseline {}:\n{}", name, report);
+        if regression {
+            return Err(Error::other(format!("performance regression against baseline {}", name)));
+        }
+    }
     Ok(())
 }
 
-fn benchmark_worker(port: u16, host: String, keys: usize, requests: usize, expiration: usize,
-                    types: [BenchmarkCommand; 4], error_count: Arc<AtomicUsize>, barrier: Arc<Barrier>) -> Result<(), Error> {
+fn baseline_file_name(name: &str) -> String {
+    format!("{}.baseline.csv", name)
+}
+
+// command -> (requests per second, p50, p99) from format_results CSV output
+fn parse_results(csv: &str) -> Result<BTreeMap<String, (f64, f64, f64)>, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "invalid benchmark results");
+    let mut lines = csv.lines();
+    let header: Vec<&str> = lines.next().ok_or_else(invalid)?.split(',').collect();
+    let column = |name: &str| header.iter().position(|h| *h == name).ok_or_else(invalid);
+    let (command, rps, p50, p99) = (column("command")?, column("requests_per_second")?, column("p50_ms")?,
+                                    column("p99_ms")?);
+    let mut result = BTreeMap::new();
+    for line in lines {
+        let values: Vec<&str> = line.split(',').collect();
+        if values.len() != header.len() {
+            return Err(invalid());
+        }
+        let number = |idx: usize| values[idx].parse::<f64>().map_err(|_e| invalid());
+        result.insert(values[command].trim_matches('"').to_string(), (number(rps)?, number(p50)?, number(p99)?));
+    }
+    Ok(result)
+}
+
+// returns the report and whether throughput or p99 latency of any command is worse than threshold percent
+fn compare_results(baseline: &str, current: &str, threshold: f64) -> Result<(String, bool), Error> {
+    let baseline = parse_results(baseline)?;
+    let current = parse_results(current)?;
+    let delta = |old: f64, new: f64| if old == 0.0 { 0.0 } else { (new - old) * 100.0 / old };
+    let mut lines = Vec::new();
+    let mut regression = false;
+    for (command, (rps, p50, p99)) in &current {
+        match baseline.get(command) {
+            Some((old_rps, old_p50, old_p99)) => {
+                let (rps_delta, p99_delta) = (delta(*old_rps, *rps), delta(*old_p99, *p99));
+                let command_regression = -rps_delta > threshold || p99_delta > threshold;
+                regression |= command_regression;
+                lines.push(format!("  {}: requests per second {} -> {} ({:+.2}%), p50 {:.3} -> {:.3} ms ({:+.2}%), p99 {:.3} -> {:.3} ms ({:+.2}%){}",
+                                   command, old_rps, rps, rps_delta, old_p50, p50, delta(*old_p50, *p50), old_p99, p99,
+                                   p99_delta, if command_regression { " REGRESSION" } else { "" }));
+            }
+            None => lines.push(format!("  {}: not in the baseline", command))
+        }
+    }
+    Ok((lines.join("\n"), regression))
+}
+
+// one line or object for all commands and every command type
+fn format_results(total: &mut CommandResults, results: &mut BTreeMap<&'static str, CommandResults>, elapsed: usize,
+                  format: ResultFormat) -> String {
+    let rows: Vec<Vec<(&str, String)>> = std::iter::once(("all", total))
+        .chain(results.iter_mut().map(|(name, r)| (*name, r)))
+        .map(|(name, r)| {
+            let ms = |v: u64| format!("{:.3}", v as f64 / 1000.0);
+            vec![("command", format!("\"{}\"", name)), ("requests", r.requests.to_string()),
+                 ("errors", r.errors.to_string()), ("requests_per_second", (r.requests * 1000 / elapsed).to_string()),
+                 ("min_ms", ms(r.latency.min())), ("p50_ms", ms(r.latency.percentile(50.0))),
+                 ("p95_ms", ms(r.latency.percentile(95.0))), ("p99_ms", ms(r.latency.percentile(99.0))),
+                 ("p99_9_ms", ms(r.latency.percentile(99.9))), ("max_ms", ms(r.latency.max()))]
+        }).collect();
+    match format {
+        ResultFormat::Csv => {
+            let mut lines = vec![rows[0].iter().map(|(n, _v)| *n).collect::<Vec<_>>().join(",")];
+            rows.iter().for_each(|r| lines.push(r.iter().map(|(_n, v)| v.as_str()).collect::<Vec<_>>().join(",")));
+            lines.join("\n") + "\n"
+        }
+        ResultFormat::Json => {
+            let objects: Vec<String> = rows.iter()
+                .map(|r| format!("{{{}}}", r.iter().map(|(n, v)| format!("\"{}\":{}", n, v)).collect::<Vec<_>>().join(",")))
+                .collect();
+            format!("{{\"elapsed_ms\":{},\"results\":[{}]}}\n", elapsed, objects.join(","))
+        }
+    }
+}
+
+fn format_percentiles(latency: &mut LatencyStats) -> String {
+    format!("min {:.3}, p50 {:.3}, p95 {:.3}, p99 {:.3}, p99.9 {:.3}, max {:.3}",
+            latency.min() as f64 / 1000.0, latency.percentile(50.0) as f64 / 1000.0,
+            latency.percentile(95.0) as f64 / 1000.0, latency.percentile(99.0) as f64 / 1000.0,
+            latency.percentile(99.9) as f64 / 1000.0, latency.max() as f64 / 1000.0)
+}
+
+fn print_latency(latency: &mut LatencyStats) {
+    println!("Latency (ms): {}", format_percentiles(latency));
+    println!("Latency histogram:");
+    let total = latency.count() as f64;
+    let mut cumulative = 0;
+    for (bound, count) in latency.histogram() {
+        cumulative += count;
+        println!("  <= {:.3} ms: {:.2}% (cumulative {:.2}%)", bound as f64 / 1000.0, count as f64 * 100.0 / total,
+                 cumulative as f64 * 100.0 / total);
+    }
+}
+
+// with pipelining the latency of every request in a batch is the time to send the batch and read all replies
+fn benchmark_worker(settings: Arc<BenchmarkSettings>, index: usize, barrier: Arc<Barrier>,
+                    warmup_barrier: Arc<Barrier>) -> Result<BTreeMap<&'static str, CommandResults>, Error> {
     let mut rng = rand::thread_rng();
-    let keys4 = keys * 4;
-    let ex = expiration.to_string();
-    let mut commands = Vec::new();
-    for _i in 0..requests {
-        let n = rng.gen::<usize>() % keys4;
-        let key = (n / 4).to_string();
-        let data = match &types[n & 3] {
-            BenchmarkCommand::Get => resp_encode_strings(&vec!["get".to_string(), key]),
-            BenchmarkCommand::Set => resp_encode_strings(&vec!["set".to_string(), key.clone(), key]),
-            BenchmarkCommand::Ping => "ping\r\n".to_string().into_bytes(),
-            _ => resp_encode_strings(&vec!["set".to_string(), key.clone(), key, "px".to_string(), ex.clone()])
+    let total_weight: usize = settings.types.iter().map(|(_c, w)| w).sum();
+    let ex = settings.expiration.to_string();
+    let mut encoder = Encoder::new();
+    let mut batches = Vec::new();
+    let mut names = Vec::new();
+    for i in 0..settings.requests {
+        let key = (rng.gen::<usize>() % settings.keys).to_string();
+        let key = key.as_bytes();
+        let field = (rng.gen::<usize>() % settings.fields).to_string();
+        let field = field.as_bytes();
+        let command = select_command(&settings.types, rng.gen::<usize>() % total_weight);
+        match command {
+            BenchmarkCommand::Get => encoder.encode_command(&[b"get", key]),
+            BenchmarkCommand::Set => encoder.encode_command(&[b"set", key, &build_value(&settings, key, &mut rng)]),
+            BenchmarkCommand::SetPX =>
+                encoder.encode_command(&[b"set", key, &build_value(&settings, key, &mut rng), b"px", ex.as_bytes()]),
+            BenchmarkCommand::Ping => encoder.encode_command(&[b"ping"]),
+            // every data type has its own key prefix, so keys of different types do not collide
+            BenchmarkCommand::HSet => encoder.encode_command(&[b"hset", &[b"h:", key].concat(), field,
+                &build_value(&settings, key, &mut rng)]),
+            BenchmarkCommand::HGet => encoder.encode_command(&[b"hget", &[b"h:", key].concat(), field]),
+            BenchmarkCommand::SAdd => encoder.encode_command(&[b"sadd", &[b"s:", key].concat(), field]),
+            BenchmarkCommand::LPush => encoder.encode_command(&[b"lpush", &[b"l:", key].concat(),
+                &build_value(&settings, key, &mut rng)]),
+            // the field is used as the score too
+            BenchmarkCommand::ZAdd => encoder.encode_command(&[b"zadd", &[b"z:", key].concat(), field, field])
         };
-        commands.push(data);
+        names.push(command.get_name());
+        if (i + 1) % settings.pipeline == 0 || i + 1 == settings.requests {
+            batches.push((encoder.take(), std::mem::take(&mut names)));
+        }
     }
-    let mut buffer = [0; 1000];
-    let mut error_counter = 0;
-    let mut connection = TcpStream::connect(format!("{}:{}", host, port))?;
+    let connection = Connection::connect(&settings.addresses[index % settings.addresses.len()]);
+    // other workers wait on the barriers, so errors are returned after them
     barrier.wait();
-    for command in commands {
-        connection.write_all(&command)?;
-        connection.read(&mut buffer)?;
-        if buffer[0] == '-' as u8 {
-            error_counter += 1;
+    let connection = connection.and_then(|mut c| {
+        if !settings.warmup.is_zero() {
+            run_until(&mut c, &batches, Instant::now() + settings.warmup, &mut BTreeMap::new())?;
+        }
+        Ok(c)
+    });
+    warmup_barrier.wait();
+    let mut connection = connection?;
+    let mut results = BTreeMap::new();
+    if settings.duration.is_zero() {
+        for batch in &batches {
+            run_batch(&mut connection, batch, &mut results)?;
         }
+    } else {
+        run_until(&mut connection, &batches, Instant::now() + settings.duration, &mut results)?;
+    }
+    Ok(results)
+}
+
+// batches are sent repeatedly until the end time
+fn run_until(connection: &mut Connection, batches: &[(Vec<u8>, Vec<&'static str>)], end: Instant,
+             results: &mut BTreeMap<&'static str, CommandResults>) -> Result<(), Error> {
+    loop {
+        for batch in batches {
+            if Instant::now() >= end {
+                return Ok(());
+            }
+            run_batch(connection, batch, results)?;
+        }
+    }
+}
+
+fn run_batch(connection: &mut Connection, (batch, names): &(Vec<u8>, Vec<&'static str>),
+             results: &mut BTreeMap<&'static str, CommandResults>) -> Result<(), Error> {
+    let request_start = Instant::now();
+    connection.send(batch)?;
+    let mut errors = Vec::with_capacity(names.len());
+    for _name in names {
+        errors.push(matches!(connection.read_reply()?, RespError(_)));
+    }
+    let elapsed = request_start.elapsed();
+    for (name, error) in names.iter().zip(errors) {
+        let r = results.entry(name).or_default();
+        r.requests += 1;
+        r.errors += error as usize;
+        r.latency.add(elapsed);
     }
-    error_count.fetch_add(error_counter, Ordering::Relaxed);
     Ok(())
 }
+
+fn build_value(settings: &BenchmarkSettings, key: &[u8], rng: &mut impl Rng) -> Vec<u8> {
+    if settings.value_size == 0 {
+        return key.to_vec();
+    }
+    match settings.payload {
+        Payload::Random => {
+            let mut value = vec![0; settings.value_size];
+            rng.fill(value.as_mut_slice());
+            value
+        }
+        Payload::Compressible => key.iter().copied().cycle().take(settings.value_size).collect()
+    }
+}
+
+// n is in 0..sum of weights
+fn select_command(types: &[(BenchmarkCommand, usize)], mut n: usize) -> &BenchmarkCommand {
+    for (command, weight) in types {
+        if n < *weight {
+            return command;
+        }
+        n -= weight;
+    }
+    &types[types.len() - 1].0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+    use crate::benchmark::{build_value, compare_results, format_results, parse_command_mix, select_command, BenchmarkSettings,
+                           CommandResults, Payload, ResultFormat};
+    use crate::benchmark::BenchmarkCommand::{Get, HSet, Ping, Set, SetPX, ZAdd};
+
+    #[test]
+    fn test_command_mix() {
+        let types = parse_command_mix("get:70,set:25,setpx:5").unwrap();
+        assert_eq!(types, vec![(Get, 70), (Set, 25), (SetPX, 5)]);
+        assert_eq!(select_command(&types, 0), &Get);
+        assert_eq!(select_command(&types, 69), &Get);
+        assert_eq!(select_command(&types, 70), &Set);
+        assert_eq!(select_command(&types, 99), &SetPX);
+        assert_eq!(parse_command_mix("get,set,get,ping").unwrap(), vec![(Get, 1), (Set, 1), (Get, 1), (Ping, 1)]);
+        assert!(parse_command_mix("get:x").is_none());
+        assert_eq!(parse_command_mix("hset:5,zadd").unwrap(), vec![(HSet, 5), (ZAdd, 1)]);
+        assert!(parse_command_mix("hdel:5").is_none());
+        assert!(parse_command_mix("get:0").is_none());
+    }
+
+    #[test]
+    fn test_build_value() {
+        let mut settings = BenchmarkSettings {
+            addresses: vec!["127.0.0.1:6379".to_string()],
+            keys: 10,
+            requests: 10,
+            threads: 1,
+            expiration: 100,
+            types: vec![(Set, 1)],
+            pipeline: 1,
+            value_size: 0,
+            fields: 1,
+            payload: Payload::Compressible,
+            output: None,
+            save_baseline: None,
+            compare_baseline: None,
+            regression_threshold: 10.0,
+            warmup: Duration::ZERO,
+            duration: Duration::ZERO,
+        };
+        let mut rng = rand::thread_rng();
+        assert_eq!(build_value(&settings, b"12", &mut rng), b"12");
+        settings.value_size = 5;
+        assert_eq!(build_value(&settings, b"12", &mut rng), b"12121");
+        settings.payload = Payload::Random;
+        assert_eq!(build_value(&settings, b"12", &mut rng).len(), 5);
+        assert_eq!(Payload::parse("random"), Some(Payload::Random));
+        assert!(Payload::parse("zeros").is_none());
+    }
+
+    #[test]
+    fn test_format_results() {
+        let mut results = BTreeMap::new();
+        let mut get = CommandResults { requests: 2, errors: 1, ..Default::default() };
+        get.latency.add(Duration::from_micros(1500));
+        get.latency.add(Duration::from_micros(500));
+        results.insert("get", get);
+        let mut total = CommandResults::default();
+        results.values().for_each(|r| total.merge(r));
+        assert_eq!(format_results(&mut total, &mut results, 1000, ResultFormat::Csv),
+                   "command,requests,errors,requests_per_second,min_ms,p50_ms,p95_ms,p99_ms,p99_9_ms,max_ms\n\
+                    \"all\",2,1,2,0.500,0.500,1.500,1.500,1.500,1.500\n\
+                    \"get\",2,1,2,0.500,0.500,1.500,1.500,1.500,1.500\n");
+        assert!(format_results(&mut total, &mut results, 1000, ResultFormat::Json)
+            .starts_with("{\"elapsed_ms\":1000,\"results\":[{\"command\":\"all\",\"requests\":2,\"errors\":1,"));
+    }
+
+    #[test]
+    fn test_compare_results() {
+        let baseline = "command,requests,errors,requests_per_second,min_ms,p50_ms,p95_ms,p99_ms,p99_9_ms,max_ms\n\
+                        \"all\",100,0,1000,0.1,0.2,0.3,0.400,0.5,0.6\n\
+                        \"get\",100,0,1000,0.1,0.2,0.3,0.400,0.5,0.6\n";
+        let same = compare_results(baseline, baseline, 10.0).unwrap();
+        assert!(!same.1);
+        assert!(same.0.contains("get: requests per second 1000 -> 1000 (+0.00%)"));
+        let slower = baseline.replace(",1000,", ",850,");
+        assert!(compare_results(baseline, &slower, 10.0).unwrap().1);
+        assert!(!compare_results(baseline, &slower, 20.0).unwrap().1);
+        let higher_p99 = baseline.replace(",0.400,", ",0.500,");
+        let (report, regression) = compare_results(baseline, &higher_p99, 10.0).unwrap();
+        assert!(regression && report.contains("(+25.00%) REGRESSION"));
+        assert!(compare_results("command\n", baseline, 10.0).is_err());
+    }
+}
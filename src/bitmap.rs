@@ -0,0 +1,101 @@
+// string values as bit arrays, bit 0 is the most significant bit of the first byte
+
+#[derive(Clone, Copy)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+pub fn get_bit(bytes: &[u8], offset: usize) -> bool {
+    bytes.get(offset / 8).map(|b| b & (0x80 >> (offset % 8)) != 0).unwrap_or(false)
+}
+
+// the value is padded with zero bytes up to offset, returns the previous bit
+pub fn set_bit(bytes: &mut Vec<u8>, offset: usize, bit: bool) -> bool {
+    let idx = offset / 8;
+    if bytes.len() <= idx {
+        bytes.resize(idx + 1, 0);
+    }
+    let mask = 0x80 >> (offset % 8);
+    let old = bytes[idx] & mask != 0;
+    if bit {
+        bytes[idx] |= mask;
+    } else {
+        bytes[idx] &= !mask;
+    }
+    old
+}
+
+// BITCOUNT, inclusive range of bytes or of bits (bit_unit = true), negative indexes are counted from the end
+pub fn bit_count(bytes: &[u8], range: Option<(isize, isize)>, bit_unit: bool) -> usize {
+    let (start, end) = match range {
+        Some(range) => range,
+        None => return bytes.iter().map(|b| b.count_ones() as usize).sum()
+    };
+    let len = (if bit_unit { bytes.len() * 8 } else { bytes.len() }) as isize;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let end = if end < 0 { len + end } else { end.min(len - 1) };
+    if start > end {
+        return 0;
+    }
+    let (start, end) = (start as usize, end as usize);
+    if bit_unit {
+        (start..=end).filter(|i| get_bit(bytes, *i)).count()
+    } else {
+        bytes[start..=end].iter().map(|b| b.count_ones() as usize).sum()
+    }
+}
+
+// BITOP, applies the operation to the result of the previous steps, the shorter value is padded with zero bytes
+pub fn apply(operation: BitOperation, result: &mut Vec<u8>, value: &[u8]) {
+    if result.len() < value.len() {
+        result.resize(value.len(), 0);
+    }
+    match operation {
+        BitOperation::And => result.iter_mut().enumerate().for_each(|(i, b)| *b &= value.get(i).copied().unwrap_or(0)),
+        BitOperation::Or => result.iter_mut().zip(value).for_each(|(b, v)| *b |= v),
+        BitOperation::Xor => result.iter_mut().zip(value).for_each(|(b, v)| *b ^= v),
+        BitOperation::Not => result.iter_mut().for_each(|b| *b = !*b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitmap::{apply, bit_count, get_bit, set_bit, BitOperation};
+
+    #[test]
+    fn test_bitmap() {
+        let mut bytes = Vec::new();
+        assert!(!set_bit(&mut bytes, 7, true));
+        assert_eq!(bytes, vec![1]);
+        assert!(!set_bit(&mut bytes, 8, true));
+        assert!(set_bit(&mut bytes, 8, true));
+        assert_eq!(bytes, vec![1, 0x80]);
+        assert!(get_bit(&bytes, 7));
+        assert!(!get_bit(&bytes, 6));
+        assert!(!get_bit(&bytes, 100));
+        assert!(set_bit(&mut bytes, 7, false));
+        assert_eq!(bytes, vec![0, 0x80]);
+
+        let bytes = b"foobar";
+        assert_eq!(bit_count(bytes, None, false), 26);
+        assert_eq!(bit_count(bytes, Some((0, 0)), false), 4);
+        assert_eq!(bit_count(bytes, Some((1, 1)), false), 6);
+        assert_eq!(bit_count(bytes, Some((-2, -1)), false), 7);
+        assert_eq!(bit_count(bytes, Some((5, 30)), true), 17);
+        assert_eq!(bit_count(bytes, Some((3, 1)), false), 0);
+        assert_eq!(bit_count(b"", Some((0, -1)), false), 0);
+
+        let mut result = b"abc".to_vec();
+        apply(BitOperation::And, &mut result, b"a");
+        assert_eq!(result, vec![b'a', 0, 0]);
+        apply(BitOperation::Or, &mut result, b"\x00\x01\x02\x03");
+        assert_eq!(result, vec![b'a', 1, 2, 3]);
+        apply(BitOperation::Xor, &mut result, b"a");
+        assert_eq!(result, vec![0, 1, 2, 3]);
+        apply(BitOperation::Not, &mut result, b"");
+        assert_eq!(result, vec![0xff, 0xfe, 0xfd, 0xfc]);
+    }
+}
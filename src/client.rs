@@ -0,0 +1,897 @@
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use crate::resp_codec::{Decoder, Encoder, RespToken};
+use crate::resp_codec::RespToken::{RespArray, RespBinaryString, RespError, RespInteger, RespNullArray, RespNullString, RespString};
+
+// delay before the second reconnect attempt, doubled for every next attempt
+const RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const MAX_REDIRECTS: usize = 5;
+
+// unix socket addresses have unix: prefix
+const UNIX_SOCKET_PREFIX: &str = "unix:";
+
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn try_clone(&self) -> Result<Stream, Error> {
+        match self {
+            Stream::Tcp(s) => s.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.try_clone().map(Stream::Unix)
+        }
+    }
+
+    fn shutdown(&self, how: Shutdown) -> Result<(), Error> {
+        match self {
+            Stream::Tcp(s) => s.shutdown(how),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.shutdown(how)
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        match self {
+            Stream::Tcp(s) => s.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.set_read_timeout(timeout)
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.read(buf)
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.flush()
+        }
+    }
+}
+
+// connection to the server, replies are decoded into RespToken values
+pub struct Connection {
+    // host:port or unix:path
+    address: String,
+    stream: Stream,
+    decoder: Decoder,
+    encoder: Encoder,
+    // user and password, AUTH is sent again after reconnect
+    credentials: Option<(Option<Vec<u8>>, Vec<u8>)>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    // number of reconnect attempts when the connection is broken
+    retries: usize,
+    follow_redirects: bool,
+}
+
+impl Connection {
+    pub fn connect(address: &str) -> Result<Connection, Error> {
+        Connection::connect_timeout(address, None)
+    }
+
+    // timeout is used for reconnects too, None - system default
+    pub fn connect_timeout(address: &str, timeout: Option<Duration>) -> Result<Connection, Error> {
+        let stream = open_stream(address, timeout)?;
+        Ok(Connection { address: address.to_string(), stream, decoder: Decoder::new(), encoder: Encoder::new(),
+                        credentials: None, connect_timeout: timeout, read_timeout: None, retries: 1,
+                        follow_redirects: true })
+    }
+
+    // when a reply is not received in time, the connection is closed and reopened on the next command
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.read_timeout = timeout;
+        self.stream.set_read_timeout(timeout)
+    }
+
+    pub fn set_retries(&mut self, retries: usize) {
+        self.retries = retries;
+    }
+
+    // -MOVED and -ASK replies to command() are followed by default
+    pub fn set_follow_redirects(&mut self, follow_redirects: bool) {
+        self.follow_redirects = follow_redirects;
+    }
+
+    // sends AUTH [user] password, credentials are kept for reconnects
+    pub fn auth(&mut self, user: Option<&[u8]>, password: &[u8]) -> Result<(), Error> {
+        self.credentials = Some((user.map(|u| u.to_vec()), password.to_vec()));
+        self.send_auth()
+    }
+
+    fn send_auth(&mut self) -> Result<(), Error> {
+        if let Some((user, password)) = self.credentials.clone() {
+            let data = match user {
+                Some(u) => self.encoder.encode_command(&[b"auth", &u, &password]).take(),
+                None => self.encoder.encode_command(&[b"auth", &password]).take()
+            };
+            return check_ok(self.send_and_read(&data, 1)?.remove(0), b"OK");
+        }
+        Ok(())
+    }
+
+    // sends encoded commands without waiting for replies
+    pub fn send(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.stream.write_all(data)
+    }
+
+    pub fn read_reply(&mut self) -> Result<RespToken, Error> {
+        let mut buffer = [0; 10000];
+        loop {
+            match self.decoder.next_frame() {
+                Ok(Some(token)) => return Ok(token),
+                Ok(None) => {}
+                Err(e) => {
+                    self.decoder.clear();
+                    return Err(Error::new(ErrorKind::InvalidData, e));
+                }
+            }
+            let amt = self.stream.read(&mut buffer)?;
+            if amt == 0 {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed by server"));
+            }
+            self.decoder.feed(&buffer[..amt]);
+        }
+    }
+
+    // when the connection is broken, reconnects and sends the command again,
+    // -MOVED and -ASK redirects are followed when enabled
+    pub fn command(&mut self, args: &[&[u8]]) -> Result<RespToken, Error> {
+        let data = self.encoder.encode_command(args).take();
+        let mut reply = self.send_with_reconnect(&data, 1)?.remove(0);
+        let mut redirects = 0;
+        while self.follow_redirects && redirects < MAX_REDIRECTS {
+            reply = match parse_redirect(&reply) {
+                Some((true, address)) => {
+                    self.address = address;
+                    self.reconnect()?;
+                    self.send_with_reconnect(&data, 1)?.remove(0)
+                }
+                // ASK redirects only this command
+                Some((false, address)) => {
+                    let mut connection = self.redirected(&address)?;
+                    connection.pipeline(&[vec![b"asking"], args.to_vec()])?.remove(1)
+                }
+                None => break
+            };
+            redirects += 1;
+        }
+        Ok(reply)
+    }
+
+    // sends all commands at once and reads their replies
+    pub fn pipeline(&mut self, commands: &[Vec<&[u8]>]) -> Result<Vec<RespToken>, Error> {
+        commands.iter().for_each(|c| { self.encoder.encode_command(c); });
+        let data = self.encoder.take();
+        self.send_with_reconnect(&data, commands.len())
+    }
+
+    pub fn reconnect(&mut self) -> Result<(), Error> {
+        let _ = self.stream.shutdown(Shutdown::Both);
+        self.stream = open_stream(&self.address, self.connect_timeout)?;
+        self.stream.set_read_timeout(self.read_timeout)?;
+        self.decoder.clear();
+        self.send_auth()
+    }
+
+    // connection to another server with the same settings
+    fn redirected(&self, address: &str) -> Result<Connection, Error> {
+        let mut connection = Connection::connect_timeout(address, self.connect_timeout)?;
+        connection.set_read_timeout(self.read_timeout)?;
+        connection.retries = self.retries;
+        connection.follow_redirects = false;
+        connection.credentials = self.credentials.clone();
+        connection.send_auth()?;
+        Ok(connection)
+    }
+
+    fn send_with_reconnect(&mut self, data: &[u8], replies: usize) -> Result<Vec<RespToken>, Error> {
+        let mut result = self.send_and_read(data, replies);
+        let mut attempt = 0;
+        while attempt < self.retries && matches!(&result, Err(e) if is_connection_error(e)) {
+            if attempt > 0 {
+                thread::sleep(MAX_RECONNECT_DELAY.min(RECONNECT_DELAY * 2u32.pow(attempt as u32 - 1)));
+            }
+            attempt += 1;
+            result = match self.reconnect() {
+                Ok(()) => self.send_and_read(data, replies),
+                // the server can be unavailable for a while, next attempt is made after a delay
+                Err(e) => Err(Error::new(ErrorKind::NotConnected, e))
+            };
+        }
+        result
+    }
+
+    fn send_and_read(&mut self, data: &[u8], replies: usize) -> Result<Vec<RespToken>, Error> {
+        self.send(data)?;
+        let result = (0..replies).map(|_i| self.read_reply()).collect();
+        if let Err(e) = &result {
+            // a late reply would be taken as the reply to the next command
+            if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+                let _ = self.stream.shutdown(Shutdown::Both);
+                self.decoder.clear();
+            }
+        }
+        result
+    }
+
+    // sends SUBSCRIBE or PSUBSCRIBE and passes all received messages to handler
+    // until it returns false or the connection is closed
+    pub fn subscribe(&mut self, args: &[&[u8]], handler: &mut dyn FnMut(RespToken) -> bool) -> Result<(), Error> {
+        let data = self.encoder.encode_command(args).take();
+        self.send(&data)?;
+        loop {
+            match self.read_reply() {
+                Ok(message) => if !handler(message) {
+                    return Ok(());
+                },
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    // iterates all keys matching pattern with SCAN, a key can be passed to handler more than once
+    pub fn scan(&mut self, pattern: Option<&[u8]>, handler: &mut dyn FnMut(Vec<u8>)) -> Result<(), Error> {
+        let mut cursor = b"0".to_vec();
+        loop {
+            let mut args: Vec<&[u8]> = vec![b"scan", &cursor, b"count", b"100"];
+            if let Some(p) = pattern {
+                args.push(b"match");
+                args.push(p);
+            }
+            match self.command(&args)? {
+                RespArray(mut v) if v.len() == 2 => {
+                    if let (RespArray(keys), RespBinaryString(next)) = (v.pop().unwrap(), v.pop().unwrap()) {
+                        for key in keys {
+                            match key {
+                                RespBinaryString(k) => handler(k),
+                                reply => return Err(unexpected_reply(reply))
+                            }
+                        }
+                        if next == b"0" {
+                            return Ok(());
+                        }
+                        cursor = next;
+                    } else {
+                        return Err(Error::new(ErrorKind::InvalidData, "invalid scan reply"));
+                    }
+                }
+                reply => return Err(unexpected_reply(reply))
+            }
+        }
+    }
+
+    pub fn ping(&mut self) -> Result<(), Error> {
+        check_ok(self.command(&[b"ping"])?, b"PONG")
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match self.command(&[b"get", key])? {
+            RespBinaryString(v) => Ok(Some(v)),
+            RespNullString => Ok(None),
+            reply => Err(unexpected_reply(reply))
+        }
+    }
+
+    // expiry in milliseconds
+    pub fn set(&mut self, key: &[u8], value: &[u8], expiry: Option<u64>) -> Result<(), Error> {
+        let reply = match expiry {
+            Some(px) => self.command(&[b"set", key, value, b"px", px.to_string().as_bytes()])?,
+            None => self.command(&[b"set", key, value])?
+        };
+        check_ok(reply, b"OK")
+    }
+
+    // returns the number of removed keys
+    pub fn del(&mut self, keys: &[&[u8]]) -> Result<isize, Error> {
+        let mut args: Vec<&[u8]> = vec![b"del"];
+        args.extend_from_slice(keys);
+        match self.command(&args)? {
+            RespInteger(n) => Ok(n),
+            reply => Err(unexpected_reply(reply))
+        }
+    }
+}
+
+pub struct PipeSummary {
+    pub commands: usize,
+    pub replies: usize,
+    pub errors: usize,
+}
+
+// sends all commands from input without waiting for replies, replies are counted by a separate thread
+// input can contain RESP encoded commands and inline commands separated by new lines
+pub fn pipe(address: &str, input: &mut dyn Read) -> Result<PipeSummary, Error> {
+    let connection = Connection::connect(address)?;
+    let mut stream = connection.stream.try_clone()?;
+    let reader = thread::spawn(move || count_replies(connection));
+    let mut decoder = Decoder::new();
+    let mut encoder = Encoder::new();
+    let mut buffer = [0; 65536];
+    let mut commands = 0;
+    loop {
+        let amt = input.read(&mut buffer)?;
+        if amt == 0 {
+            break;
+        }
+        decoder.feed(&buffer[..amt]);
+        while let Some(token) = decoder.next_frame().map_err(|e| Error::new(ErrorKind::InvalidData, e))? {
+            match token {
+                RespString(line) => {
+                    let args: Vec<&[u8]> = line.split(|c| c.is_ascii_whitespace()).filter(|a| !a.is_empty()).collect();
+                    if args.is_empty() {
+                        continue;
+                    }
+                    encoder.encode_command(&args);
+                }
+                token => { encoder.encode(&token); }
+            }
+            commands += 1;
+        }
+        stream.write_all(&encoder.take())?;
+    }
+    if decoder.pending() != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "incomplete command at the end of input"));
+    }
+    // the server closes the connection after all commands are processed
+    stream.shutdown(Shutdown::Write)?;
+    let (replies, errors) = reader.join().unwrap_or_else(|_e| Err(Error::other("reader thread panicked")))?;
+    Ok(PipeSummary { commands, replies, errors })
+}
+
+fn count_replies(mut connection: Connection) -> Result<(usize, usize), Error> {
+    let mut replies = 0;
+    let mut errors = 0;
+    loop {
+        match connection.read_reply() {
+            Ok(reply) => {
+                replies += 1;
+                if let RespError(e) = reply {
+                    if errors == 0 {
+                        println!("First error: {}", String::from_utf8_lossy(&e));
+                    }
+                    errors += 1;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok((replies, errors)),
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+// splits a command line into arguments, arguments with spaces can be enclosed in double quotes
+// (with \\, \", \n, \r, \t escapes) or single quotes
+pub fn split_command_line(line: &str) -> Result<Vec<Vec<u8>>, &'static str> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut arg = String::new();
+        match chars.peek() {
+            None => return Ok(args),
+            Some('"') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => arg.push('\n'),
+                            Some('r') => arg.push('\r'),
+                            Some('t') => arg.push('\t'),
+                            Some(c) => arg.push(c),
+                            None => return Err("unbalanced quotes")
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err("unbalanced quotes")
+                    }
+                }
+            }
+            Some('\'') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err("unbalanced quotes")
+                    }
+                }
+            }
+            Some(_) => {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    arg.push(c);
+                }
+            }
+        }
+        args.push(arg.into_bytes());
+    }
+}
+
+fn is_connection_error(e: &Error) -> bool {
+    matches!(e.kind(), ErrorKind::UnexpectedEof | ErrorKind::BrokenPipe | ErrorKind::ConnectionReset |
+        ErrorKind::ConnectionAborted | ErrorKind::NotConnected)
+}
+
+fn open_stream(address: &str, timeout: Option<Duration>) -> Result<Stream, Error> {
+    if let Some(path) = address.strip_prefix(UNIX_SOCKET_PREFIX) {
+        #[cfg(unix)]
+        return UnixStream::connect(path).map(Stream::Unix);
+        #[cfg(not(unix))]
+        return Err(Error::new(ErrorKind::Unsupported, format!("unix sockets are not supported: {}", path)));
+    }
+    let stream = match timeout {
+        Some(t) => {
+            let mut last_error = Error::new(ErrorKind::InvalidInput, "address is not resolved");
+            let mut stream = None;
+            for a in address.to_socket_addrs()? {
+                match TcpStream::connect_timeout(&a, t) {
+                    Ok(s) => {
+                        stream = Some(s);
+                        break;
+                    }
+                    Err(e) => last_error = e
+                }
+            }
+            stream.ok_or(last_error)?
+        }
+        None => TcpStream::connect(address)?
+    };
+    stream.set_nodelay(true)?;
+    Ok(Stream::Tcp(stream))
+}
+
+// -MOVED slot address or -ASK slot address, returns whether the redirect is permanent and the address
+fn parse_redirect(reply: &RespToken) -> Option<(bool, String)> {
+    if let RespError(e) = reply {
+        let parts: Vec<&[u8]> = e.split(|c| *c == b' ').collect();
+        if parts.len() == 3 && (parts[0] == b"MOVED" || parts[0] == b"ASK") {
+            return Some((parts[0] == b"MOVED", String::from_utf8_lossy(parts[2]).to_string()));
+        }
+    }
+    None
+}
+
+fn check_ok(reply: RespToken, expected: &[u8]) -> Result<(), Error> {
+    match reply {
+        RespString(s) if s == expected => Ok(()),
+        reply => Err(unexpected_reply(reply))
+    }
+}
+
+// server errors are returned as Error with the error message
+fn unexpected_reply(reply: RespToken) -> Error {
+    match reply {
+        RespError(e) => Error::other(String::from_utf8_lossy(&e).to_string()),
+        reply => Error::new(ErrorKind::InvalidData, format!("unexpected reply {:?}", reply))
+    }
+}
+
+// reply in the redis-cli format: quoted bulk strings, numbered array elements with nested indentation
+pub fn format_reply(reply: &RespToken) -> String {
+    match reply {
+        RespString(s) => String::from_utf8_lossy(s).to_string(),
+        RespBinaryString(s) => quote(s),
+        RespError(e) => format!("(error) {}", String::from_utf8_lossy(e)),
+        RespInteger(n) => format!("(integer) {}", n),
+        RespNullString | RespNullArray => "(nil)".to_string(),
+        RespArray(v) => {
+            if v.is_empty() {
+                return "(empty array)".to_string();
+            }
+            let width = v.len().to_string().len();
+            let mut lines = Vec::new();
+            for (i, token) in v.iter().enumerate() {
+                let prefix = format!("{:>width$}) ", i + 1, width = width);
+                let indent = " ".repeat(prefix.len());
+                for (j, line) in format_reply(token).lines().enumerate() {
+                    lines.push(format!("{}{}", if j == 0 { &prefix } else { &indent }, line));
+                }
+            }
+            lines.join("\n")
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputFormat {
+    // redis-cli format, see format_reply
+    Standard,
+    // strings as is, array elements on separate lines, nil as an empty line
+    Raw,
+    // array elements are separated by commas, strings are quoted, nil is NULL
+    Csv,
+    Json,
+}
+
+pub fn format_reply_as(reply: &RespToken, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Standard => format_reply(reply),
+        OutputFormat::Raw => format_raw(reply),
+        OutputFormat::Csv => format_csv(reply),
+        OutputFormat::Json => format_json(reply)
+    }
+}
+
+fn format_raw(reply: &RespToken) -> String {
+    match reply {
+        RespString(s) | RespBinaryString(s) => String::from_utf8_lossy(s).to_string(),
+        RespError(e) => String::from_utf8_lossy(e).to_string(),
+        RespInteger(n) => n.to_string(),
+        RespNullString | RespNullArray => String::new(),
+        RespArray(v) => v.iter().map(format_raw).collect::<Vec<_>>().join("\n")
+    }
+}
+
+fn format_csv(reply: &RespToken) -> String {
+    match reply {
+        RespString(s) | RespBinaryString(s) => quote(s),
+        RespError(e) => format!("ERROR,{}", quote(e)),
+        RespInteger(n) => n.to_string(),
+        RespNullString | RespNullArray => "NULL".to_string(),
+        RespArray(v) => v.iter().map(format_csv).collect::<Vec<_>>().join(",")
+    }
+}
+
+fn format_json(reply: &RespToken) -> String {
+    match reply {
+        RespString(s) | RespBinaryString(s) => json_string(s),
+        RespError(e) => format!("{{\"error\":{}}}", json_string(e)),
+        RespInteger(n) => n.to_string(),
+        RespNullString | RespNullArray => "null".to_string(),
+        RespArray(v) => format!("[{}]", v.iter().map(format_json).collect::<Vec<_>>().join(","))
+    }
+}
+
+// invalid UTF-8 sequences are replaced
+fn json_string(s: &[u8]) -> String {
+    let mut result = String::from("\"");
+    for c in String::from_utf8_lossy(s).chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn quote(s: &[u8]) -> String {
+    let mut result = String::from("\"");
+    for c in s {
+        match c {
+            b'"' => result.push_str("\\\""),
+            b'\\' => result.push_str("\\\\"),
+            b'\n' => result.push_str("\\n"),
+            b'\r' => result.push_str("\\r"),
+            b'\t' => result.push_str("\\t"),
+            0x20..=0x7e => result.push(*c as char),
+            _ => result.push_str(&format!("\\x{:02x}", c))
+        }
+    }
+    result.push('"');
+    result
+}
+
+// pool of connections to one server, a connection is returned to the pool when PooledConnection is dropped
+pub struct Client {
+    address: String,
+    connections: Mutex<Vec<Connection>>,
+    // maximum number of idle connections kept in the pool
+    max_idle: usize,
+}
+
+pub struct PooledConnection<'a> {
+    client: &'a Client,
+    connection: Option<Connection>,
+}
+
+impl Client {
+    pub fn new(host: &str, port: u16, max_idle: usize) -> Client {
+        Client { address: format!("{}:{}", host, port), connections: Mutex::new(Vec::new()), max_idle }
+    }
+
+    pub fn connection(&self) -> Result<PooledConnection<'_>, Error> {
+        let pooled = self.connections.lock().unwrap().pop();
+        let connection = match pooled {
+            Some(c) => c,
+            None => Connection::connect(&self.address)?
+        };
+        Ok(PooledConnection { client: self, connection: Some(connection) })
+    }
+
+    pub fn command(&self, args: &[&[u8]]) -> Result<RespToken, Error> {
+        self.connection()?.command(args)
+    }
+
+    pub fn pipeline(&self, commands: &[Vec<&[u8]>]) -> Result<Vec<RespToken>, Error> {
+        self.connection()?.pipeline(commands)
+    }
+
+    pub fn idle_connections(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.connection.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(c) = self.connection.take() {
+            let mut connections = self.client.connections.lock().unwrap();
+            // a connection with unread replies can not be reused
+            if connections.len() < self.client.max_idle && c.decoder.pending() == 0 {
+                connections.push(c);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+    use crate::client::{format_reply, format_reply_as, pipe, split_command_line, Client, Connection, OutputFormat};
+    use crate::resp_codec::RespToken::{RespArray, RespBinaryString, RespError, RespInteger, RespNullString, RespString};
+    use crate::server_builder::CacheServerBuilder;
+
+    #[test]
+    fn test_client() {
+        let server = CacheServerBuilder::new()
+            .port(0)
+            .max_memory(1024 * 1024)
+            .key_maps(4)
+            .defrag_interval(0)
+            .start()
+            .unwrap();
+        let client = Client::new("127.0.0.1", server.port(), 2);
+        {
+            let mut c = client.connection().unwrap();
+            c.ping().unwrap();
+            c.set(b"a", b"1", None).unwrap();
+            c.set(b"b", b"2", Some(100000)).unwrap();
+            assert_eq!(c.get(b"a").unwrap(), Some(b"1".to_vec()));
+            assert_eq!(c.get(b"c").unwrap(), None);
+            assert_eq!(c.del(&[b"c"]).unwrap(), 0);
+        }
+        assert_eq!(client.idle_connections(), 1);
+
+        let replies = client.pipeline(&[vec![b"get", b"b"], vec![b"del", b"a", b"b"], vec![b"set", b"c", b"3"]]).unwrap();
+        assert_eq!(replies, vec![RespBinaryString(b"2".to_vec()), RespInteger(2), RespString(b"OK".to_vec())]);
+        assert_eq!(client.idle_connections(), 1);
+        assert_eq!(server.common_data().size(), 1);
+
+        let mut input: Vec<u8> = Vec::new();
+        input.extend_from_slice(b"set a 1\n\nget a\r\nfoo\n*3\r\n$3\r\nset\r\n$1\r\nb\r\n$2\r\n22\r\n");
+        (0..1000).for_each(|i| input.extend_from_slice(format!("set key{} {}\n", i, i).as_bytes()));
+        let summary = pipe(&format!("127.0.0.1:{}", server.port()), &mut input.as_slice()).unwrap();
+        assert_eq!((summary.commands, summary.replies, summary.errors), (1004, 1004, 1));
+        assert_eq!(server.common_data().size(), 1003);
+
+        let mut keys = Vec::new();
+        client.connection().unwrap().scan(Some(b"key99*"), &mut |k| keys.push(k)).unwrap();
+        keys.sort();
+        assert_eq!(keys, vec![b"key99".to_vec(), b"key990".to_vec(), b"key991".to_vec(), b"key992".to_vec(),
+                              b"key993".to_vec(), b"key994".to_vec(), b"key995".to_vec(), b"key996".to_vec(),
+                              b"key997".to_vec(), b"key998".to_vec(), b"key999".to_vec()]);
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_format_reply() {
+        assert_eq!(format_reply(&RespString(b"OK".to_vec())), "OK");
+        assert_eq!(format_reply(&RespError(b"invalid command".to_vec())), "(error) invalid command");
+        assert_eq!(format_reply(&RespInteger(5)), "(integer) 5");
+        assert_eq!(format_reply(&RespNullString), "(nil)");
+        assert_eq!(format_reply(&RespBinaryString(b"a \"b\"\r\n\x01".to_vec())), "\"a \\\"b\\\"\\r\\n\\x01\"");
+        assert_eq!(format_reply(&RespArray(Vec::new())), "(empty array)");
+        let mut v: Vec<_> = (0..9).map(RespInteger).collect();
+        v.push(RespArray(vec![RespBinaryString(b"a".to_vec()), RespNullString]));
+        assert_eq!(format_reply(&RespArray(v)).lines().skip(8).collect::<Vec<_>>(),
+                   vec![" 9) (integer) 8", "10) 1) \"a\"", "    2) (nil)"]);
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let reply = RespArray(vec![RespBinaryString(b"a\"b".to_vec()), RespInteger(1), RespNullString,
+                                   RespArray(vec![RespString(b"OK".to_vec())])]);
+        assert_eq!(format_reply_as(&reply, OutputFormat::Raw), "a\"b\n1\n\nOK");
+        assert_eq!(format_reply_as(&reply, OutputFormat::Csv), "\"a\\\"b\",1,NULL,\"OK\"");
+        assert_eq!(format_reply_as(&reply, OutputFormat::Json), "[\"a\\\"b\",1,null,[\"OK\"]]");
+        let error = RespError(b"ERR x".to_vec());
+        assert_eq!(format_reply_as(&error, OutputFormat::Raw), "ERR x");
+        assert_eq!(format_reply_as(&error, OutputFormat::Csv), "ERROR,\"ERR x\"");
+        assert_eq!(format_reply_as(&error, OutputFormat::Json), "{\"error\":\"ERR x\"}");
+        assert_eq!(format_reply_as(&RespBinaryString(b"\x01\n".to_vec()), OutputFormat::Json), "\"\\u0001\\n\"");
+    }
+
+    #[test]
+    fn test_split_command_line() {
+        assert_eq!(split_command_line("  set  key value ").unwrap(), vec![b"set".to_vec(), b"key".to_vec(), b"value".to_vec()]);
+        assert_eq!(split_command_line("set \"a b\" 'c \"d\"' \"e\\n\\\"\"").unwrap(),
+                   vec![b"set".to_vec(), b"a b".to_vec(), b"c \"d\"".to_vec(), b"e\n\"".to_vec()]);
+        assert!(split_command_line("").unwrap().is_empty());
+        assert!(split_command_line("set \"a").is_err());
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _address) = listener.accept().unwrap();
+            let mut buffer = [0; 100];
+            let amt = stream.read(&mut buffer).unwrap();
+            assert_eq!(&buffer[..amt], b"*2\r\n$9\r\nsubscribe\r\n$2\r\nch\r\n");
+            stream.write_all(b"*3\r\n$9\r\nsubscribe\r\n$2\r\nch\r\n:1\r\n*3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$5\r\nhello\r\n").unwrap();
+        });
+        let mut connection = Connection::connect(&address).unwrap();
+        let mut messages = Vec::new();
+        connection.subscribe(&[b"subscribe", b"ch"], &mut |m| {
+            messages.push(format_reply(&m));
+            true
+        }).unwrap();
+        server.join().unwrap();
+        assert_eq!(messages, vec!["1) \"subscribe\"\n2) \"ch\"\n3) (integer) 1", "1) \"message\"\n2) \"ch\"\n3) \"hello\""]);
+    }
+
+    #[test]
+    fn test_auth() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut buffer = [0; 100];
+            // the first connection is closed after AUTH, the client has to authenticate again
+            for close in [true, false] {
+                let (mut stream, _address) = listener.accept().unwrap();
+                let amt = stream.read(&mut buffer).unwrap();
+                assert_eq!(&buffer[..amt], b"*3\r\n$4\r\nauth\r\n$4\r\nuser\r\n$4\r\npass\r\n");
+                stream.write_all(b"+OK\r\n").unwrap();
+                if !close {
+                    let amt = stream.read(&mut buffer).unwrap();
+                    assert_eq!(&buffer[..amt], b"*1\r\n$4\r\nping\r\n");
+                    stream.write_all(b"+PONG\r\n").unwrap();
+                }
+            }
+        });
+        let mut connection = Connection::connect(&address).unwrap();
+        connection.auth(Some(b"user"), b"pass").unwrap();
+        connection.ping().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_redirects() {
+        let target = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target_address = target.local_addr().unwrap().to_string();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let moved = format!("-MOVED 3999 {}\r\n", target_address);
+        let ask = format!("-ASK 3999 {}\r\n", target_address);
+        let server = thread::spawn(move || {
+            let mut buffer = [0; 100];
+            let (mut stream, _address) = listener.accept().unwrap();
+            stream.read(&mut buffer).unwrap();
+            stream.write_all(ask.as_bytes()).unwrap();
+            stream.read(&mut buffer).unwrap();
+            stream.write_all(moved.as_bytes()).unwrap();
+        });
+        let target_server = thread::spawn(move || {
+            let mut buffer = [0; 100];
+            // ASK - a separate connection, ASKING is sent before the command
+            let (mut stream, _address) = target.accept().unwrap();
+            let mut received = Vec::new();
+            while received.len() < 34 {
+                let amt = stream.read(&mut buffer).unwrap();
+                received.extend_from_slice(&buffer[..amt]);
+            }
+            assert_eq!(received, b"*1\r\n$6\r\nasking\r\n*2\r\n$3\r\nget\r\n$1\r\na\r\n");
+            stream.write_all(b"+OK\r\n$1\r\n1\r\n").unwrap();
+            // MOVED - the connection is switched to the new server
+            let (mut stream, _address) = target.accept().unwrap();
+            for reply in [b"$1\r\n2\r\n", b"$1\r\n3\r\n"] {
+                stream.read(&mut buffer).unwrap();
+                stream.write_all(reply).unwrap();
+            }
+        });
+        let mut connection = Connection::connect(&address).unwrap();
+        assert_eq!(connection.get(b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(connection.get(b"a").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(connection.get(b"a").unwrap(), Some(b"3".to_vec()));
+        server.join().unwrap();
+        target_server.join().unwrap();
+    }
+
+    #[test]
+    fn test_timeout_and_retries() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut buffer = [0; 100];
+            // no reply to the first command
+            let (mut stream, _address) = listener.accept().unwrap();
+            stream.read(&mut buffer).unwrap();
+            // connections are closed without replies, the client has to reconnect twice
+            for _i in 0..2 {
+                let (mut stream, _address) = listener.accept().unwrap();
+                stream.read(&mut buffer).unwrap();
+            }
+            let (mut stream, _address) = listener.accept().unwrap();
+            stream.read(&mut buffer).unwrap();
+            stream.write_all(b"+PONG\r\n").unwrap();
+            drop(stream);
+        });
+        let mut connection = Connection::connect_timeout(&address, Some(Duration::from_secs(1))).unwrap();
+        connection.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        connection.set_retries(3);
+        let e = connection.ping().unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut));
+        connection.ping().unwrap();
+        server.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        let path = std::env::temp_dir().join(format!("cache_test_{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _address) = listener.accept().unwrap();
+            let mut buffer = [0; 100];
+            let amt = stream.read(&mut buffer).unwrap();
+            assert_eq!(&buffer[..amt], b"*1\r\n$4\r\nping\r\n");
+            stream.write_all(b"+PONG\r\n").unwrap();
+        });
+        let mut connection = Connection::connect(&format!("unix:{}", path.display())).unwrap();
+        connection.ping().unwrap();
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
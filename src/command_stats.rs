@@ -0,0 +1,152 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// commands that have statistics, unknown commands are not counted
+pub const COMMANDS: [&str; 75] = ["bitcount", "bitop", "client", "command", "config", "dbsize", "debug", "del",
+    "exists", "expire", "expireat", "flushall", "flushdb", "geoadd", "geodist", "geosearch", "get", "getbit", "getex",
+    "getrange", "incrbyfloat", "info", "latency", "memory", "mget", "mset", "msetnx", "persist", "pexpire", "pexpireat",
+    "ping", "psetex", "psubscribe", "pttl", "publish", "punsubscribe", "sadd", "scan", "scard", "sdiff", "sdiffstore",
+    "select", "set", "setbit", "setex", "setnx", "setrange", "shardstats", "sinter", "sinterstore", "sismember",
+    "smembers", "smismember", "spop", "srandmember", "srem", "sscan", "subscribe", "sunion", "sunionstore", "touch",
+    "ttl", "type", "unsubscribe", "zadd", "zcard", "zincrby", "zpopmax", "zpopmin", "zrange", "zrangebyscore", "zrank",
+    "zrem", "zrevrank", "zscore"];
+
+// latency histogram buckets, bucket i counts calls that took up to 2^i microseconds
+const HISTOGRAM_BUCKETS: usize = 40;
+
+struct CommandStat {
+    calls: AtomicU64,
+    usec: AtomicU64,
+    max_usec: AtomicU64,
+    failed_calls: AtomicU64,
+    histogram: Vec<AtomicU64>,
+}
+
+impl CommandStat {
+    fn new() -> CommandStat {
+        CommandStat {
+            calls: AtomicU64::new(0),
+            usec: AtomicU64::new(0),
+            max_usec: AtomicU64::new(0),
+            failed_calls: AtomicU64::new(0),
+            histogram: (0..HISTOGRAM_BUCKETS).map(|_i| AtomicU64::new(0)).collect(),
+        }
+    }
+}
+
+fn histogram_bucket(usec: u64) -> usize {
+    if usec <= 1 {
+        0
+    } else {
+        ((u64::BITS - (usec - 1).leading_zeros()) as usize).min(HISTOGRAM_BUCKETS - 1)
+    }
+}
+
+// per command calls, execution time and errors, updated by the dispatcher
+pub struct CommandStats {
+    stats: Vec<CommandStat>,
+}
+
+impl CommandStats {
+    pub fn new() -> CommandStats {
+        CommandStats { stats: COMMANDS.iter().map(|_c| CommandStat::new()).collect() }
+    }
+
+    // index of the command in COMMANDS, the name is case insensitive
+    pub fn find(name: &[u8]) -> Option<usize> {
+        COMMANDS.iter().position(|c| c.as_bytes().eq_ignore_ascii_case(name))
+    }
+
+    pub fn add(&self, idx: usize, time: Duration, failed: bool) {
+        let stat = &self.stats[idx];
+        let usec = time.as_micros() as u64;
+        stat.calls.fetch_add(1, Ordering::Relaxed);
+        stat.usec.fetch_add(usec, Ordering::Relaxed);
+        stat.max_usec.fetch_max(usec, Ordering::Relaxed);
+        if failed {
+            stat.failed_calls.fetch_add(1, Ordering::Relaxed);
+        }
+        stat.histogram[histogram_bucket(usec)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        for stat in &self.stats {
+            stat.calls.store(0, Ordering::Relaxed);
+            stat.usec.store(0, Ordering::Relaxed);
+            stat.max_usec.store(0, Ordering::Relaxed);
+            stat.failed_calls.store(0, Ordering::Relaxed);
+            stat.histogram.iter().for_each(|b| b.store(0, Ordering::Relaxed));
+        }
+    }
+
+    pub fn calls(&self, idx: usize) -> u64 {
+        self.stats[idx].calls.load(Ordering::Relaxed)
+    }
+
+    // cumulative number of calls for the buckets from the first to the last non empty one,
+    // returns (bucket upper bound in microseconds, count)
+    pub fn histogram(&self, idx: usize) -> Vec<(u64, u64)> {
+        let counts: Vec<u64> = self.stats[idx].histogram.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        let first = match counts.iter().position(|c| *c > 0) {
+            Some(first) => first,
+            None => return Vec::new()
+        };
+        let last = counts.iter().rposition(|c| *c > 0).unwrap_or(first);
+        let mut total = 0;
+        (first..=last)
+            .map(|i| {
+                total += counts[i];
+                (1 << i, total)
+            })
+            .collect()
+    }
+
+    // INFO commandstats lines for the commands that were called at least once
+    pub fn build_info(&self, info: &mut String) {
+        info.push_str("# Commandstats\r\n");
+        for (name, stat) in COMMANDS.iter().zip(&self.stats) {
+            let calls = stat.calls.load(Ordering::Relaxed);
+            if calls == 0 {
+                continue;
+            }
+            let usec = stat.usec.load(Ordering::Relaxed);
+            info.push_str(&format!("cmdstat_{}:calls={},usec={},usec_per_call={:.2},max_usec={},failed_calls={}\r\n",
+                                   name, calls, usec, usec as f64 / calls as f64,
+                                   stat.max_usec.load(Ordering::Relaxed), stat.failed_calls.load(Ordering::Relaxed)));
+        }
+    }
+}
+
+impl Default for CommandStats {
+    fn default() -> Self {
+        CommandStats::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::command_stats::{CommandStats, COMMANDS};
+
+    #[test]
+    fn test_command_stats() {
+        let stats = CommandStats::new();
+        let get = CommandStats::find(b"GeT").unwrap();
+        assert_eq!(COMMANDS[get], "get");
+        assert_eq!(CommandStats::find(b"unknown"), None);
+        stats.add(get, Duration::from_micros(10), false);
+        stats.add(get, Duration::from_micros(30), true);
+        let mut info = String::new();
+        stats.build_info(&mut info);
+        assert_eq!(info, "# Commandstats\r\ncmdstat_get:calls=2,usec=40,usec_per_call=20.00,max_usec=30,failed_calls=1\r\n");
+        stats.add(get, Duration::from_micros(32), false);
+        assert_eq!(stats.calls(get), 3);
+        assert_eq!(stats.histogram(get), vec![(16, 1), (32, 3)]);
+        assert!(stats.histogram(0).is_empty());
+        stats.reset();
+        info.clear();
+        stats.build_info(&mut info);
+        assert_eq!(info, "# Commandstats\r\n");
+        assert!(stats.histogram(get).is_empty());
+    }
+}
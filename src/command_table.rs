@@ -0,0 +1,136 @@
+// static command descriptions for COMMAND, the values follow the Redis command table, so clients that read
+// arity and key positions on connect get the same information as from Redis
+
+const READ: &[&str] = &["readonly"];
+const READ_FAST: &[&str] = &["readonly", "fast"];
+const WRITE: &[&str] = &["write"];
+const WRITE_FAST: &[&str] = &["write", "fast"];
+const WRITE_OOM: &[&str] = &["write", "denyoom"];
+const WRITE_OOM_FAST: &[&str] = &["write", "denyoom", "fast"];
+const ADMIN: &[&str] = &["admin", "noscript", "loading", "stale"];
+const PUBSUB: &[&str] = &["pubsub", "noscript", "loading", "stale"];
+const FAST: &[&str] = &["fast", "loading", "stale"];
+const STALE: &[&str] = &["loading", "stale"];
+
+// keys: no keys, one key at position 1, all arguments starting from position 1
+const NO_KEYS: (isize, isize, isize) = (0, 0, 0);
+const KEY: (isize, isize, isize) = (1, 1, 1);
+const KEYS: (isize, isize, isize) = (1, -1, 1);
+
+pub struct CommandInfo {
+    pub name: &'static str,
+    // positive - exact number of arguments including the command name, negative - minimum number
+    pub arity: isize,
+    pub flags: &'static [&'static str],
+    // positions of the first and the last key and the step between keys, a negative last key is counted from the end
+    pub first_key: isize,
+    pub last_key: isize,
+    pub step: isize,
+    pub group: &'static str,
+    pub summary: &'static str,
+}
+
+const fn command(name: &'static str, arity: isize, flags: &'static [&'static str], keys: (isize, isize, isize),
+                 group: &'static str, summary: &'static str) -> CommandInfo {
+    CommandInfo { name, arity, flags, first_key: keys.0, last_key: keys.1, step: keys.2, group, summary }
+}
+
+// the same commands and order as in COMMANDS
+pub const COMMAND_TABLE: [CommandInfo; 75] = [
+    command("bitcount", -2, READ, KEY, "bitmap", "Counts the number of set bits in a string."),
+    command("bitop", -4, WRITE_OOM, (2, -1, 1), "bitmap", "Performs bitwise operations on strings and stores the result."),
+    command("client", -2, ADMIN, NO_KEYS, "connection", "Lists, names and closes client connections."),
+    command("command", -1, STALE, NO_KEYS, "server", "Returns detailed information about commands."),
+    command("config", -2, ADMIN, NO_KEYS, "server", "Gets, sets, reloads and rewrites the configuration."),
+    command("dbsize", 1, READ_FAST, NO_KEYS, "server", "Returns the number of keys."),
+    command("debug", -2, ADMIN, NO_KEYS, "server", "A container for debugging commands."),
+    command("del", -2, WRITE, KEYS, "generic", "Deletes one or more keys."),
+    command("exists", -2, READ_FAST, KEYS, "generic", "Determines whether one or more keys exist."),
+    command("expire", 3, WRITE_FAST, KEY, "generic", "Sets the expiration time of a key in seconds."),
+    command("expireat", 3, WRITE_FAST, KEY, "generic", "Sets the expiration time of a key to a Unix timestamp."),
+    command("flushall", -1, WRITE, NO_KEYS, "server", "Removes all keys."),
+    command("flushdb", -1, WRITE, NO_KEYS, "server", "Removes all keys."),
+    command("geoadd", -5, WRITE_OOM, KEY, "geo", "Adds one or more members to a geospatial index."),
+    command("geodist", -4, READ, KEY, "geo", "Returns the distance between two members of a geospatial index."),
+    command("geosearch", -7, READ, KEY, "geo", "Queries a geospatial index for members inside an area of a box or a circle."),
+    command("get", 2, READ_FAST, KEY, "string", "Returns the string value of a key."),
+    command("getbit", 3, READ_FAST, KEY, "bitmap", "Returns a bit value by offset."),
+    command("getex", -2, WRITE_FAST, KEY, "string", "Returns the string value of a key after setting its expiration time."),
+    command("getrange", 4, READ, KEY, "string", "Returns a substring of the string stored at a key."),
+    command("incrbyfloat", 3, WRITE_OOM_FAST, KEY, "string", "Increments the floating point value of a key by a number."),
+    command("info", -1, STALE, NO_KEYS, "server", "Returns information and statistics about the server."),
+    command("latency", -2, ADMIN, NO_KEYS, "server", "Reports latency histograms of commands and latency spikes."),
+    command("memory", -2, READ, (2, 2, 1), "server", "Reports and releases memory."),
+    command("mget", -2, READ_FAST, KEYS, "string", "Atomically returns the string values of one or more keys."),
+    command("mset", -3, WRITE_OOM, (1, -1, 2), "string", "Atomically creates or modifies the string values of one or more keys."),
+    command("msetnx", -3, WRITE_OOM, (1, -1, 2), "string", "Atomically sets the string values of keys only when none of the keys exist."),
+    command("persist", 2, WRITE_FAST, KEY, "generic", "Removes the expiration time of a key."),
+    command("pexpire", 3, WRITE_FAST, KEY, "generic", "Sets the expiration time of a key in milliseconds."),
+    command("pexpireat", 3, WRITE_FAST, KEY, "generic", "Sets the expiration time of a key to a Unix milliseconds timestamp."),
+    command("ping", -1, FAST, NO_KEYS, "connection", "Returns the server's liveliness response."),
+    command("psetex", 4, WRITE_OOM, KEY, "string", "Sets the string value and the expiration time in milliseconds of a key."),
+    command("psubscribe", -2, PUBSUB, NO_KEYS, "pubsub", "Listens for messages published to channels that match patterns."),
+    command("pttl", 2, READ_FAST, KEY, "generic", "Returns the expiration time in milliseconds of a key."),
+    command("publish", 3, PUBSUB, NO_KEYS, "pubsub", "Posts a message to a channel."),
+    command("punsubscribe", -1, PUBSUB, NO_KEYS, "pubsub", "Stops listening to messages published to channels that match patterns."),
+    command("sadd", -3, WRITE_OOM_FAST, KEY, "set", "Adds one or more members to a set."),
+    command("scan", -2, READ, NO_KEYS, "generic", "Iterates over the key names."),
+    command("scard", 2, READ_FAST, KEY, "set", "Returns the number of members in a set."),
+    command("sdiff", -2, READ, KEYS, "set", "Returns the difference of multiple sets."),
+    command("sdiffstore", -3, WRITE_OOM, KEYS, "set", "Stores the difference of multiple sets in a key."),
+    command("select", 2, FAST, NO_KEYS, "connection", "Changes the selected database."),
+    command("set", -3, WRITE_OOM, KEY, "string", "Sets the string value of a key."),
+    command("setbit", 4, WRITE_OOM, KEY, "bitmap", "Sets or clears the bit at offset of the string value."),
+    command("setex", 4, WRITE_OOM, KEY, "string", "Sets the string value and the expiration time of a key."),
+    command("setnx", 3, WRITE_OOM_FAST, KEY, "string", "Sets the string value of a key only when the key doesn't exist."),
+    command("setrange", 4, WRITE_OOM, KEY, "string", "Overwrites a part of a string value with another by an offset."),
+    command("shardstats", -1, ADMIN, NO_KEYS, "server", "Returns per key map statistics."),
+    command("sinter", -2, READ, KEYS, "set", "Returns the intersect of multiple sets."),
+    command("sinterstore", -3, WRITE_OOM, KEYS, "set", "Stores the intersect of multiple sets in a key."),
+    command("sismember", 3, READ_FAST, KEY, "set", "Determines whether a member belongs to a set."),
+    command("smembers", 2, READ, KEY, "set", "Returns all members of a set."),
+    command("smismember", -3, READ_FAST, KEY, "set", "Determines whether multiple members belong to a set."),
+    command("spop", -2, WRITE_FAST, KEY, "set", "Returns one or more random members from a set after removing them."),
+    command("srandmember", -2, READ, KEY, "set", "Gets one or more random members from a set."),
+    command("srem", -3, WRITE_FAST, KEY, "set", "Removes one or more members from a set."),
+    command("sscan", -3, READ, KEY, "set", "Iterates over members of a set."),
+    command("subscribe", -2, PUBSUB, NO_KEYS, "pubsub", "Listens for messages published to channels."),
+    command("sunion", -2, READ, KEYS, "set", "Returns the union of multiple sets."),
+    command("sunionstore", -3, WRITE_OOM, KEYS, "set", "Stores the union of multiple sets in a key."),
+    command("touch", -2, READ_FAST, KEYS, "generic", "Returns the number of existing keys out of those specified."),
+    command("ttl", 2, READ_FAST, KEY, "generic", "Returns the expiration time in seconds of a key."),
+    command("type", 2, READ_FAST, KEY, "generic", "Determines the type of value stored at a key."),
+    command("unsubscribe", -1, PUBSUB, NO_KEYS, "pubsub", "Stops listening to messages posted to channels."),
+    command("zadd", -4, WRITE_OOM_FAST, KEY, "sorted-set", "Adds one or more members to a sorted set, or updates their scores."),
+    command("zcard", 2, READ_FAST, KEY, "sorted-set", "Returns the number of members in a sorted set."),
+    command("zincrby", 4, WRITE_OOM_FAST, KEY, "sorted-set", "Increments the score of a member in a sorted set."),
+    command("zpopmax", -2, WRITE_FAST, KEY, "sorted-set", "Returns the highest-scoring members from a sorted set after removing them."),
+    command("zpopmin", -2, WRITE_FAST, KEY, "sorted-set", "Returns the lowest-scoring members from a sorted set after removing them."),
+    command("zrange", -4, READ, KEY, "sorted-set", "Returns members in a sorted set within a range of indexes."),
+    command("zrangebyscore", -4, READ, KEY, "sorted-set", "Returns members in a sorted set within a range of scores."),
+    command("zrank", -3, READ_FAST, KEY, "sorted-set", "Returns the index of a member in a sorted set ordered by ascending scores."),
+    command("zrem", -3, WRITE_FAST, KEY, "sorted-set", "Removes one or more members from a sorted set."),
+    command("zrevrank", -3, READ_FAST, KEY, "sorted-set", "Returns the index of a member in a sorted set ordered by descending scores."),
+    command("zscore", 3, READ_FAST, KEY, "sorted-set", "Returns the score of a member in a sorted set."),
+];
+
+// the name is case insensitive
+pub fn find_command(name: &[u8]) -> Option<&'static CommandInfo> {
+    COMMAND_TABLE.iter().find(|c| c.name.as_bytes().eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_stats::COMMANDS;
+    use crate::command_table::{find_command, COMMAND_TABLE};
+
+    #[test]
+    fn test_command_table() {
+        let names: Vec<&str> = COMMAND_TABLE.iter().map(|c| c.name).collect();
+        assert_eq!(names, COMMANDS);
+        assert!(COMMAND_TABLE.iter().all(|c| c.arity != 0 && (c.first_key == 0) == (c.step == 0)));
+        let mset = find_command(b"MSET").unwrap();
+        assert_eq!((mset.arity, mset.first_key, mset.last_key, mset.step), (-3, 1, -1, 2));
+        assert!(find_command(b"x").is_none());
+    }
+}
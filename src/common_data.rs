@@ -1,25 +1,68 @@
-use std::collections::HashMap;
-use std::net::TcpStream;
-use std::sync::{Arc, Mutex, RwLock};
-use std::sync::atomic::AtomicBool;
-use std::time::SystemTime;
-use crate::common_maps;
-use crate::common_maps::{build_maps, CommonMaps};
+use std::collections::{HashMap, HashSet};
+use std::net::Shutdown;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Instant, SystemTime};
+use crate::allocator::allocated_memory;
+use crate::bitmap::{apply, bit_count, get_bit, set_bit, BitOperation};
+use crate::common_maps::{common_maps_factory, EvictionPolicy};
+use crate::command_stats::CommandStats;
+use crate::config_file::{read_config_file, rewrite_config_file};
+use crate::connections::Connection;
+use crate::event_hooks::EventHooks;
+use crate::glob::glob_match;
+use rand::seq::index::sample;
 use crate::hash_builders::HashBuilder;
+use crate::member_set::{MemberSet, SetOperation};
+use crate::latency_monitor::LatencyMonitor;
+use crate::network_stats::NetworkStats;
+#[cfg(feature = "server")]
+use crate::otlp::SpanExporter;
+use crate::pubsub::{encode_message, PubSub};
+use crate::shared_values::SharedValues;
+use crate::storage_backend::{GetResult, ShardStats, StorageBackend, StorageBackendFactory, UpdateFunction};
+use crate::values::{format_float, ValueHolder, ValueSettings, WRONGTYPE_ERROR};
+use crate::sorted_set::SortedSet;
+use crate::values::ValueHolder::{HashSetValue, SortedSetValue};
 
 pub struct CommonData {
     start_time: SystemTime,
     hash_builder: Box<dyn HashBuilder + Send + Sync>,
-    pub verbose: bool,
+    // additional server messages, can be changed at runtime
+    verbose: AtomicBool,
+    max_memory: AtomicUsize,
+    pub use_allocator_stats: bool,
+    eviction_policy: RwLock<EvictionPolicy>,
+    // number of maps checked to select a map for eviction
+    maxmemory_samples: AtomicUsize,
+    eviction_batch: AtomicUsize,
+    // expiration in milliseconds for keys set without expiry, 0 - keys do not expire
+    default_ttl: AtomicU64,
+    value_settings: ValueSettings,
+    shared_values: Arc<SharedValues>,
     pub configuration: HashMap<Vec<u8>, Vec<u8>>,
-    maps: Vec<RwLock<CommonMaps>>,
+    maps: Vec<Box<dyn StorageBackend>>,
+    // memory used by all maps
+    used_memory: Arc<AtomicUsize>,
+    defragmented_maps: AtomicUsize,
+    keyspace_hits: AtomicUsize,
+    keyspace_misses: AtomicUsize,
+    hooks: EventHooks,
+    pub(crate) command_stats: CommandStats,
+    pub(crate) network_stats: NetworkStats,
+    pub(crate) latency_monitor: LatencyMonitor,
+    #[cfg(feature = "server")]
+    span_exporter: OnceLock<SpanExporter>,
+    // changeable settings are applied from this file at startup and by CONFIG RELOAD
+    config_file: OnceLock<String>,
     pub exit_flag: AtomicBool,
-    pub threads: RwLock<HashMap<usize, Arc<Mutex<TcpStream>>>>,
+    pub threads: RwLock<HashMap<usize, Arc<Connection>>>,
+    pub(crate) pubsub: PubSub,
 }
 
 impl CommonData {
     pub fn flush(&self) {
-        self.maps.iter().for_each(|m|m.write().unwrap().flush());
+        self.maps.iter().for_each(|m| m.flush());
     }
 
     pub fn removekeys(&self, keys: Vec<&Vec<u8>>) -> isize {
@@ -36,49 +79,1357 @@ impl CommonData {
             }
         }
         key_map.into_iter()
-            .map(|(idx, keys)|self.maps[idx].write().unwrap().removekeys(keys))
+            .map(|(idx, keys)| {
+                let removed = self.maps[idx].removekeys(keys);
+                removed.iter().for_each(|k| self.hooks.delete(k));
+                removed.len() as isize
+            })
             .sum()
     }
 
-    pub fn set(&self, key: &Vec<u8>, value: &Vec<u8>, expiry: Option<u64>) {
+    // number of existing keys, a key is counted every time it is repeated
+    pub fn exists(&self, keys: Vec<&Vec<u8>>) -> isize {
+        keys.into_iter()
+            .filter(|k| self.maps[self.hash_builder.build_hash(k)].ttl(k, self.start_time).is_some())
+            .count() as isize
+    }
+
+    // moves the keys to the end of the LRU order, returns the number of existing keys
+    pub fn touch(&self, keys: Vec<&Vec<u8>>) -> isize {
+        keys.into_iter()
+            .filter(|k| {
+                let mut found = false;
+                // update sets the key time to now
+                let _ = self.update_value(k, &mut |v, _ttl| {
+                    found = v.is_some();
+                    Ok(())
+                });
+                found
+            })
+            .count() as isize
+    }
+
+    // returns the number of added members, a missing key is created
+    pub fn sadd(&self, key: &[u8], members: &[&Vec<u8>]) -> Result<usize, &'static str> {
+        if *self.eviction_policy.read().unwrap() == EvictionPolicy::NoEviction && self.memory_limit_reached() {
+            return Err(OOM_ERROR);
+        }
+        let mut added = 0;
+        self.update_value(key, &mut |v, ttl| {
+            if v.is_none() {
+                *v = Some(HashSetValue(MemberSet::new()));
+                *ttl = self.default_ttl();
+            }
+            match v {
+                Some(HashSetValue(set)) => {
+                    added = members.iter().filter(|m| set.insert(m)).count();
+                    Ok(())
+                }
+                _ => Err(WRONGTYPE_ERROR)
+            }
+        })?;
+        self.cleanup();
+        Ok(added)
+    }
+
+    // returns the number of removed members, the key is removed with the last member
+    pub fn srem(&self, key: &[u8], members: &[&Vec<u8>]) -> Result<usize, &'static str> {
+        let mut removed = 0;
+        let mut deleted = false;
+        self.update_value(key, &mut |v, _ttl| {
+            match v {
+                Some(HashSetValue(set)) => {
+                    removed = members.iter().filter(|m| set.remove(m)).count();
+                    if set.is_empty() {
+                        *v = None;
+                        deleted = true;
+                    }
+                    Ok(())
+                }
+                Some(_) => Err(WRONGTYPE_ERROR),
+                None => Ok(())
+            }
+        })?;
+        if deleted {
+            self.hooks.delete(key);
+        }
+        Ok(removed)
+    }
+
+    // removes up to count random members, the key is removed with the last member
+    pub fn spop(&self, key: &[u8], count: usize) -> Result<Vec<Vec<u8>>, &'static str> {
+        let mut popped = Vec::new();
+        let mut deleted = false;
+        self.update_value(key, &mut |v, _ttl| {
+            match v {
+                Some(HashSetValue(set)) => {
+                    popped = set.pop_random(count);
+                    if set.is_empty() {
+                        *v = None;
+                        deleted = true;
+                    }
+                    Ok(())
+                }
+                Some(_) => Err(WRONGTYPE_ERROR),
+                None => Ok(())
+            }
+        })?;
+        if deleted {
+            self.hooks.delete(key);
+        }
+        Ok(popped)
+    }
+
+    // SINTER, SUNION, SDIFF: every set is read under its own key map lock, so the result is not a snapshot
+    // of all the sets at one moment when they are changed concurrently
+    pub fn set_operation(&self, operation: SetOperation, keys: &[&Vec<u8>]) -> Result<HashSet<Vec<u8>>, &'static str> {
+        let mut members = HashSet::new();
+        let found = self.read_set(keys[0], &mut |set| members = set.iter().cloned().collect())?;
+        if !found && matches!(operation, SetOperation::Intersection | SetOperation::Difference) {
+            return Ok(members);
+        }
+        for key in &keys[1..] {
+            let found = self.read_set(key, &mut |set| set.apply(operation, &mut members))?;
+            if !found && matches!(operation, SetOperation::Intersection) {
+                members.clear();
+            }
+            if members.is_empty() && !matches!(operation, SetOperation::Union) {
+                break;
+            }
+        }
+        Ok(members)
+    }
+
+    // replaces the destination key with a set of the members, an empty set removes the key,
+    // returns the set size
+    pub fn store_set(&self, key: &[u8], members: HashSet<Vec<u8>>) -> Result<usize, &'static str> {
+        let len = members.len();
+        if len == 0 {
+            self.removekeys(vec![&key.to_vec()]);
+            return Ok(0);
+        }
+        if *self.eviction_policy.read().unwrap() == EvictionPolicy::NoEviction && self.memory_limit_reached() {
+            return Err(OOM_ERROR);
+        }
+        let mut set = MemberSet::new();
+        members.iter().for_each(|m| { set.insert(m); });
+        let mut holder = Some(HashSetValue(set));
+        self.update_value(key, &mut |v, ttl| {
+            *v = holder.take();
+            *ttl = self.default_ttl();
+            Ok(())
+        })?;
+        self.cleanup();
+        Ok(len)
+    }
+
+    // calls f with the members of the set, Ok(false) - the key is not found
+    pub fn read_set(&self, key: &[u8], f: &mut dyn FnMut(&MemberSet)) -> Result<bool, &'static str> {
+        let idx = self.hash_builder.build_hash(key);
+        let mut is_set = true;
+        let found = self.maps[idx].read_value(key, self.start_time, &mut |v| match v {
+            HashSetValue(set) => f(set),
+            _ => is_set = false
+        });
+        if is_set { Ok(found) } else { Err(WRONGTYPE_ERROR) }
+    }
+
+    // returns the number of added members (and changed scores with options.ch), a missing key is created
+    // unless options.xx is set
+    pub fn zadd(&self, key: &[u8], options: &ZAddOptions, members: &[(f64, &Vec<u8>)]) -> Result<usize, &'static str> {
+        if *self.eviction_policy.read().unwrap() == EvictionPolicy::NoEviction && self.memory_limit_reached() {
+            return Err(OOM_ERROR);
+        }
+        let mut count = 0;
+        self.update_value(key, &mut |v, ttl| {
+            if v.is_none() {
+                if options.xx {
+                    return Ok(());
+                }
+                *v = Some(SortedSetValue(SortedSet::new()));
+                *ttl = self.default_ttl();
+            }
+            match v {
+                Some(SortedSetValue(set)) => {
+                    for (score, member) in members {
+                        let skip = match set.score(member) {
+                            None => options.xx,
+                            Some(old) => options.nx || (options.gt && *score <= old) || (options.lt && *score >= old)
+                        };
+                        if skip {
+                            continue;
+                        }
+                        match set.insert(member, *score) {
+                            None => count += 1,
+                            Some(old) if options.ch && old != *score => count += 1,
+                            _ => {}
+                        }
+                    }
+                    Ok(())
+                }
+                _ => Err(WRONGTYPE_ERROR)
+            }
+        })?;
+        self.cleanup();
+        Ok(count)
+    }
+
+    // ZINCRBY, a missing member is added with the increment as its score, returns the new score
+    pub fn zincrby(&self, key: &[u8], increment: f64, member: &[u8]) -> Result<f64, &'static str> {
+        if *self.eviction_policy.read().unwrap() == EvictionPolicy::NoEviction && self.memory_limit_reached() {
+            return Err(OOM_ERROR);
+        }
+        let mut score = increment;
+        self.update_value(key, &mut |v, ttl| {
+            if v.is_none() {
+                *v = Some(SortedSetValue(SortedSet::new()));
+                *ttl = self.default_ttl();
+            }
+            match v {
+                Some(SortedSetValue(set)) => {
+                    score = set.score(member).map(|s| s + increment).unwrap_or(increment);
+                    // inf + -inf
+                    if score.is_nan() {
+                        if set.is_empty() {
+                            *v = None;
+                        }
+                        return Err("ERR resulting score is not a number (NaN)");
+                    }
+                    set.insert(member, score);
+                    Ok(())
+                }
+                _ => Err(WRONGTYPE_ERROR)
+            }
+        })?;
+        self.cleanup();
+        Ok(score)
+    }
+
+    // returns the number of removed members, the key is removed with the last member
+    pub fn zrem(&self, key: &[u8], members: &[&Vec<u8>]) -> Result<usize, &'static str> {
+        let mut removed = 0;
+        let mut deleted = false;
+        self.update_value(key, &mut |v, _ttl| {
+            match v {
+                Some(SortedSetValue(set)) => {
+                    removed = members.iter().filter(|m| set.remove(m)).count();
+                    if set.is_empty() {
+                        *v = None;
+                        deleted = true;
+                    }
+                    Ok(())
+                }
+                Some(_) => Err(WRONGTYPE_ERROR),
+                None => Ok(())
+            }
+        })?;
+        if deleted {
+            self.hooks.delete(key);
+        }
+        Ok(removed)
+    }
+
+    // ZPOPMIN, ZPOPMAX (max = true), the key is removed with the last member
+    pub fn zpop(&self, key: &[u8], count: usize, max: bool) -> Result<Vec<(Vec<u8>, f64)>, &'static str> {
+        let mut popped = Vec::new();
+        let mut deleted = false;
+        self.update_value(key, &mut |v, _ttl| {
+            match v {
+                Some(SortedSetValue(set)) => {
+                    popped = set.pop(count, max);
+                    if set.is_empty() {
+                        *v = None;
+                        deleted = true;
+                    }
+                    Ok(())
+                }
+                Some(_) => Err(WRONGTYPE_ERROR),
+                None => Ok(())
+            }
+        })?;
+        if deleted {
+            self.hooks.delete(key);
+        }
+        Ok(popped)
+    }
+
+    // calls f with the sorted set, Ok(false) - the key is not found
+    pub fn read_sorted_set(&self, key: &[u8], f: &mut dyn FnMut(&SortedSet)) -> Result<bool, &'static str> {
+        let idx = self.hash_builder.build_hash(key);
+        let mut is_sorted_set = true;
+        let found = self.maps[idx].read_value(key, self.start_time, &mut |v| match v {
+            SortedSetValue(set) => f(set),
+            _ => is_sorted_set = false
+        });
+        if is_sorted_set { Ok(found) } else { Err(WRONGTYPE_ERROR) }
+    }
+
+    // writes the message to the connections subscribed to the channel or to a matching pattern, returns the number
+    // of delivered messages, a connection subscribed to the channel and to a pattern receives both messages,
+    // a slow subscriber blocks the publisher until its socket accepts the message
+    pub fn publish(&self, channel: &[u8], message: &[u8]) -> usize {
+        self.pubsub.subscribers(channel).into_iter()
+            .filter(|(client, pattern)| {
+                let mut data = Vec::new();
+                encode_message(pattern.as_deref(), channel, message, &mut data);
+                let sent = self.connection(*client).is_some_and(|c| c.write(&data).is_ok());
+                if sent {
+                    self.network_stats.add_output(data.len());
+                }
+                sent
+            })
+            .count()
+    }
+
+    // callbacks for embedders, see EventHooks
+    pub fn on_set<F: Fn(&[u8], &[u8]) + Send + Sync + 'static>(&self, hook: F) {
+        self.hooks.add_set_hook(Box::new(hook));
+    }
+
+    pub fn on_delete<F: Fn(&[u8]) + Send + Sync + 'static>(&self, hook: F) {
+        self.hooks.add_delete_hook(Box::new(hook));
+    }
+
+    pub fn on_expire<F: Fn(&[u8]) + Send + Sync + 'static>(&self, hook: F) {
+        self.hooks.add_expire_hook(Box::new(hook));
+    }
+
+    pub fn on_evict<F: Fn(&[u8]) + Send + Sync + 'static>(&self, hook: F) {
+        self.hooks.add_evict_hook(Box::new(hook));
+    }
+
+    // returns false when maximum memory is reached and the eviction policy is noeviction,
+    // keys without expiry get default-ttl when it is set
+    pub fn set(&self, key: &[u8], value: &Vec<u8>, expiry: Option<u64>) -> bool {
+        if *self.eviction_policy.read().unwrap() == EvictionPolicy::NoEviction && self.memory_limit_reached() {
+            return false;
+        }
+        let expiry = expiry.or_else(|| self.default_ttl());
+        let idx = self.hash_builder.build_hash(key);
+        // compression and interning are done before taking the lock
+        let holder = ValueHolder::new(value, &self.value_settings, &self.shared_values);
+        self.maps[idx].set_value(key, holder, expiry, self.start_time);
+        self.hooks.set(key, value);
+        self.cleanup();
+        true
+    }
+
+    // keys are grouped by key map, so every key map is locked only once
+    pub fn mset(&self, values: Vec<(&Vec<u8>, &Vec<u8>)>) -> bool {
+        if *self.eviction_policy.read().unwrap() == EvictionPolicy::NoEviction && self.memory_limit_reached() {
+            return false;
+        }
+        let expiry = self.default_ttl();
+        let mut key_map: HashMap<usize, Vec<(&Vec<u8>, ValueHolder)>> = HashMap::new();
+        for (key, value) in &values {
+            let holder = ValueHolder::new(value, &self.value_settings, &self.shared_values);
+            key_map.entry(self.hash_builder.build_hash(key)).or_default().push((key, holder));
+        }
+        key_map.into_iter().for_each(|(idx, v)| self.maps[idx].set_values(v, expiry, self.start_time));
+        values.into_iter().for_each(|(k, v)| self.hooks.set(k, v));
+        self.cleanup();
+        true
+    }
+
+    // get that changes the expiration time of the found key to now + ttl ms, None - the key does not expire,
+    // Some(0) - the key is removed after it is read, keys of other types are not changed
+    pub fn get_ex(&self, key: &[u8], ttl: Option<u64>, result: &mut Vec<u8>) -> Result<bool, &'static str> {
+        let mut found = false;
+        self.update_value(key, &mut |v, t| {
+            if let Some(value) = v.as_ref() {
+                if !value.is_string() {
+                    return Err(WRONGTYPE_ERROR);
+                }
+                value.encode(result);
+                found = true;
+                if ttl == Some(0) {
+                    *v = None;
+                } else {
+                    *t = ttl;
+                }
+            }
+            Ok(())
+        })?;
+        if found {
+            self.keyspace_hits.fetch_add(1, Ordering::Relaxed);
+            if ttl == Some(0) {
+                self.hooks.delete(key);
+            }
+        } else {
+            self.keyspace_misses.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(found)
+    }
+
+    pub fn get_range(&self, key: &[u8], start: isize, end: isize) -> Result<Vec<u8>, &'static str> {
+        let idx = self.hash_builder.build_hash(key);
+        let mut range = Ok(Vec::new());
+        let found = self.maps[idx].read_value(key, self.start_time, &mut |v| {
+            range = if v.is_string() { Ok(v.get_range(start, end)) } else { Err(WRONGTYPE_ERROR) };
+        });
+        if found {
+            self.keyspace_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.keyspace_misses.fetch_add(1, Ordering::Relaxed);
+        }
+        range
+    }
+
+    // returns the new value length, an empty value does not create a missing key
+    pub fn set_range(&self, key: &[u8], offset: usize, value: &[u8]) -> Result<usize, &'static str> {
+        if offset + value.len() > MAX_VALUE_SIZE {
+            return Err("ERR string exceeds maximum allowed size (proto-max-bulk-len)");
+        }
+        if *self.eviction_policy.read().unwrap() == EvictionPolicy::NoEviction && self.memory_limit_reached() {
+            return Err(OOM_ERROR);
+        }
+        let has_set_hooks = self.hooks.has_set_hooks();
+        let mut len = 0;
+        let mut new_value = None;
+        self.update_value(key, &mut |v, ttl| {
+            if v.as_ref().is_some_and(|h| !h.is_string()) {
+                return Err(WRONGTYPE_ERROR);
+            }
+            if value.is_empty() {
+                len = v.as_ref().map(|h| h.get_bytes().len()).unwrap_or(0);
+                return Ok(());
+            }
+            let holder = match v.take() {
+                Some(holder) => holder,
+                None => {
+                    *ttl = self.default_ttl();
+                    ValueHolder::StringValue(Vec::new())
+                }
+            };
+            let holder = holder.set_range(offset, value);
+            len = holder.size();
+            if has_set_hooks {
+                new_value = Some(holder.get_bytes().into_owned());
+            }
+            *v = Some(holder);
+            Ok(())
+        })?;
+        if !value.is_empty() {
+            if let Some(new_value) = new_value {
+                self.hooks.set(key, &new_value);
+            }
+            self.cleanup();
+        }
+        Ok(len)
+    }
+
+    // SETBIT, returns the previous bit, a missing key is created
+    pub fn set_bit(&self, key: &[u8], offset: usize, bit: bool) -> Result<bool, &'static str> {
+        if offset / 8 >= MAX_VALUE_SIZE {
+            return Err("ERR bit offset is not an integer or out of range");
+        }
+        if *self.eviction_policy.read().unwrap() == EvictionPolicy::NoEviction && self.memory_limit_reached() {
+            return Err(OOM_ERROR);
+        }
+        let has_set_hooks = self.hooks.has_set_hooks();
+        let mut old = false;
+        let mut new_value = None;
+        self.update_value(key, &mut |v, ttl| {
+            if v.as_ref().is_some_and(|h| !h.is_string()) {
+                return Err(WRONGTYPE_ERROR);
+            }
+            let mut bytes = match v.take() {
+                Some(ValueHolder::StringValue(bytes)) => bytes,
+                Some(holder) => holder.get_bytes().into_owned(),
+                None => {
+                    *ttl = self.default_ttl();
+                    Vec::new()
+                }
+            };
+            old = set_bit(&mut bytes, offset, bit);
+            if has_set_hooks {
+                new_value = Some(bytes.clone());
+            }
+            *v = Some(ValueHolder::StringValue(bytes));
+            Ok(())
+        })?;
+        if let Some(new_value) = new_value {
+            self.hooks.set(key, &new_value);
+        }
+        self.cleanup();
+        Ok(old)
+    }
+
+    // calls f with the bytes of the string value, a missing key is an empty string
+    fn read_string(&self, key: &[u8], f: &mut dyn FnMut(&[u8])) -> Result<(), &'static str> {
         let idx = self.hash_builder.build_hash(key);
-        self.maps[idx].write().unwrap().set(key, value, expiry, self.start_time);
+        let mut is_string = true;
+        let found = self.maps[idx].read_value(key, self.start_time, &mut |v| {
+            if v.is_string() {
+                f(&v.get_bytes());
+            } else {
+                is_string = false;
+            }
+        });
+        if !found {
+            f(&[]);
+        }
+        if is_string { Ok(()) } else { Err(WRONGTYPE_ERROR) }
+    }
+
+    pub fn get_bit(&self, key: &[u8], offset: usize) -> Result<bool, &'static str> {
+        let mut bit = false;
+        self.read_string(key, &mut |bytes| bit = get_bit(bytes, offset))?;
+        Ok(bit)
+    }
+
+    // BITCOUNT, range - inclusive range of bytes or of bits (bit_unit = true)
+    pub fn bit_count(&self, key: &[u8], range: Option<(isize, isize)>, bit_unit: bool) -> Result<usize, &'static str> {
+        let mut count = 0;
+        self.read_string(key, &mut |bytes| count = bit_count(bytes, range, bit_unit))?;
+        Ok(count)
+    }
+
+    // BITOP, the source values are read one at a time like in set_operation, an empty result removes the destination,
+    // returns the result length
+    pub fn bit_operation(&self, operation: BitOperation, destination: &Vec<u8>, keys: &[&Vec<u8>])
+        -> Result<usize, &'static str> {
+        let mut value = Vec::new();
+        self.read_string(keys[0], &mut |bytes| value = bytes.to_vec())?;
+        if matches!(operation, BitOperation::Not) {
+            apply(operation, &mut value, &[]);
+        }
+        for key in &keys[1..] {
+            self.read_string(key, &mut |bytes| apply(operation, &mut value, bytes))?;
+        }
+        if value.is_empty() {
+            self.removekeys(vec![destination]);
+        } else if !self.set(destination, &value, None) {
+            return Err(OOM_ERROR);
+        }
+        Ok(value.len())
+    }
+
+    // sets the key only when it does not exist, returns true when the key was set
+    pub fn set_nx(&self, key: &[u8], value: &Vec<u8>) -> Result<bool, &'static str> {
+        let options = SetOptions { nx: true, ..SetOptions::default() };
+        self.set_with_options(key, value, &options, &mut Vec::new()).map(|(set, _found)| set)
+    }
+
+    // SET with conditions, the old value is encoded into old_value when options.get is set,
+    // returns (the key was set, the key existed)
+    pub fn set_with_options(&self, key: &[u8], value: &Vec<u8>, options: &SetOptions, old_value: &mut Vec<u8>)
+        -> Result<(bool, bool), &'static str> {
+        if *self.eviction_policy.read().unwrap() == EvictionPolicy::NoEviction && self.memory_limit_reached() {
+            return Err(OOM_ERROR);
+        }
+        let expiry = options.expiry.or_else(|| self.default_ttl());
+        let mut holder = Some(ValueHolder::new(value, &self.value_settings, &self.shared_values));
+        let mut found = false;
+        self.update_value(key, &mut |v, ttl| {
+            found = v.is_some();
+            if options.get {
+                if let Some(old) = v.as_ref() {
+                    if !old.is_string() {
+                        return Err(WRONGTYPE_ERROR);
+                    }
+                    old.encode(old_value);
+                }
+            }
+            if (options.nx && found) || (options.xx && !found) {
+                return Ok(());
+            }
+            *v = holder.take();
+            if !options.keep_ttl || !found {
+                *ttl = expiry;
+            }
+            Ok(())
+        })?;
+        let set = holder.is_none();
+        if set {
+            self.hooks.set(key, value);
+            self.cleanup();
+        }
+        Ok((set, found))
+    }
+
+    // returns the new value, a missing key is created with 0 + increment
+    pub fn incr_by_float(&self, key: &[u8], increment: f64) -> Result<f64, &'static str> {
+        if *self.eviction_policy.read().unwrap() == EvictionPolicy::NoEviction && self.memory_limit_reached() {
+            return Err(OOM_ERROR);
+        }
+        let mut new_value = 0.0;
+        self.update_value(key, &mut |value, ttl| {
+            let current = match value {
+                Some(v) if !v.is_string() => return Err(WRONGTYPE_ERROR),
+                Some(v) => v.as_float().ok_or("ERR value is not a valid float")?,
+                None => {
+                    *ttl = self.default_ttl();
+                    0.0
+                }
+            };
+            new_value = current + increment;
+            if !new_value.is_finite() {
+                return Err("ERR increment would produce NaN or Infinity");
+            }
+            *value = Some(ValueHolder::FloatValue(new_value));
+            Ok(())
+        })?;
+        self.hooks.set(key, format_float(new_value).as_bytes());
+        self.cleanup();
+        Ok(new_value)
+    }
+
+    fn default_ttl(&self) -> Option<u64> {
+        match self.default_ttl.load(Ordering::Relaxed) {
+            0 => None,
+            ttl => Some(ttl)
+        }
+    }
+
+    fn memory_limit_reached(&self) -> bool {
+        let used = if self.use_allocator_stats {
+            allocated_memory().unwrap_or_else(|| self.memory())
+        } else {
+            self.memory()
+        };
+        used >= self.max_memory()
+    }
+
+    // returns the map with the lowest eviction score among maxmemory_samples random maps,
+    // when none of them has keys to evict - among all maps
+    fn select_map_for_eviction(&self) -> Option<usize> {
+        let samples = self.maxmemory_samples.load(Ordering::Relaxed);
+        let best = |indexes: &mut dyn Iterator<Item = usize>| indexes
+            .filter_map(|idx| self.maps[idx].eviction_score().map(|s| (s, idx)))
+            .min()
+            .map(|(_s, idx)| idx);
+        if samples < self.maps.len() {
+            let selected = best(&mut sample(&mut rand::thread_rng(), self.maps.len(), samples).into_iter());
+            if selected.is_some() {
+                return selected;
+            }
+        }
+        best(&mut (0..self.maps.len()))
+    }
+
+    // the memory budget is shared by all maps, so keys are evicted from the map selected by the eviction policy
+    // regardless of where the insert landed, only one map lock is held at a time
+    fn cleanup(&self) {
+        if !self.memory_limit_reached() {
+            return;
+        }
+        let start = Instant::now();
+        while self.memory_limit_reached() {
+            match self.select_map_for_eviction() {
+                Some(idx) => {
+                    let evicted = self.maps[idx].evict(self.start_time);
+                    if evicted.keys.is_empty() {
+                        break;
+                    }
+                    if evicted.expired {
+                        evicted.keys.iter().for_each(|k| self.hooks.expire(k));
+                    } else {
+                        evicted.keys.iter().for_each(|k| self.hooks.evict(k));
+                    }
+                }
+                None => break
+            }
+        }
+        self.latency_monitor.add_sample("eviction-cycle", start.elapsed());
+    }
+
+    pub fn max_memory(&self) -> usize {
+        self.max_memory.load(Ordering::Relaxed)
+    }
+
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        *self.eviction_policy.read().unwrap()
+    }
+
+    pub fn set_eviction_policy(&self, policy: EvictionPolicy) {
+        *self.eviction_policy.write().unwrap() = policy;
+        self.update_eviction_parameters();
+    }
+
+    pub fn get_configuration(&self, name: &[u8]) -> Option<Vec<u8>> {
+        let value = match name.to_ascii_lowercase().as_slice() {
+            b"maxmemory" => self.max_memory().to_string(),
+            b"maxmemory-policy" => self.eviction_policy().get_name().to_string(),
+            b"maxmemory-samples" => self.maxmemory_samples.load(Ordering::Relaxed).to_string(),
+            b"maxmemory-eviction-batch" => self.eviction_batch.load(Ordering::Relaxed).to_string(),
+            b"default-ttl" => self.default_ttl.load(Ordering::Relaxed).to_string(),
+            b"verbose" => (if self.verbose() { "yes" } else { "no" }).to_string(),
+            b"latency-monitor-threshold" => self.latency_monitor.threshold().to_string(),
+            #[cfg(feature = "server")]
+            b"loglevel" => log::max_level().as_str().to_ascii_lowercase(),
+            _ => return self.configuration.get(name).cloned()
+        };
+        Some(value.into_bytes())
+    }
+
+    pub fn set_configuration(&self, name: &[u8], value: &[u8]) -> Result<(), &'static str> {
+        match name.to_ascii_lowercase().as_slice() {
+            b"maxmemory" => {
+                let max_memory = parse_size(value).ok_or("invalid maxmemory value")?;
+                self.max_memory.store(max_memory, Ordering::Relaxed);
+                self.cleanup();
+            }
+            b"maxmemory-policy" => {
+                let policy = EvictionPolicy::parse(value).ok_or("invalid maxmemory-policy value")?;
+                self.set_eviction_policy(policy);
+            }
+            b"maxmemory-samples" => {
+                // at least one key map has to be sampled and one key evicted, otherwise memory is never reclaimed
+                let samples = parse_size(value).filter(|s| *s > 0).ok_or("invalid maxmemory-samples value")?;
+                self.maxmemory_samples.store(samples, Ordering::Relaxed);
+            }
+            b"maxmemory-eviction-batch" => {
+                let batch = parse_size(value).filter(|b| *b > 0).ok_or("invalid maxmemory-eviction-batch value")?;
+                self.eviction_batch.store(batch, Ordering::Relaxed);
+                self.update_eviction_parameters();
+            }
+            b"default-ttl" => {
+                let ttl = std::str::from_utf8(value).ok()
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .ok_or("invalid default-ttl value")?;
+                self.default_ttl.store(ttl, Ordering::Relaxed);
+            }
+            b"verbose" => {
+                let verbose = match value.to_ascii_lowercase().as_slice() {
+                    b"yes" => true,
+                    b"no" => false,
+                    _ => return Err("invalid verbose value")
+                };
+                self.verbose.store(verbose, Ordering::Relaxed);
+            }
+            b"latency-monitor-threshold" => {
+                let threshold = std::str::from_utf8(value).ok()
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .ok_or("invalid latency-monitor-threshold value")?;
+                self.latency_monitor.set_threshold(threshold);
+            }
+            #[cfg(feature = "server")]
+            b"loglevel" => {
+                let level = std::str::from_utf8(value).ok()
+                    .and_then(|v| v.parse::<log::LevelFilter>().ok())
+                    .ok_or("invalid loglevel value")?;
+                log::set_max_level(level);
+            }
+            _ => return Err("unsupported configuration parameter")
+        }
+        Ok(())
+    }
+
+    pub fn verbose(&self) -> bool {
+        self.verbose.load(Ordering::Relaxed)
+    }
+
+    // can be set once
+    pub fn set_config_file(&self, file_name: &str) {
+        let _ = self.config_file.set(file_name.to_string());
+    }
+
+    // applies all settings from the configuration file, stops at the first invalid setting
+    pub fn reload_configuration(&self) -> Result<(), String> {
+        let file_name = self.config_file.get().ok_or("configuration file is not specified")?;
+        for (line, name, value) in read_config_file(file_name)? {
+            self.set_configuration(&name, &value)
+                .map_err(|e| format!("{} line {}: {}", file_name, line, e))?;
+        }
+        Ok(())
+    }
+
+    // writes the current values of the settings that can be changed with CONFIG SET to the configuration file
+    pub fn rewrite_configuration(&self) -> Result<(), String> {
+        let file_name = self.config_file.get().ok_or("configuration file is not specified")?;
+        let names = ["maxmemory", "maxmemory-policy", "maxmemory-samples", "maxmemory-eviction-batch", "default-ttl",
+            "verbose", "latency-monitor-threshold", #[cfg(feature = "server")] "loglevel"];
+        let settings: Vec<(&str, String)> = names.iter()
+            .filter_map(|n| self.get_configuration(n.as_bytes())
+                .map(|v| (*n, String::from_utf8_lossy(&v).to_string())))
+            .collect();
+        rewrite_config_file(file_name, &settings)
+    }
+
+    fn update_eviction_parameters(&self) {
+        let policy = self.eviction_policy();
+        let batch = self.eviction_batch.load(Ordering::Relaxed);
+        self.maps.iter().for_each(|m| m.set_eviction_parameters(policy, batch));
     }
 
-    pub fn get(&self, key: &Vec<u8>, result: &mut Vec<u8>) -> bool {
+    pub fn get(&self, key: &[u8], result: &mut Vec<u8>) -> bool {
         let idx = self.hash_builder.build_hash(key);
-        let lock = self.maps[idx].read().unwrap();
-        match lock.get(key, result, self.start_time) {
-            common_maps::GetResult::Found => true,
-            common_maps::GetResult::NotFound => false,
-            common_maps::GetResult::Expired => {
-                drop(lock);
-                self.maps[idx].write().unwrap().removekey(key);
+        let map = &self.maps[idx];
+        let found = match map.get(key, result, self.start_time) {
+            GetResult::Found => true,
+            GetResult::NotFound => false,
+            GetResult::Expired => {
+                if map.remove_if_expired(key, self.start_time) {
+                    self.hooks.expire(key);
+                }
                 false
             }
+        };
+        if found {
+            self.keyspace_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.keyspace_misses.fetch_add(1, Ordering::Relaxed);
+        }
+        found
+    }
+
+    // read-modify-write of one key, an expired record removed by the key map is reported to the expire hooks
+    // before the caller reports its own change
+    fn update_value(&self, key: &[u8], f: UpdateFunction<'_>) -> Result<(), &'static str> {
+        let idx = self.hash_builder.build_hash(key);
+        if self.maps[idx].update(key, self.start_time, f)? {
+            self.hooks.expire(key);
+        }
+        Ok(())
+    }
+
+    // MGET: keys of other types are returned as nil like in Redis, the value encoder writes WRONGTYPE for them
+    // and encoded strings never start with '-'
+    pub fn get_string(&self, key: &[u8], result: &mut Vec<u8>) -> bool {
+        let start = result.len();
+        if !self.get(key, result) {
+            return false;
+        }
+        if result.get(start) == Some(&b'-') {
+            result.truncate(start);
+            return false;
+        }
+        true
+    }
+
+    // index of the key map that stores the key
+    pub fn key_map_index(&self, key: &[u8]) -> usize {
+        self.hash_builder.build_hash(key)
+    }
+
+    // command spans are exported only when the exporter is set, it can be set once
+    #[cfg(feature = "server")]
+    pub(crate) fn set_span_exporter(&self, exporter: SpanExporter) {
+        let _ = self.span_exporter.set(exporter);
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn span_exporter(&self) -> Option<&SpanExporter> {
+        self.span_exporter.get()
+    }
+
+    // number of successful and failed key lookups
+    pub fn keyspace_stats(&self) -> (usize, usize) {
+        (self.keyspace_hits.load(Ordering::Relaxed), self.keyspace_misses.load(Ordering::Relaxed))
+    }
+
+    // resets the command statistics, network statistics and keyspace hit/miss counters
+    pub fn reset_stats(&self) {
+        self.command_stats.reset();
+        self.network_stats.reset();
+        self.keyspace_hits.store(0, Ordering::Relaxed);
+        self.keyspace_misses.store(0, Ordering::Relaxed);
+    }
+
+    // expiry in ms from now, None - removes the expiration, returns false when the key is not found
+    pub fn expire(&self, key: &[u8], expiry: Option<u64>) -> bool {
+        let idx = self.hash_builder.build_hash(key);
+        self.maps[idx].set_expiry(key, expiry, self.start_time)
+    }
+
+    // removes the expiration, returns false when the key is not found or does not expire
+    pub fn persist(&self, key: &[u8]) -> bool {
+        let mut removed = false;
+        let _ = self.update_value(key, &mut |v, ttl| {
+            removed = v.is_some() && ttl.take().is_some();
+            Ok(())
+        });
+        removed
+    }
+
+    // None - the key is not found, Some(None) - the key does not expire, Some(Some(ms)) - remaining time to live
+    pub fn ttl(&self, key: &[u8]) -> Option<Option<u64>> {
+        let idx = self.hash_builder.build_hash(key);
+        self.maps[idx].ttl(key, self.start_time)
+    }
+
+    // cursor = key map cursor * number of key maps + key map index, 0 - start and end of the iteration
+    // at least count entries are visited when there are enough, keys are filtered by pattern after that
+    pub fn scan(&self, cursor: usize, count: usize, pattern: Option<&[u8]>) -> (usize, Vec<Vec<u8>>) {
+        let n = self.maps.len();
+        let mut idx = cursor % n;
+        let mut map_cursor = cursor / n;
+        let mut keys = Vec::new();
+        let mut visited = 0;
+        let next = loop {
+            let (next, v) = self.maps[idx].scan(map_cursor, count - visited, self.start_time, &mut keys);
+            visited += v;
+            if next != 0 {
+                break next * n + idx;
+            }
+            idx += 1;
+            map_cursor = 0;
+            if idx == n {
+                break 0;
+            }
+            if visited >= count {
+                break idx;
+            }
+        };
+        if let Some(p) = pattern {
+            keys.retain(|k| glob_match(p, k));
         }
+        (next, keys)
+    }
+
+    pub fn memory_usage(&self, key: &[u8]) -> Option<usize> {
+        let idx = self.hash_builder.build_hash(key);
+        self.maps[idx].memory_usage(key, self.start_time)
+    }
+
+    // string, set or zset
+    pub fn key_type(&self, key: &[u8]) -> Option<&'static str> {
+        let idx = self.hash_builder.build_hash(key);
+        let mut name = None;
+        self.maps[idx].read_value(key, self.start_time, &mut |v| name = Some(v.type_name()));
+        name
+    }
+
+    // DEBUG OBJECT: encoding and size of the stored data
+    pub fn object_info(&self, key: &[u8]) -> Option<(&'static str, usize)> {
+        let idx = self.hash_builder.build_hash(key);
+        let mut info = None;
+        self.maps[idx].read_value(key, self.start_time, &mut |v| info = Some((v.encoding_name(), v.size())));
+        info
     }
 
     pub fn size(&self) -> usize {
-        self.maps.iter().map(|m|m.read().unwrap().size()).sum()
+        self.maps.iter().map(|m| m.size()).sum()
+    }
+
+    pub fn memory(&self) -> usize {
+        self.used_memory.load(Ordering::Relaxed) + self.shared_values.memory()
+    }
+
+    // shrinks fragmented maps (all maps when force is set), one map is locked at a time
+    // returns the number of shrunk maps
+    pub fn defrag(&self, force: bool) -> usize {
+        let mut count = 0;
+        for m in &self.maps {
+            if force || m.is_fragmented() {
+                m.shrink();
+                count += 1;
+            }
+        }
+        if force {
+            self.shared_values.shrink();
+        }
+        self.defragmented_maps.fetch_add(count, Ordering::Relaxed);
+        count
+    }
+
+    pub fn defragmented_maps(&self) -> usize {
+        self.defragmented_maps.load(Ordering::Relaxed)
+    }
+
+    // number of distinct interned values and their memory
+    pub fn shared_values_stats(&self) -> (usize, usize) {
+        (self.shared_values.count(), self.shared_values.memory())
+    }
+
+    // number of expired and evicted keys
+    pub fn removal_stats(&self) -> (usize, usize) {
+        self.maps.iter()
+            .map(|m| m.removal_stats())
+            .fold((0, 0), |(e, v), (e2, v2)| (e + e2, v + v2))
+    }
+
+    pub fn compression_stats(&self) -> (usize, usize, usize) {
+        self.maps.iter()
+            .map(|m| m.compression_stats())
+            .fold((0, 0, 0), |(c, o, s), (c2, o2, s2)| (c + c2, o + o2, s + s2))
+    }
+
+    pub fn shard_stats(&self) -> Vec<ShardStats> {
+        self.maps.iter().map(|m| m.shard_stats()).collect()
+    }
+
+    // number of keys and number of keys with expiration time
+    pub fn key_counts(&self) -> (usize, usize) {
+        self.maps.iter()
+            .map(|m| m.shard_stats())
+            .fold((0, 0), |(k, e), s| (k + s.keys, e + s.expires))
+    }
+
+    pub fn uptime(&self) -> u64 {
+        self.start_time.elapsed().map(|d| d.as_secs()).unwrap_or(0)
+    }
+
+    pub fn connected_clients(&self) -> usize {
+        self.threads.read().unwrap().len()
+    }
+
+    pub fn connection(&self, client: usize) -> Option<Arc<Connection>> {
+        self.threads.read().unwrap().get(&client).cloned()
+    }
+
+    // CLIENT LIST lines ordered by connection id, all connections when ids is empty
+    pub fn client_list(&self, ids: &[usize]) -> String {
+        let mut connections: Vec<(usize, Arc<Connection>)> = self.threads.read().unwrap().iter()
+            .filter(|(id, _c)| ids.is_empty() || ids.contains(id))
+            .map(|(id, c)| (*id, c.clone()))
+            .collect();
+        connections.sort_by_key(|(id, _c)| *id);
+        connections.iter()
+            .map(|(id, c)| {
+                let sub = self.pubsub.subscriptions(*id, false).len();
+                let psub = self.pubsub.subscriptions(*id, true).len();
+                format!("{}\n", c.info(*id, sub, psub))
+            })
+            .collect()
+    }
+
+    // closes the connections selected by the filter, returns the number of closed connections
+    pub fn kill_clients(&self, filter: &dyn Fn(usize, &Connection) -> bool) -> usize {
+        let connections: Vec<Arc<Connection>> = self.threads.read().unwrap().iter()
+            .filter(|(id, c)| filter(**id, c))
+            .map(|(_id, c)| c.clone())
+            .collect();
+        connections.iter().for_each(|c| c.shutdown(Shutdown::Both));
+        connections.len()
     }
 }
 
+// positive number with optional k, m or g suffix
+fn parse_size(value: &[u8]) -> Option<usize> {
+    let s = std::str::from_utf8(value).ok()?.to_ascii_lowercase();
+    let (number, multiplier) = match s.chars().last()? {
+        'k' => (&s[..s.len() - 1], 1024),
+        'm' => (&s[..s.len() - 1], 1024 * 1024),
+        'g' => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s.as_str(), 1)
+    };
+    match number.parse::<usize>() {
+        Ok(n) if n > 0 => n.checked_mul(multiplier),
+        _ => None
+    }
+}
+
+// number of database indexes accepted by SELECT, all of them refer to the same keyspace
+pub const DATABASES: usize = 16;
+
+// SET options, expiry is the time to live in ms
+#[derive(Default)]
+pub struct SetOptions {
+    pub nx: bool,
+    pub xx: bool,
+    pub keep_ttl: bool,
+    pub get: bool,
+    pub expiry: Option<u64>,
+}
+
+// ZADD options: nx - only new members, xx - only existing members, gt/lt - only a greater/lower new score,
+// ch - changed scores are counted together with added members
+#[derive(Default)]
+pub struct ZAddOptions {
+    pub nx: bool,
+    pub xx: bool,
+    pub gt: bool,
+    pub lt: bool,
+    pub ch: bool,
+}
+
+// the largest value SETRANGE can create
+pub const MAX_VALUE_SIZE: usize = 512 * 1024 * 1024;
+
+pub const OOM_ERROR: &str = "OOM command not allowed when used memory > 'maxmemory'";
+
 fn build_configuration() -> HashMap<Vec<u8>, Vec<u8>> {
     HashMap::from([
         ("save".to_string().into_bytes(), "".to_string().into_bytes()),
+        ("databases".to_string().into_bytes(), DATABASES.to_string().into_bytes()),
         ("appendonly".to_string().into_bytes(), "no".to_string().into_bytes())])
 }
 
 pub fn build_common_data(verbose: bool, max_memory: usize, vector_size: usize,
-                         hash_builder: Box<dyn HashBuilder + Send + Sync>) -> CommonData {
+                         hash_builder: Box<dyn HashBuilder + Send + Sync>, use_allocator_stats: bool,
+                         value_settings: ValueSettings) -> CommonData {
+    build_common_data_with_backend(verbose, max_memory, vector_size, hash_builder, use_allocator_stats,
+                                   value_settings, common_maps_factory())
+}
+
+pub fn build_common_data_with_backend(verbose: bool, max_memory: usize, vector_size: usize,
+                                      hash_builder: Box<dyn HashBuilder + Send + Sync>, use_allocator_stats: bool,
+                                      value_settings: ValueSettings, backend_factory: StorageBackendFactory) -> CommonData {
+    let used_memory = Arc::new(AtomicUsize::new(0));
+    let maps = (0..vector_size)
+        .map(|_i| {
+            let map = backend_factory(used_memory.clone());
+            map.set_eviction_parameters(EvictionPolicy::AllKeysLru, 16);
+            map
+        })
+        .collect();
     CommonData {
         start_time: SystemTime::now(),
         hash_builder,
-        verbose,
+        verbose: AtomicBool::new(verbose),
+        max_memory: AtomicUsize::new(max_memory),
+        use_allocator_stats,
+        eviction_policy: RwLock::new(EvictionPolicy::AllKeysLru),
+        maxmemory_samples: AtomicUsize::new(5),
+        eviction_batch: AtomicUsize::new(16),
+        default_ttl: AtomicU64::new(0),
+        value_settings,
+        shared_values: SharedValues::new(),
         configuration: build_configuration(),
-        maps: build_maps(vector_size, max_memory),
+        maps,
+        used_memory,
+        defragmented_maps: AtomicUsize::new(0),
+        keyspace_hits: AtomicUsize::new(0),
+        keyspace_misses: AtomicUsize::new(0),
+        hooks: EventHooks::default(),
+        command_stats: CommandStats::new(),
+        network_stats: NetworkStats::new(),
+        latency_monitor: LatencyMonitor::new(),
+        #[cfg(feature = "server")]
+        span_exporter: OnceLock::new(),
+        config_file: OnceLock::new(),
         exit_flag: AtomicBool::new(false),
         threads: RwLock::new(HashMap::new()),
+        pubsub: PubSub::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use rand::distributions::{Alphanumeric, DistString};
+    use rand::Rng;
+    use crate::common_data::{build_common_data, CommonData};
+    use crate::common_maps::EvictionPolicy;
+    use crate::hash_builders::create_hash_builder;
+    use crate::values::ValueSettings;
+
+    fn build_data(max_memory: usize, vector_size: usize) -> CommonData {
+        build_common_data(false, max_memory, vector_size,
+                          create_hash_builder("sum".to_string(), vector_size).unwrap(), false,
+                          ValueSettings{ compression_threshold: 0, integer_encoding: false, intern_threshold: 0 })
+    }
+
+    #[test]
+    fn test_cleanup() {
+        let mut rng = rand::thread_rng();
+        let data = build_data(100000, 16);
+        for _i in 0..1000 {
+            let key_length = (rng.gen::<usize>() % 100) + 10;
+            let value_length = (rng.gen::<usize>() % 200) + 10;
+            let key = Alphanumeric.sample_string(&mut rng, key_length).into_bytes();
+            let value = Alphanumeric.sample_string(&mut rng, value_length).into_bytes();
+            data.set(&key, &value, None);
+        }
+
+        assert!(data.memory() < data.max_memory());
+        let (expired, evicted) = data.removal_stats();
+        assert_eq!(expired, 0);
+        assert_eq!(data.size() + evicted, 1000);
+    }
+
+    #[test]
+    fn test_cleanup2() {
+        let mut rng = rand::thread_rng();
+        let data = build_data(100000, 1);
+        for _i in 0..1000 {
+            let key_length = (rng.gen::<usize>() % 100) + 10;
+            let value_length = (rng.gen::<usize>() % 200) + 10;
+            let key = Alphanumeric.sample_string(&mut rng, key_length).into_bytes();
+            let value = Alphanumeric.sample_string(&mut rng, value_length).into_bytes();
+            data.set(&key, &value, Some(100));
+        }
+
+        thread::sleep(Duration::from_millis(200));
+
+        let key_length = (rng.gen::<usize>() % 100) + 10;
+        let key = Alphanumeric.sample_string(&mut rng, key_length).into_bytes();
+        let value = Alphanumeric.sample_string(&mut rng, 20000).into_bytes();
+        data.set(&key, &value, None);
+
+        assert_eq!(data.size(), 1);
+        let (expired, evicted) = data.removal_stats();
+        assert!(expired > 0);
+        assert_eq!(expired + evicted, 1000);
+    }
+
+    #[test]
+    fn test_eviction_configuration() {
+        let data = build_data(100000, 16);
+        let name = "maxmemory-policy".to_string().into_bytes();
+        assert_eq!(data.get_configuration(&name), Some("allkeys-lru".to_string().into_bytes()));
+        assert!(data.set_configuration(&name, &"lfu".to_string().into_bytes()).is_err());
+        assert!(data.set_configuration(&name, &"NoEviction".to_string().into_bytes()).is_ok());
+        assert_eq!(data.eviction_policy(), EvictionPolicy::NoEviction);
+        assert!(data.set_configuration(&"maxmemory".to_string().into_bytes(), &"1k".to_string().into_bytes()).is_ok());
+        assert_eq!(data.max_memory(), 1024);
+        assert!(data.set_configuration(b"maxmemory-samples", b"0").is_err());
+        assert!(data.set_configuration(b"maxmemory", b"99999999999999999g").is_err());
+        assert_eq!(data.max_memory(), 1024);
+        assert!(data.set_configuration(b"maxmemory-eviction-batch", b"0").is_err());
+        assert!(data.set_configuration(b"maxmemory-eviction-batch", b"1").is_ok());
+
+        let value = "value".repeat(20).into_bytes();
+        let stored = (0..100).filter(|i| data.set(&i.to_string().into_bytes(), &value, None)).count();
+        assert!(stored > 0 && stored < 100);
+        assert_eq!(data.size(), stored);
+        assert_eq!(data.removal_stats(), (0, 0));
+
+        // volatile-ttl evicts keys with the nearest expiration time only
+        assert!(data.set_configuration(&name, &"volatile-ttl".to_string().into_bytes()).is_ok());
+        assert!(data.set(&"volatile".to_string().into_bytes(), &value, Some(100000)));
+        let mut result = Vec::new();
+        assert!(data.get(&"0".to_string().into_bytes(), &mut result));
+        assert!(!data.get(&"volatile".to_string().into_bytes(), &mut result));
+    }
+
+    #[test]
+    fn test_rewrite_configuration() {
+        let data = build_data(100000, 4);
+        assert!(data.rewrite_configuration().is_err());
+        let path = std::env::temp_dir().join(format!("cache_test_{}.conf", std::process::id()));
+        let file_name = path.to_str().unwrap();
+        std::fs::write(&path, "# test\nmaxmemory 1k\n").unwrap();
+        data.set_config_file(file_name);
+        assert!(data.set_configuration(b"maxmemory", b"2k").is_ok());
+        assert!(data.set_configuration(b"maxmemory-policy", b"noeviction").is_ok());
+        assert!(data.rewrite_configuration().is_ok());
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# test\nmaxmemory 2048\nmaxmemory-policy noeviction\n"), "{}", text);
+
+        let data2 = build_data(100000, 4);
+        data2.set_config_file(file_name);
+        assert!(data2.reload_configuration().is_ok());
+        assert_eq!(data2.max_memory(), 2048);
+        assert_eq!(data2.eviction_policy(), EvictionPolicy::NoEviction);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_default_ttl() {
+        let data = build_data(100000, 4);
+        let name = "default-ttl".to_string().into_bytes();
+        assert!(data.set_configuration(&name, &"x".to_string().into_bytes()).is_err());
+        assert!(data.set_configuration(&name, &"50".to_string().into_bytes()).is_ok());
+        assert_eq!(data.get_configuration(&name), Some("50".to_string().into_bytes()));
+        let verbose = "verbose".to_string().into_bytes();
+        assert_eq!(data.get_configuration(&verbose), Some("no".to_string().into_bytes()));
+        assert!(data.set_configuration(&verbose, &"YES".to_string().into_bytes()).is_ok());
+        assert!(data.verbose());
+        assert!(data.set_configuration(&verbose, &"1".to_string().into_bytes()).is_err());
+        let value = "value".to_string().into_bytes();
+        data.set(&"a".to_string().into_bytes(), &value, None);
+        data.set(&"b".to_string().into_bytes(), &value, Some(100000));
+        assert_eq!(data.set_nx(&"d".to_string().into_bytes(), &value), Ok(true));
+        assert_eq!(data.incr_by_float(b"f", 1.5), Ok(1.5));
+        assert!(data.set_configuration(&name, &"0".to_string().into_bytes()).is_ok());
+        assert_eq!(data.set_nx(&"d".to_string().into_bytes(), &value), Ok(false));
+        data.set(&"c".to_string().into_bytes(), &value, None);
+        thread::sleep(Duration::from_millis(100));
+        let mut result = Vec::new();
+        assert!(!data.get(&"a".to_string().into_bytes(), &mut result));
+        assert!(data.get(&"b".to_string().into_bytes(), &mut result));
+        assert!(data.get(&"c".to_string().into_bytes(), &mut result));
+        assert!(!data.get(&"d".to_string().into_bytes(), &mut result));
+        assert!(!data.get(b"f", &mut result));
+    }
+
+    #[test]
+    fn test_shard_stats() {
+        let data = build_data(100000, 4);
+        let mut result = Vec::new();
+        for i in 0..100 {
+            let key = format!("key{}", i).into_bytes();
+            data.set(&key, &key, None);
+            data.get(&key, &mut result);
+        }
+        let stats = data.shard_stats();
+        assert_eq!(stats.len(), 4);
+        assert_eq!(stats.iter().map(|s| s.keys).sum::<usize>(), 100);
+        assert_eq!(stats.iter().map(|s| s.memory).sum::<usize>(), data.memory());
+        assert_eq!(stats.iter().map(|s| s.reads).sum::<usize>(), 100);
+        assert_eq!(stats.iter().map(|s| s.writes).sum::<usize>(), 100);
+        assert_eq!(stats.iter().map(|s| s.contended).sum::<usize>(), 0);
+
+        assert!(!data.get(&"missing".to_string().into_bytes(), &mut result));
+        assert_eq!(data.keyspace_stats(), (100, 1));
+        data.reset_stats();
+        assert_eq!(data.keyspace_stats(), (0, 0));
+    }
+
+    #[test]
+    fn test_event_hooks() {
+        let data = build_data(2000, 1);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let e = events.clone();
+        data.on_set(move |k, v| e.lock().unwrap().push(format!("set {} {}", String::from_utf8_lossy(k), String::from_utf8_lossy(v))));
+        let e = events.clone();
+        data.on_delete(move |k| e.lock().unwrap().push(format!("delete {}", String::from_utf8_lossy(k))));
+        let e = events.clone();
+        data.on_expire(move |k| e.lock().unwrap().push(format!("expire {}", String::from_utf8_lossy(k))));
+        let e = events.clone();
+        data.on_evict(move |k| e.lock().unwrap().push(format!("evict {}", String::from_utf8_lossy(k))));
+
+        let key = "a".to_string().into_bytes();
+        data.set(&key, &"1".to_string().into_bytes(), None);
+        assert_eq!(data.removekeys(vec![&key, &"b".to_string().into_bytes()]), 1);
+        data.set(&key, &"2".to_string().into_bytes(), Some(50));
+        thread::sleep(Duration::from_millis(100));
+        let mut result = Vec::new();
+        assert!(!data.get(&key, &mut result));
+        // a key removed lazily by an update is reported too
+        data.set(&key, &"3".to_string().into_bytes(), Some(50));
+        thread::sleep(Duration::from_millis(100));
+        assert!(!data.persist(&key));
+        assert_eq!(*events.lock().unwrap(), vec!["set a 1", "delete a", "set a 2", "expire a", "set a 3", "expire a"]);
+
+        events.lock().unwrap().clear();
+        let value = "value".repeat(20).into_bytes();
+        for i in 0..100 {
+            data.set(&i.to_string().into_bytes(), &value, None);
+        }
+        let events = events.lock().unwrap();
+        let evicted = events.iter().filter(|e| e.starts_with("evict ")).count();
+        assert!(evicted > 0);
+        assert_eq!(evicted + data.size(), 100);
+        assert!(!events.contains(&"evict 99".to_string()));
+    }
+
+    #[test]
+    fn test_scan() {
+        let data = build_data(1000000, 4);
+        for i in 0..1000 {
+            let key = format!("key{}", i).into_bytes();
+            data.set(&key, &key, None);
+        }
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, k) = data.scan(cursor, 30, None);
+            // updated keys move to the end of the iteration order and are returned again
+            if cursor == 0 {
+                k.iter().for_each(|key| { data.set(key, key, None); });
+            }
+            keys.extend(k);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 1000);
+
+        let (next, k) = data.scan(0, 10000, Some(b"key1?"));
+        assert_eq!(next, 0);
+        assert_eq!(k.len(), 10);
+
+        assert_eq!(data.key_type(b"key1"), Some("string"));
+        assert_eq!(data.memory_usage(b"key1"), Some(32));
+        assert_eq!(data.key_type(b"key1000"), None);
     }
 }
@@ -1,17 +1,23 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::SystemTime;
-use crate::common_maps::GetResult::{Expired, Found, NotFound};
-use crate::resp_encoder::resp_encode_binary_string;
+use rand::Rng;
+use rand::seq::IteratorRandom;
+use crate::common_maps::EvictionPolicy::{AllKeysLru, AllKeysRandom, NoEviction, VolatileTtl};
+use crate::storage_backend::{EvictedKeys, GetResult, ShardStats, StorageBackend, StorageBackendFactory, UpdateFunction};
+use crate::storage_backend::GetResult::{Expired, Found, NotFound};
+use crate::values::ValueHolder;
+use crate::values::ValueHolder::CompressedValue;
 
 struct Value {
-    value: Vec<u8>,
+    value: ValueHolder,
     created_at: u64,
     expires_at: Option<u64>,
 }
 
 impl Value {
-    fn new(value: Vec<u8>, created_at: u64, expiration: Option<u64>) -> Value {
+    fn new(value: ValueHolder, created_at: u64, expiration: Option<u64>) -> Value {
         let expires_at = expiration.map(|e| created_at + e);
         Value {
             value,
@@ -22,100 +28,293 @@ impl Value {
 
     fn is_expired(&self, start_time: SystemTime) -> bool {
         if let Some(e) = self.expires_at {
-            let now = SystemTime::now().duration_since(start_time).unwrap().as_millis() as u64;
-            if now >= e {
+            if now(start_time) >= e {
                 return true;
             }
         }
         false
     }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EvictionPolicy {
+    // the oldest keys are removed first
+    AllKeysLru,
+    // keys with the nearest expiration time are removed first
+    VolatileTtl,
+    AllKeysRandom,
+    // writes are rejected when maximum memory is reached
+    NoEviction,
+}
+
+impl EvictionPolicy {
+    pub fn parse(name: &[u8]) -> Option<EvictionPolicy> {
+        match name.to_ascii_lowercase().as_slice() {
+            b"allkeys-lru" => Some(AllKeysLru),
+            b"volatile-ttl" => Some(VolatileTtl),
+            b"allkeys-random" => Some(AllKeysRandom),
+            b"noeviction" => Some(NoEviction),
+            _ => None
+        }
+    }
 
-    fn get_value(&self) -> &Vec<u8> {
-        &self.value
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            AllKeysLru => "allkeys-lru",
+            VolatileTtl => "volatile-ttl",
+            AllKeysRandom => "allkeys-random",
+            NoEviction => "noeviction"
+        }
     }
 }
 
-pub struct CommonMaps {
-    max_memory: usize,
+#[derive(Default)]
+struct LockStats {
+    reads: AtomicUsize,
+    writes: AtomicUsize,
+    // acquisitions that had to wait for another thread
+    contended: AtomicUsize,
+}
+
+// time indexes and statistics
+struct MapState {
     current_memory: usize,
-    map: HashMap<Vec<u8>, Value>,
+    // memory used by all maps
+    used_memory: Arc<AtomicUsize>,
+    compressed_values: usize,
+    compressed_original_size: usize,
+    compressed_size: usize,
+    expired_keys: usize,
+    evicted_keys: usize,
+    eviction_policy: EvictionPolicy,
+    // maximum number of keys removed by one evict call
+    eviction_batch: usize,
     map_by_time: BTreeMap<u64, HashSet<Vec<u8>>>,
     map_by_expiration: BTreeMap<u64, HashSet<Vec<u8>>>,
 }
 
-fn build_map(max_memory: usize) -> CommonMaps {
+// the key map and the state have separate locks: all modifications are serialized by the state lock
+// and take the map write lock only for the hash map update itself, so get never waits
+// for time index updates or eviction scans
+pub struct CommonMaps {
+    map: RwLock<HashMap<Vec<u8>, Value>>,
+    state: Mutex<MapState>,
+    lock_stats: LockStats,
+}
+
+fn build_map(used_memory: Arc<AtomicUsize>) -> CommonMaps {
     CommonMaps {
-        current_memory: 0,
-        max_memory,
-        map: HashMap::new(),
-        map_by_time: BTreeMap::new(),
-        map_by_expiration: BTreeMap::new(),
+        map: RwLock::new(HashMap::new()),
+        state: Mutex::new(MapState {
+            current_memory: 0,
+            used_memory,
+            compressed_values: 0,
+            compressed_original_size: 0,
+            compressed_size: 0,
+            expired_keys: 0,
+            evicted_keys: 0,
+            eviction_policy: AllKeysLru,
+            eviction_batch: 16,
+            map_by_time: BTreeMap::new(),
+            map_by_expiration: BTreeMap::new(),
+        }),
+        lock_stats: LockStats::default(),
     }
 }
 
-pub fn build_maps(vector_size: usize, all_memory: usize) -> Vec<RwLock<CommonMaps>> {
-    let max_memory = all_memory / vector_size;
-    (0..vector_size)
-        .map(|_i| RwLock::new(build_map(max_memory)))
-        .collect()
+pub fn common_maps_factory() -> StorageBackendFactory {
+    Arc::new(|used_memory| Box::new(build_map(used_memory)))
 }
 
-#[derive(PartialEq, Debug)]
-pub enum GetResult {
-    NotFound,
-    Found,
-    Expired,
-}
+// maps with a smaller capacity are never shrunk
+const MIN_SHRINK_CAPACITY: usize = 1024;
 
 fn calculate_record_size(key_size: usize, value_size: usize) -> usize {
     3 * key_size + value_size + 16
 }
 
-impl CommonMaps {
-    pub fn flush(&mut self) {
-        self.current_memory = 0;
-        self.map.clear();
+fn now(start_time: SystemTime) -> u64 {
+    SystemTime::now().duration_since(start_time).unwrap().as_millis() as u64
+}
+
+fn add_to_btree(map: &mut BTreeMap<u64, HashSet<Vec<u8>>>, time: u64, key: &[u8]) {
+    match map.get_mut(&time) {
+        Some(v) => { let _ = v.insert(key.to_vec()); }
+        None => {
+            let mut s = HashSet::new();
+            s.insert(key.to_vec());
+            map.insert(time, s);
+        }
+    };
+}
+
+fn remove_from_btree(map: &mut BTreeMap<u64, HashSet<Vec<u8>>>, time: u64, key: &[u8]) {
+    let h = map.get_mut(&time).unwrap();
+    if h.len() == 1 {
+        map.remove(&time);
+    } else {
+        h.remove(key);
+    }
+}
+
+impl MapState {
+    fn add_memory(&mut self, size: usize) {
+        self.current_memory += size;
+        self.used_memory.fetch_add(size, Ordering::Relaxed);
+    }
+
+    fn sub_memory(&mut self, size: usize) {
+        self.current_memory -= size;
+        self.used_memory.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    fn flush(&mut self) {
+        self.sub_memory(self.current_memory);
+        self.compressed_values = 0;
+        self.compressed_original_size = 0;
+        self.compressed_size = 0;
         self.map_by_expiration.clear();
         self.map_by_time.clear();
     }
 
-    fn remove_from_btree(&mut self, key: &Vec<u8>, value: Value) {
-        if let Some(ex) = value.expires_at {
-            let h = self.map_by_expiration.get_mut(&ex).unwrap();
-            if h.len() == 1 {
-                self.map_by_expiration.remove(&ex);
+    fn update_compression_stats(&mut self, value: &ValueHolder, added: bool) {
+        if let CompressedValue(v, original_size) = value {
+            if added {
+                self.compressed_values += 1;
+                self.compressed_original_size += original_size;
+                self.compressed_size += v.len();
             } else {
-                h.remove(key);
+                self.compressed_values -= 1;
+                self.compressed_original_size -= original_size;
+                self.compressed_size -= v.len();
             }
         }
-        let h = self.map_by_time.get_mut(&value.created_at).unwrap();
-        if h.len() == 1 {
-            self.map_by_time.remove(&value.created_at);
-        } else {
-            h.remove(key);
+    }
+
+    fn add_value(&mut self, key: &[u8], value: &Value) {
+        self.add_memory(calculate_record_size(key.len(), value.value.size()));
+        self.update_compression_stats(&value.value, true);
+    }
+
+    // should be called after the old value of the key is removed from the time indexes
+    fn add_to_indexes(&mut self, key: &[u8], created_at: u64, expires_at: Option<u64>) {
+        if let Some(ex) = expires_at {
+            add_to_btree(&mut self.map_by_expiration, ex, key);
         }
+        add_to_btree(&mut self.map_by_time, created_at, key);
     }
 
-    pub fn removekey(&mut self, key: &Vec<u8>) -> isize {
-        if let Some(value) = self.map.remove(key) {
-            self.current_memory -= calculate_record_size(key.len(), value.value.len());
-            self.remove_from_btree(key, value);
-            return 1;
+    // value is dropped after the map lock is released, returns the value holder
+    fn remove_value(&mut self, key: &[u8], value: Value) -> ValueHolder {
+        self.sub_memory(calculate_record_size(key.len(), value.value.size()));
+        self.update_compression_stats(&value.value, false);
+        if let Some(ex) = value.expires_at {
+            remove_from_btree(&mut self.map_by_expiration, ex, key);
         }
-        0
+        remove_from_btree(&mut self.map_by_time, value.created_at, key);
+        value.value
     }
+}
 
-    pub fn removekeys(&mut self, keys: Vec<&Vec<u8>>) -> isize {
-        keys.into_iter().map(|k| self.removekey(k)).sum()
+impl CommonMaps {
+    fn read_map(&self) -> RwLockReadGuard<'_, HashMap<Vec<u8>, Value>> {
+        self.lock_stats.reads.fetch_add(1, Ordering::Relaxed);
+        self.map.try_read().unwrap_or_else(|_e| {
+            self.lock_stats.contended.fetch_add(1, Ordering::Relaxed);
+            self.map.read().unwrap()
+        })
     }
 
-    pub fn get(&self, key: &Vec<u8>, result: &mut Vec<u8>, start_time: SystemTime) -> GetResult {
-        return match self.map.get(key) {
+    fn write_map(&self) -> RwLockWriteGuard<'_, HashMap<Vec<u8>, Value>> {
+        self.lock_stats.writes.fetch_add(1, Ordering::Relaxed);
+        self.map.try_write().unwrap_or_else(|_e| {
+            self.lock_stats.contended.fetch_add(1, Ordering::Relaxed);
+            self.map.write().unwrap()
+        })
+    }
+
+    #[cfg(test)]
+    pub fn removekey(&self, key: &Vec<u8>) -> usize {
+        self.removekeys(vec![key]).len()
+    }
+
+    #[cfg(test)]
+    pub fn set(&self, key: &Vec<u8>, value: &Vec<u8>, expiry: Option<u64>, start_time: SystemTime) {
+        self.set_value(key, ValueHolder::StringValue(value.clone()), expiry, start_time);
+    }
+
+    // memory used by this map only
+    fn memory(&self) -> usize {
+        self.state.lock().unwrap().current_memory
+    }
+}
+
+impl StorageBackend for CommonMaps {
+    fn flush(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.write_map().clear();
+        state.flush();
+    }
+
+    // keys are visited in the time index order by whole groups of keys with the same time, so the scan resumes
+    // without walking the visited keys again, a key changed during the scan moves to the end of the order
+    // and can be returned twice, cursor is the time of the next group
+    fn scan(&self, cursor: usize, count: usize, start_time: SystemTime, keys: &mut Vec<Vec<u8>>) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        let map = self.read_map();
+        let mut visited = 0;
+        for (time, group) in state.map_by_time.range(cursor as u64..) {
+            if visited >= count {
+                return (*time as usize, visited);
+            }
+            visited += group.len();
+            for key in group {
+                if map.get(key).is_some_and(|v| !v.is_expired(start_time)) {
+                    keys.push(key.clone());
+                }
+            }
+        }
+        (0, visited)
+    }
+
+    fn removekeys<'a>(&self, keys: Vec<&'a Vec<u8>>) -> Vec<&'a Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let removed: Vec<(&Vec<u8>, Value)> = {
+            let mut map = self.write_map();
+            keys.into_iter().filter_map(|k| map.remove(k).map(|v| (k, v))).collect()
+        };
+        removed.into_iter()
+            .map(|(k, v)| {
+                state.remove_value(k, v);
+                k
+            })
+            .collect()
+    }
+
+    fn remove_if_expired(&self, key: &[u8], start_time: SystemTime) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let removed = {
+            let mut map = self.write_map();
+            match map.get(key) {
+                Some(value) if value.is_expired(start_time) => map.remove(key),
+                _ => None
+            }
+        };
+        if let Some(value) = removed {
+            state.remove_value(key, value);
+            state.expired_keys += 1;
+            return true;
+        }
+        false
+    }
+
+    fn get(&self, key: &[u8], result: &mut Vec<u8>, start_time: SystemTime) -> GetResult {
+        return match self.read_map().get(key) {
             Some(value) => {
                 if value.is_expired(start_time) {
                     Expired
                 } else {
-                    resp_encode_binary_string(value.get_value(), result);
+                    value.value.encode(result);
                     Found
                 }
             }
@@ -123,84 +322,245 @@ impl CommonMaps {
         };
     }
 
-    fn remove_expired(&mut self, start_time: SystemTime) {
-        let now = SystemTime::now().duration_since(start_time).unwrap().as_millis() as u64;
-        let mut to_remove = Vec::new();
-        for (k, v) in &self.map_by_expiration {
-            let kk = *k;
-            if kk < now {
-                for k in v {
-                    to_remove.push(k.clone());
-                }
+    fn read_value(&self, key: &[u8], start_time: SystemTime, f: &mut dyn FnMut(&ValueHolder)) -> bool {
+        match self.read_map().get(key) {
+            Some(value) if !value.is_expired(start_time) => {
+                f(&value.value);
+                true
             }
+            _ => false
         }
-        for k in to_remove {
-            self.removekey(&k);
+    }
+
+    fn memory_usage(&self, key: &[u8], start_time: SystemTime) -> Option<usize> {
+        match self.read_map().get(key) {
+            Some(value) if !value.is_expired(start_time) => Some(calculate_record_size(key.len(), value.value.size())),
+            _ => None
         }
     }
 
-    fn cleanup(&mut self, start_time: SystemTime) {
-        if self.current_memory >= self.max_memory {
-            self.remove_expired(start_time);
-            while self.current_memory >= self.max_memory {
-                //remove by lru
-                let (_k, v) = self.map_by_time.first_key_value().unwrap();
-                v.clone().iter().for_each(|k| { let _ = self.removekey(k); });
+    // fragmented - less than a quarter of the capacity is used
+    fn is_fragmented(&self) -> bool {
+        let map = self.read_map();
+        map.capacity() > MIN_SHRINK_CAPACITY && map.len() * 4 < map.capacity()
+    }
+
+    // releases unused capacity of the map and the time indexes
+    fn shrink(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.write_map().shrink_to_fit();
+        state.map_by_time.values_mut().for_each(|s| s.shrink_to_fit());
+        state.map_by_expiration.values_mut().for_each(|s| s.shrink_to_fit());
+    }
+
+    fn set_eviction_parameters(&self, eviction_policy: EvictionPolicy, eviction_batch: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.eviction_policy = eviction_policy;
+        state.eviction_batch = eviction_batch;
+    }
+
+    fn eviction_score(&self) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+        match state.eviction_policy {
+            AllKeysLru => state.map_by_time.first_key_value().map(|(k, _v)| *k),
+            VolatileTtl => state.map_by_expiration.first_key_value().map(|(k, _v)| *k),
+            AllKeysRandom => if state.map_by_time.is_empty() { None } else { Some(rand::thread_rng().gen()) },
+            NoEviction => None
+        }
+    }
+
+    fn evict(&self, start_time: SystemTime) -> EvictedKeys {
+        let mut state = self.state.lock().unwrap();
+        let expired: Vec<Vec<u8>> = state.map_by_expiration.range(..now(start_time))
+            .flat_map(|(_k, v)| v.iter().cloned())
+            .collect();
+        let has_expired = !expired.is_empty();
+        let keys: Vec<Vec<u8>> = if has_expired {
+            expired
+        } else {
+            let batch = state.eviction_batch;
+            match state.eviction_policy {
+                AllKeysLru => state.map_by_time.values().flatten().take(batch).cloned().collect(),
+                VolatileTtl => state.map_by_expiration.values().flatten().take(batch).cloned().collect(),
+                AllKeysRandom => self.read_map().keys().choose_multiple(&mut rand::thread_rng(), batch).into_iter().cloned().collect(),
+                NoEviction => Vec::new()
             }
+        };
+        let removed: Vec<(Vec<u8>, Value)> = {
+            let mut map = self.write_map();
+            keys.into_iter().filter_map(|k| map.remove(&k).map(|v| (k, v))).collect()
+        };
+        if has_expired {
+            state.expired_keys += removed.len();
+        } else {
+            state.evicted_keys += removed.len();
         }
+        let keys = removed.into_iter()
+            .map(|(k, v)| {
+                state.remove_value(&k, v);
+                k
+            })
+            .collect();
+        EvictedKeys { keys, expired: has_expired }
     }
 
-    pub fn set(&mut self, key: &Vec<u8>, value: &Vec<u8>, expiry: Option<u64>, start_time: SystemTime) {
-        let size = calculate_record_size(key.len(), value.len());
-        self.current_memory += size;
-        self.cleanup(start_time);
-        let created_at = SystemTime::now().duration_since(start_time).unwrap().as_millis() as u64;
-        let v = Value::new(value.clone(), created_at, expiry);
-        let created_at = v.created_at;
-        let expires_at = v.expires_at;
-        if let Some(old) = self.map.insert(key.clone(), v) {
-            self.current_memory = self.current_memory - size + value.len() - old.value.len();
-            self.remove_from_btree(key, old);
+    fn set_value(&self, key: &[u8], value: ValueHolder, expiry: Option<u64>, start_time: SystemTime) {
+        let mut state = self.state.lock().unwrap();
+        let v = Value::new(value, now(start_time), expiry);
+        let (created_at, expires_at) = (v.created_at, v.expires_at);
+        state.add_value(key, &v);
+        let old = self.write_map().insert(key.to_vec(), v);
+        if let Some(old) = old {
+            state.remove_value(key, old);
         }
-        if let Some(ex) = expires_at {
-            match self.map_by_expiration.get_mut(&ex) {
-                Some(v) => { let _ = v.insert(key.clone()); }
-                None => {
-                    let mut s = HashSet::new();
-                    s.insert(key.clone());
-                    self.map_by_expiration.insert(ex, s);
+        state.add_to_indexes(key, created_at, expires_at);
+    }
+
+    fn set_values(&self, values: Vec<(&Vec<u8>, ValueHolder)>, expiry: Option<u64>, start_time: SystemTime) {
+        let mut state = self.state.lock().unwrap();
+        let created_at = now(start_time);
+        let mut old = Vec::new();
+        {
+            let mut map = self.write_map();
+            // the same key can be set more than once, so the indexes are updated key by key
+            for (key, value) in values {
+                let v = Value::new(value, created_at, expiry);
+                let expires_at = v.expires_at;
+                state.add_value(key, &v);
+                if let Some(o) = map.insert(key.clone(), v) {
+                    old.push(state.remove_value(key, o));
                 }
-            };
+                state.add_to_indexes(key, created_at, expires_at);
+            }
         }
-        match self.map_by_time.get_mut(&created_at) {
-            Some(v) => { let _ = v.insert(key.clone()); }
-            None => {
-                let mut s = HashSet::new();
-                s.insert(key.clone());
-                self.map_by_time.insert(created_at, s);
+    }
+
+    fn update(&self, key: &[u8], start_time: SystemTime, f: UpdateFunction<'_>) -> Result<bool, &'static str> {
+        let mut state = self.state.lock().unwrap();
+        let mut map = self.write_map();
+        let now = now(start_time);
+        // an expired record is removed only when f succeeds, created_at and expires_at of a live record
+        // are restored when f fails
+        let mut expired = None;
+        let mut original = None;
+        let mut holder = match map.remove(key) {
+            Some(value) if value.is_expired(start_time) => {
+                expired = Some(value);
+                None
             }
+            Some(value) => {
+                original = Some((value.created_at, value.expires_at));
+                Some(state.remove_value(key, value))
+            }
+            None => None
         };
+        let mut ttl = original.and_then(|(_created_at, expires_at)| expires_at).map(|e| e.saturating_sub(now));
+        if let Err(e) = f(&mut holder, &mut ttl) {
+            if let Some(v) = expired {
+                map.insert(key.to_vec(), v);
+            } else if let (Some(holder), Some((created_at, expires_at))) = (holder, original) {
+                let v = Value { value: holder, created_at, expires_at };
+                state.add_value(key, &v);
+                state.add_to_indexes(key, created_at, expires_at);
+                map.insert(key.to_vec(), v);
+            }
+            return Err(e);
+        }
+        let removed_expired = expired.is_some();
+        if let Some(v) = expired {
+            state.remove_value(key, v);
+            state.expired_keys += 1;
+        }
+        if let Some(holder) = holder {
+            let v = Value::new(holder, now, ttl);
+            state.add_value(key, &v);
+            state.add_to_indexes(key, v.created_at, v.expires_at);
+            map.insert(key.to_vec(), v);
+        }
+        Ok(removed_expired)
     }
 
-    pub fn size(&self) -> usize {
-        self.map.len()
+    fn set_expiry(&self, key: &[u8], expiry: Option<u64>, start_time: SystemTime) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let old = {
+            let mut map = self.write_map();
+            match map.get_mut(key) {
+                Some(value) if !value.is_expired(start_time) => {
+                    let old = value.expires_at;
+                    value.expires_at = expiry.map(|e| now(start_time) + e);
+                    Some((old, value.expires_at))
+                }
+                _ => None
+            }
+        };
+        match old {
+            Some((old, new)) => {
+                if let Some(ex) = old {
+                    remove_from_btree(&mut state.map_by_expiration, ex, key);
+                }
+                if let Some(ex) = new {
+                    add_to_btree(&mut state.map_by_expiration, ex, key);
+                }
+                true
+            }
+            None => false
+        }
+    }
+
+    fn ttl(&self, key: &[u8], start_time: SystemTime) -> Option<Option<u64>> {
+        match self.read_map().get(key) {
+            Some(value) if !value.is_expired(start_time) => Some(value.expires_at.map(|e| e.saturating_sub(now(start_time)))),
+            _ => None
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.read_map().len()
+    }
+
+    fn removal_stats(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.expired_keys, state.evicted_keys)
+    }
+
+    fn compression_stats(&self) -> (usize, usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.compressed_values, state.compressed_original_size, state.compressed_size)
+    }
+
+    // the map lock is taken directly, so that collecting statistics does not change lock counters
+    fn shard_stats(&self) -> ShardStats {
+        let expires = self.state.lock().unwrap().map_by_expiration.values().map(|keys| keys.len()).sum();
+        ShardStats {
+            keys: self.map.read().unwrap().len(),
+            expires,
+            memory: self.memory(),
+            reads: self.lock_stats.reads.load(Ordering::Relaxed),
+            writes: self.lock_stats.writes.load(Ordering::Relaxed),
+            contended: self.lock_stats.contended.load(Ordering::Relaxed),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
     use std::thread;
     use std::time::{Duration, SystemTime};
     use rand::distributions::{Alphanumeric, DistString};
     use rand::Rng;
-    use crate::common_maps::build_map;
-    use crate::common_maps::GetResult::{Expired, Found, NotFound};
+    use crate::common_maps::{build_map, calculate_record_size, EvictionPolicy};
+    use crate::storage_backend::StorageBackend;
+    use crate::storage_backend::GetResult::{Expired, Found, NotFound};
+    use crate::values::ValueHolder;
 
     #[test]
     fn test_set_delete() {
         let mut rng = rand::thread_rng();
         let mut keys = Vec::new();
-        let mut maps = build_map(100000000);
+        let maps = build_map(Arc::new(AtomicUsize::new(0)));
         let start_time = SystemTime::now();
         for _i in 0..1000 {
             let key_length = (rng.gen::<usize>() % 100) + 10;
@@ -215,61 +575,62 @@ mod tests {
             let value_length = (rng.gen::<usize>() % 200) + 10;
             let value = Alphanumeric.sample_string(&mut rng, value_length).into_bytes();
             maps.set(key, &value, None, start_time);
+            maps.set(key, &value, Some(1000), start_time);
         }
 
         for key in keys {
             maps.removekey(&key);
         }
 
-        assert_eq!(maps.map.len(), 0);
-        assert_eq!(maps.map_by_time.len(), 0);
-        assert_eq!(maps.map_by_expiration.len(), 0);
-        assert_eq!(maps.current_memory, 0);
+        assert_eq!(maps.map.read().unwrap().len(), 0);
+        let state = maps.state.lock().unwrap();
+        assert_eq!(state.map_by_time.len(), 0);
+        assert_eq!(state.map_by_expiration.len(), 0);
+        assert_eq!(state.current_memory, 0);
     }
 
     #[test]
-    fn test_cleanup() {
-        let mut rng = rand::thread_rng();
-        let mut maps = build_map(100000);
+    fn test_random_eviction() {
+        let maps = build_map(Arc::new(AtomicUsize::new(0)));
         let start_time = SystemTime::now();
-        for _i in 0..1000 {
-            let key_length = (rng.gen::<usize>() % 100) + 10;
-            let value_length = (rng.gen::<usize>() % 200) + 10;
-            let key = Alphanumeric.sample_string(&mut rng, key_length).into_bytes();
-            let value = Alphanumeric.sample_string(&mut rng, value_length).into_bytes();
-            maps.set(&key, &value, None, start_time);
+        maps.set_eviction_parameters(EvictionPolicy::AllKeysRandom, 1);
+        for i in 0..100 {
+            let key = i.to_string().into_bytes();
+            maps.set(&key, &key, None, start_time);
         }
-
-        assert!(maps.current_memory - 1000 < maps.max_memory);
+        let mut evicted = HashSet::new();
+        for _i in 0..20 {
+            let keys = maps.evict(start_time).keys;
+            assert_eq!(keys.len(), 1);
+            maps.set(&keys[0], &keys[0], None, start_time);
+            evicted.insert(keys[0].clone());
+        }
+        assert!(evicted.len() > 1);
+        assert_eq!(maps.size(), 100);
     }
 
     #[test]
-    fn test_cleanup2() {
-        let mut rng = rand::thread_rng();
-        let mut maps = build_map(100000);
+    fn test_shrink() {
+        let maps = build_map(Arc::new(AtomicUsize::new(0)));
         let start_time = SystemTime::now();
-        for _i in 0..1000 {
-            let key_length = (rng.gen::<usize>() % 100) + 10;
-            let value_length = (rng.gen::<usize>() % 200) + 10;
-            let key = Alphanumeric.sample_string(&mut rng, key_length).into_bytes();
-            let value = Alphanumeric.sample_string(&mut rng, value_length).into_bytes();
-            maps.set(&key, &value, Some(100), start_time);
+        let keys: Vec<Vec<u8>> = (0..10000).map(|i| i.to_string().into_bytes()).collect();
+        for key in &keys {
+            maps.set(key, key, None, start_time);
         }
-
-        thread::sleep(Duration::from_millis(200));
-
-        let key_length = (rng.gen::<usize>() % 100) + 10;
-        let key = Alphanumeric.sample_string(&mut rng, key_length).into_bytes();
-        let value = Alphanumeric.sample_string(&mut rng, 20000).into_bytes();
-        maps.set(&key, &value, None, start_time);
-
-        assert_eq!(maps.size(), 1);
+        assert!(!maps.is_fragmented());
+        for key in &keys[100..] {
+            maps.removekey(key);
+        }
+        assert!(maps.is_fragmented());
+        maps.shrink();
+        assert!(!maps.is_fragmented());
+        assert_eq!(maps.size(), 100);
     }
 
     #[test]
     fn test_set_get() {
         let mut rng = rand::thread_rng();
-        let mut maps = build_map(1000000);
+        let maps = build_map(Arc::new(AtomicUsize::new(0)));
         let start_time = SystemTime::now();
         let key_length = (rng.gen::<usize>() % 100) + 10;
         let value_length = (rng.gen::<usize>() % 200) + 10;
@@ -287,4 +648,94 @@ mod tests {
 
         assert_eq!(maps.get(&key, &mut result, start_time), Expired);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_set_expiry() {
+        let maps = build_map(Arc::new(AtomicUsize::new(0)));
+        let start_time = SystemTime::now();
+        let key = "key".to_string().into_bytes();
+        assert!(!maps.set_expiry(&key, Some(100), start_time));
+        assert_eq!(maps.ttl(&key, start_time), None);
+        maps.set(&key, &key, None, start_time);
+        assert_eq!(maps.ttl(&key, start_time), Some(None));
+        assert!(maps.set_expiry(&key, Some(100000), start_time));
+        assert!(matches!(maps.ttl(&key, start_time), Some(Some(t)) if t > 99000 && t <= 100000));
+        assert!(maps.set_expiry(&key, None, start_time));
+        assert_eq!(maps.ttl(&key, start_time), Some(None));
+        assert!(maps.state.lock().unwrap().map_by_expiration.is_empty());
+        assert!(maps.set_expiry(&key, Some(100000), start_time));
+        maps.update(&key, start_time, &mut |_v, ttl| {
+            *ttl = None;
+            Ok(())
+        }).unwrap();
+        assert_eq!(maps.ttl(&key, start_time), Some(None));
+        assert!(maps.state.lock().unwrap().map_by_expiration.is_empty());
+        assert!(maps.set_expiry(&key, Some(50), start_time));
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(maps.ttl(&key, start_time), None);
+        assert_eq!(maps.evict(start_time).keys, vec![key]);
+    }
+
+    #[test]
+    fn test_update() {
+        let maps = build_map(Arc::new(AtomicUsize::new(0)));
+        let start_time = SystemTime::now();
+        let key = "key".to_string().into_bytes();
+        maps.set(&key, &"1234567890".to_string().into_bytes(), Some(100000), start_time);
+        maps.update(&key, start_time, &mut |v, ttl| {
+            assert!(matches!(ttl, Some(t) if *t > 99000));
+            *v = Some(ValueHolder::FloatValue(1.5));
+            Ok(())
+        }).unwrap();
+        assert_eq!(maps.memory(), calculate_record_size(key.len(), 8));
+        assert!(matches!(maps.ttl(&key, start_time), Some(Some(_))));
+        assert_eq!(maps.update(&key, start_time, &mut |_v, _ttl| Err("error")), Err("error"));
+        assert_eq!(maps.size(), 1);
+        maps.update(&key, start_time, &mut |v, _ttl| {
+            *v = None;
+            Ok(())
+        }).unwrap();
+        assert_eq!(maps.size(), 0);
+        assert_eq!(maps.memory(), 0);
+        {
+            let state = maps.state.lock().unwrap();
+            assert!(state.map_by_expiration.is_empty() && state.map_by_time.is_empty());
+        }
+
+        // update moves the key to the end of the LRU order
+        let key2 = "key2".to_string().into_bytes();
+        maps.set_eviction_parameters(EvictionPolicy::AllKeysLru, 1);
+        maps.set(&key, &key, None, start_time);
+        thread::sleep(Duration::from_millis(5));
+        maps.set(&key2, &key2, None, start_time);
+        thread::sleep(Duration::from_millis(5));
+        maps.update(&key, start_time, &mut |_v, _ttl| Ok(())).unwrap();
+        assert_eq!(maps.evict(start_time).keys, vec![key2.clone()]);
+
+        // a failed update keeps the LRU position and an expired key
+        thread::sleep(Duration::from_millis(5));
+        maps.set(&key2, &key2, None, start_time);
+        thread::sleep(Duration::from_millis(5));
+        maps.update(&key, start_time, &mut |_v, _ttl| Err("error")).unwrap_err();
+        assert_eq!(maps.evict(start_time).keys, vec![key.clone()]);
+        maps.set(&key, &key, Some(1), start_time);
+        thread::sleep(Duration::from_millis(5));
+        maps.update(&key, start_time, &mut |_v, _ttl| Err("error")).unwrap_err();
+        assert_eq!((maps.size(), maps.removal_stats().0), (2, 0));
+        assert_eq!(maps.update(&key, start_time, &mut |_v, _ttl| Ok(())), Ok(true));
+        assert_eq!((maps.size(), maps.removal_stats().0), (1, 1));
+    }
+
+    #[test]
+    fn test_get_while_state_locked() {
+        let maps = build_map(Arc::new(AtomicUsize::new(0)));
+        let start_time = SystemTime::now();
+        let key = "key".to_string().into_bytes();
+        maps.set(&key, &key, None, start_time);
+        // get takes only the map read lock
+        let _state = maps.state.lock().unwrap();
+        let mut result = Vec::new();
+        assert_eq!(maps.get(&key, &mut result, start_time), Found);
+        assert_eq!(result, "$3\r\nkey\r\n".to_string().into_bytes());
+    }
+}
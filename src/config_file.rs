@@ -0,0 +1,91 @@
+use std::fs::{read_to_string, rename, write};
+
+// line number, name, value
+pub type ConfigSetting = (usize, Vec<u8>, Vec<u8>);
+
+// redis.conf style file: one "name value" pair per line, # starts a comment line,
+// the value can be enclosed in double quotes
+pub fn read_config_file(file_name: &str) -> Result<Vec<ConfigSetting>, String> {
+    let text = read_to_string(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+    parse_config(&text)
+}
+
+// CONFIG REWRITE: the file gets the current setting values, comments and other lines are kept,
+// the first line of a setting is updated and its repeated lines are removed, missing settings are appended,
+// the new text is written to a temporary file that replaces the old one, so the file is never left half written
+pub fn rewrite_config_file(file_name: &str, settings: &[(&str, String)]) -> Result<(), String> {
+    let text = read_to_string(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+    let temp_file_name = format!("{}.tmp", file_name);
+    write(&temp_file_name, rewrite_config(&text, settings))
+        .and_then(|_| rename(&temp_file_name, file_name))
+        .map_err(|e| format!("{}: {}", file_name, e))
+}
+
+fn format_setting(name: &str, value: &str) -> String {
+    if value.is_empty() || value.contains(char::is_whitespace) {
+        format!("{} \"{}\"", name, value)
+    } else {
+        format!("{} {}", name, value)
+    }
+}
+
+fn rewrite_config(text: &str, settings: &[(&str, String)]) -> String {
+    let mut written = vec![false; settings.len()];
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let name = line.split_whitespace().next().unwrap_or("");
+        match settings.iter().position(|(n, _v)| n.eq_ignore_ascii_case(name)) {
+            Some(idx) => if !written[idx] {
+                lines.push(format_setting(settings[idx].0, &settings[idx].1));
+                written[idx] = true;
+            },
+            None => lines.push(line.to_string())
+        }
+    }
+    settings.iter().zip(written)
+        .filter(|(_s, w)| !w)
+        .for_each(|((name, value), _w)| lines.push(format_setting(name, value)));
+    lines.iter().map(|l| format!("{}\n", l)).collect()
+}
+
+fn parse_config(text: &str) -> Result<Vec<ConfigSetting>, String> {
+    let mut result = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = match line.split_once(char::is_whitespace) {
+            Some((name, value)) => (name, value.trim()),
+            None => return Err(format!("line {}: missing value for {}", idx + 1, line))
+        };
+        let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+        result.push((idx + 1, name.as_bytes().to_vec(), value.as_bytes().to_vec()));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config_file::{parse_config, rewrite_config};
+
+    #[test]
+    fn test_parse_config() {
+        let settings = parse_config("# memory\nmaxmemory 100m\n\n  maxmemory-policy   volatile-lru  \nsave \"\"\n").unwrap();
+        assert_eq!(settings, vec![
+            (2, b"maxmemory".to_vec(), b"100m".to_vec()),
+            (4, b"maxmemory-policy".to_vec(), b"volatile-lru".to_vec()),
+            (5, b"save".to_vec(), Vec::new()),
+        ]);
+        assert_eq!(parse_config("maxmemory 1\nloglevel\n"), Err("line 2: missing value for loglevel".to_string()));
+    }
+
+    #[test]
+    fn test_rewrite_config() {
+        let settings = [("maxmemory", "1024".to_string()), ("maxmemory-policy", "noeviction".to_string()),
+            ("loglevel", String::new())];
+        let text = rewrite_config("# memory\nMAXMEMORY 100m\nport 6379\nmaxmemory 200m\n", &settings);
+        assert_eq!(text, "# memory\nmaxmemory 1024\nport 6379\nmaxmemory-policy noeviction\nloglevel \"\"\n");
+        assert_eq!(parse_config(&text).unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::Write;
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// client connection in CommonData::threads: the write handle and CLIENT LIST metadata
+pub struct Connection {
+    // replies and pub/sub messages are written through it, the worker reads from its own handle
+    pub stream: Arc<Mutex<TcpStream>>,
+    address: String,
+    created: Instant,
+    name: Mutex<Vec<u8>>,
+    // the last known command and the time it was started
+    last_command: Mutex<(Option<&'static str>, Instant)>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Connection {
+        let address = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+        let now = Instant::now();
+        Connection {
+            stream: Arc::new(Mutex::new(stream)),
+            address,
+            created: now,
+            name: Mutex::new(Vec::new()),
+            last_command: Mutex::new((None, now)),
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    // empty - the connection has no name
+    pub fn name(&self) -> Vec<u8> {
+        self.name.lock().unwrap().clone()
+    }
+
+    pub fn set_name(&self, name: &[u8]) {
+        *self.name.lock().unwrap() = name.to_vec();
+    }
+
+    pub fn command_started(&self, command: &'static str) {
+        *self.last_command.lock().unwrap() = (Some(command), Instant::now());
+    }
+
+    pub fn write(&self, data: &[u8]) -> std::io::Result<()> {
+        self.stream.lock().unwrap().write_all(data)
+    }
+
+    // the worker thread sees the end of the stream and removes the connection
+    pub fn shutdown(&self, how: Shutdown) {
+        let _ = self.stream.lock().unwrap().shutdown(how);
+    }
+
+    // CLIENT LIST line without the line end, sub and psub - numbers of subscribed channels and patterns
+    pub fn info(&self, id: usize, sub: usize, psub: usize) -> String {
+        let (command, last) = *self.last_command.lock().unwrap();
+        format!("id={} addr={} name={} age={} idle={} db=0 sub={} psub={} cmd={}", id, self.address,
+                String::from_utf8_lossy(&self.name()), self.created.elapsed().as_secs(), last.elapsed().as_secs(),
+                sub, psub, command.unwrap_or("NULL"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, TcpStream};
+    use crate::connections::Connection;
+
+    #[test]
+    fn test_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let local = stream.local_addr().unwrap().to_string();
+        let (accepted, address) = listener.accept().unwrap();
+        let connection = Connection::new(accepted);
+        assert_eq!(connection.address(), address.to_string());
+        assert_eq!(connection.address(), local);
+        assert_eq!(connection.info(3, 0, 1), format!("id=3 addr={} name= age=0 idle=0 db=0 sub=0 psub=1 cmd=NULL", local));
+        connection.set_name(b"worker");
+        connection.command_started("get");
+        assert_eq!(connection.name(), b"worker");
+        assert_eq!(connection.info(3, 0, 0), format!("id=3 addr={} name=worker age=0 idle=0 db=0 sub=0 psub=0 cmd=get", local));
+    }
+}
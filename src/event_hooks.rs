@@ -0,0 +1,53 @@
+use std::sync::RwLock;
+
+type SetHook = Box<dyn Fn(&[u8], &[u8]) + Send + Sync>;
+type KeyHook = Box<dyn Fn(&[u8]) + Send + Sync>;
+
+// callbacks registered by host applications, they are called in the thread that made the change
+// after the key map locks are released, so they can access the cache, but should not register new callbacks
+#[derive(Default)]
+pub struct EventHooks {
+    on_set: RwLock<Vec<SetHook>>,
+    on_delete: RwLock<Vec<KeyHook>>,
+    on_expire: RwLock<Vec<KeyHook>>,
+    on_evict: RwLock<Vec<KeyHook>>,
+}
+
+impl EventHooks {
+    pub fn add_set_hook(&self, hook: SetHook) {
+        self.on_set.write().unwrap().push(hook);
+    }
+
+    pub fn add_delete_hook(&self, hook: KeyHook) {
+        self.on_delete.write().unwrap().push(hook);
+    }
+
+    pub fn add_expire_hook(&self, hook: KeyHook) {
+        self.on_expire.write().unwrap().push(hook);
+    }
+
+    pub fn add_evict_hook(&self, hook: KeyHook) {
+        self.on_evict.write().unwrap().push(hook);
+    }
+
+    // the new value is not always available as bytes, so it is built only when there are set hooks
+    pub fn has_set_hooks(&self) -> bool {
+        !self.on_set.read().unwrap().is_empty()
+    }
+
+    pub fn set(&self, key: &[u8], value: &[u8]) {
+        self.on_set.read().unwrap().iter().for_each(|h| h(key, value));
+    }
+
+    pub fn delete(&self, key: &[u8]) {
+        self.on_delete.read().unwrap().iter().for_each(|h| h(key));
+    }
+
+    pub fn expire(&self, key: &[u8]) {
+        self.on_expire.read().unwrap().iter().for_each(|h| h(key));
+    }
+
+    pub fn evict(&self, key: &[u8]) {
+        self.on_evict.read().unwrap().iter().for_each(|h| h(key));
+    }
+}
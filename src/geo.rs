@@ -0,0 +1,141 @@
+use crate::sorted_set::SortedSet;
+
+// the same limits, precision and earth model as in Redis, so scores and distances match
+const LONGITUDE_MIN: f64 = -180.0;
+const LONGITUDE_MAX: f64 = 180.0;
+const LATITUDE_MIN: f64 = -85.05112878;
+const LATITUDE_MAX: f64 = 85.05112878;
+// bits per coordinate, the score keeps 52 bits, so it is exact in f64
+const STEP: u32 = 26;
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+
+// GEOSEARCH area, sizes are in meters
+#[derive(Clone, Copy)]
+pub enum GeoShape {
+    Radius(f64),
+    Box(f64, f64),
+}
+
+pub fn valid_coordinates(longitude: f64, latitude: f64) -> bool {
+    (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&longitude) && (LATITUDE_MIN..=LATITUDE_MAX).contains(&latitude)
+}
+
+// meters in one unit: m, km, mi, ft
+pub fn unit_to_meters(unit: &[u8]) -> Option<f64> {
+    match unit.to_ascii_lowercase().as_slice() {
+        b"m" => Some(1.0),
+        b"km" => Some(1000.0),
+        b"mi" => Some(1609.34),
+        b"ft" => Some(0.3048),
+        _ => None
+    }
+}
+
+// spreads the 32 bits of x to the even bit positions
+fn spread(x: u32) -> u64 {
+    let mut x = x as u64;
+    x = (x | (x << 16)) & 0x0000ffff0000ffff;
+    x = (x | (x << 8)) & 0x00ff00ff00ff00ff;
+    x = (x | (x << 4)) & 0x0f0f0f0f0f0f0f0f;
+    x = (x | (x << 2)) & 0x3333333333333333;
+    (x | (x << 1)) & 0x5555555555555555
+}
+
+fn squash(x: u64) -> u32 {
+    let mut x = x & 0x5555555555555555;
+    x = (x | (x >> 1)) & 0x3333333333333333;
+    x = (x | (x >> 2)) & 0x0f0f0f0f0f0f0f0f;
+    x = (x | (x >> 4)) & 0x00ff00ff00ff00ff;
+    x = (x | (x >> 8)) & 0x0000ffff0000ffff;
+    ((x | (x >> 16)) & 0x00000000ffffffff) as u32
+}
+
+// 52 bit geohash, latitude bits are at the even positions and longitude bits at the odd ones
+pub fn encode(longitude: f64, latitude: f64) -> u64 {
+    let cells = (1u64 << STEP) as f64;
+    let lat = ((latitude - LATITUDE_MIN) / (LATITUDE_MAX - LATITUDE_MIN) * cells) as u32;
+    let lon = ((longitude - LONGITUDE_MIN) / (LONGITUDE_MAX - LONGITUDE_MIN) * cells) as u32;
+    // the maximum coordinate belongs to the last cell
+    let last = (1u32 << STEP) - 1;
+    spread(lat.min(last)) | (spread(lon.min(last)) << 1)
+}
+
+// center of the geohash cell as (longitude, latitude)
+pub fn decode(hash: u64) -> (f64, f64) {
+    let cells = (1u64 << STEP) as f64;
+    let lat = squash(hash) as f64;
+    let lon = squash(hash >> 1) as f64;
+    let latitude = LATITUDE_MIN + (lat + 0.5) / cells * (LATITUDE_MAX - LATITUDE_MIN);
+    let longitude = LONGITUDE_MIN + (lon + 0.5) / cells * (LONGITUDE_MAX - LONGITUDE_MIN);
+    (longitude.clamp(LONGITUDE_MIN, LONGITUDE_MAX), latitude.clamp(LATITUDE_MIN, LATITUDE_MAX))
+}
+
+// haversine distance in meters
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (lat1r, lat2r) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2r - lat1r) / 2.0).sin();
+    let v = ((lon2 - lon1).to_radians() / 2.0).sin();
+    2.0 * EARTH_RADIUS_IN_METERS * (u * u + lat1r.cos() * lat2r.cos() * v * v).sqrt().asin()
+}
+
+// distance from the center when the point is inside the shape
+fn distance_in_shape(center: (f64, f64), point: (f64, f64), shape: GeoShape) -> Option<f64> {
+    let d = distance(center.0, center.1, point.0, point.1);
+    match shape {
+        GeoShape::Radius(radius) => if d <= radius { Some(d) } else { None },
+        GeoShape::Box(width, height) => {
+            let lat_distance = EARTH_RADIUS_IN_METERS * (point.1.to_radians() - center.1.to_radians()).abs();
+            let lon_distance = distance(center.0, point.1, point.0, point.1);
+            if lat_distance > height / 2.0 || lon_distance > width / 2.0 { None } else { Some(d) }
+        }
+    }
+}
+
+// members inside the shape with their distance from the center in meters, every member is checked,
+// results are in score order
+pub fn search(set: &SortedSet, center: (f64, f64), shape: GeoShape) -> Vec<(&Vec<u8>, f64, u64)> {
+    set.iter()
+        .filter_map(|(member, score)| {
+            let hash = score as u64;
+            distance_in_shape(center, decode(hash), shape).map(|d| (member, d, hash))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::{decode, distance, encode, search, unit_to_meters, valid_coordinates, GeoShape};
+    use crate::sorted_set::SortedSet;
+
+    #[test]
+    fn test_geo() {
+        assert!(valid_coordinates(13.361389, 38.115556));
+        assert!(!valid_coordinates(13.361389, 86.0));
+        assert!(!valid_coordinates(-181.0, 0.0));
+        assert_eq!(unit_to_meters(b"KM"), Some(1000.0));
+        assert_eq!(unit_to_meters(b"yd"), None);
+
+        // GEOADD Sicily 13.361389 38.115556 Palermo gives this score in Redis
+        let palermo = encode(13.361389, 38.115556);
+        assert_eq!(palermo, 3479099956230698);
+        let (lon, lat) = decode(palermo);
+        assert!((lon - 13.361389).abs() < 0.00001 && (lat - 38.115556).abs() < 0.00001);
+        let catania = encode(15.087269, 37.502669);
+        assert_eq!(catania, 3479447370796909);
+
+        let (lon2, lat2) = decode(catania);
+        let d = distance(lon, lat, lon2, lat2);
+        assert!((d - 166274.1516).abs() < 0.01, "{}", d);
+
+        let mut set = SortedSet::new();
+        set.insert(b"Palermo", palermo as f64);
+        set.insert(b"Catania", catania as f64);
+        let found = search(&set, (15.0, 37.0), GeoShape::Radius(100000.0));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, b"Catania");
+        assert!((found[0].1 - 56441.2).abs() < 1.0, "{}", found[0].1);
+        assert_eq!(search(&set, (15.0, 37.0), GeoShape::Radius(200000.0)).len(), 2);
+        assert_eq!(search(&set, (15.0, 37.0), GeoShape::Box(400000.0, 400000.0)).len(), 2);
+        assert_eq!(search(&set, (15.0, 37.0), GeoShape::Box(100000.0, 400000.0)).len(), 1);
+    }
+}
@@ -0,0 +1,116 @@
+// Redis style glob matching: * - any sequence, ? - any character, [abc], [^abc], [a-z] - character classes,
+// \ - escapes the next character
+pub fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    let mut p = 0;
+    let mut i = 0;
+    // position after the last * and the string position it is matched to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while i < s.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    p += 1;
+                    backtrack = Some((p, i));
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    i += 1;
+                    continue;
+                }
+                b'[' => {
+                    if let Some((matched, next)) = match_class(pattern, p + 1, s[i]) {
+                        if matched {
+                            p = next;
+                            i += 1;
+                            continue;
+                        }
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == s[i] {
+                        p += 2;
+                        i += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == s[i] {
+                        p += 1;
+                        i += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        match backtrack {
+            // * takes one more character
+            Some((bp, bi)) => {
+                backtrack = Some((bp, bi + 1));
+                p = bp;
+                i = bi + 1;
+            }
+            None => return false
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+// returns whether c matches the class starting at idx (after [) and the position after the class,
+// None - the class is not terminated
+fn match_class(pattern: &[u8], mut idx: usize, c: u8) -> Option<(bool, usize)> {
+    let negate = idx < pattern.len() && pattern[idx] == b'^';
+    if negate {
+        idx += 1;
+    }
+    let mut matched = false;
+    loop {
+        if idx >= pattern.len() {
+            return None;
+        }
+        match pattern[idx] {
+            b']' => break,
+            b'\\' if idx + 1 < pattern.len() => {
+                matched |= pattern[idx + 1] == c;
+                idx += 2;
+            }
+            start if idx + 2 < pattern.len() && pattern[idx + 1] == b'-' && pattern[idx + 2] != b']' => {
+                let end = pattern[idx + 2];
+                let (low, high) = if start <= end { (start, end) } else { (end, start) };
+                matched |= c >= low && c <= high;
+                idx += 3;
+            }
+            v => {
+                matched |= v == c;
+                idx += 1;
+            }
+        }
+    }
+    Some((matched != negate, idx + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glob::glob_match;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+        assert!(glob_match(b"h*llo", b"heeeello"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[ae]llo", b"hillo"));
+        assert!(glob_match(b"h[^e]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(!glob_match(b"h[a-b]llo", b"hcllo"));
+        assert!(glob_match(b"user:*:name", b"user:12:34:name"));
+        assert!(!glob_match(b"user:*:name", b"user:12:names"));
+        assert!(glob_match(b"a\\*b", b"a*b"));
+        assert!(!glob_match(b"a\\*b", b"axb"));
+        assert!(glob_match(b"*a*b*c*", b"xxaxxbxxcxx"));
+        assert!(!glob_match(b"key[", b"key["));
+    }
+}
@@ -1,7 +1,8 @@
 use std::io::{Error, ErrorKind};
+use rand::Rng;
 
 pub trait HashBuilder {
-    fn build_hash(&self, key: &Vec<u8>) -> usize;
+    fn build_hash(&self, key: &[u8]) -> usize;
     fn get_name(&self) -> &'static str;
 }
 
@@ -16,7 +17,7 @@ impl DJB2HashBuilder {
 }
 
 impl HashBuilder for DJB2HashBuilder {
-    fn build_hash(&self, key: &Vec<u8>) -> usize {
+    fn build_hash(&self, key: &[u8]) -> usize {
         let mut hash = 5381;
 
         for c in key {
@@ -42,7 +43,7 @@ impl SDBMHashBuilder {
 }
 
 impl HashBuilder for SDBMHashBuilder {
-    fn build_hash(&self, key: &Vec<u8>) -> usize {
+    fn build_hash(&self, key: &[u8]) -> usize {
         let mut hash = 0;
 
         for c in key {
@@ -68,7 +69,7 @@ impl SumHashBuilder {
 }
 
 impl HashBuilder for SumHashBuilder {
-    fn build_hash(&self, key: &Vec<u8>) -> usize {
+    fn build_hash(&self, key: &[u8]) -> usize {
         let hash_sum: usize = key.iter().map(|i| *i as usize).sum();
         hash_sum % self.max_value
     }
@@ -89,7 +90,7 @@ impl XorHashBuilder {
 }
 
 impl HashBuilder for XorHashBuilder {
-    fn build_hash(&self, key: &Vec<u8>) -> usize {
+    fn build_hash(&self, key: &[u8]) -> usize {
         let hash_sum: u8 = key.iter().fold(0, |sum, v|sum ^ *v);
         (hash_sum % self.max_value) as usize
     }
@@ -108,7 +109,7 @@ impl XorHashBuilder256 {
 }
 
 impl HashBuilder for XorHashBuilder256 {
-    fn build_hash(&self, key: &Vec<u8>) -> usize {
+    fn build_hash(&self, key: &[u8]) -> usize {
         let hash_sum: u8 = key.iter().fold(0, |sum, v|sum ^ *v);
         hash_sum as usize
     }
@@ -118,6 +119,329 @@ impl HashBuilder for XorHashBuilder256 {
     }
 }
 
+struct FNV1aHashBuilder {
+    max_value: usize
+}
+
+impl FNV1aHashBuilder {
+    fn new(max_value: usize) -> FNV1aHashBuilder {
+        FNV1aHashBuilder{ max_value }
+    }
+}
+
+// 64 bit FNV-1a
+fn fnv1a(key: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for c in key {
+        hash ^= *c as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+impl HashBuilder for FNV1aHashBuilder {
+    fn build_hash(&self, key: &[u8]) -> usize {
+        (fnv1a(key) % self.max_value as u64) as usize
+    }
+
+    fn get_name(&self) -> &'static str {
+        "fnv1a"
+    }
+}
+
+struct XXHashBuilder {
+    max_value: usize
+}
+
+impl XXHashBuilder {
+    fn new(max_value: usize) -> XXHashBuilder {
+        XXHashBuilder{ max_value }
+    }
+}
+
+const XXH_PRIME64_1: u64 = 0x9E3779B185EBCA87;
+const XXH_PRIME64_2: u64 = 0xC2B2AE3D27D4EB4F;
+const XXH_PRIME64_3: u64 = 0x165667B19E3779F9;
+const XXH_PRIME64_4: u64 = 0x85EBCA77C2B2AE63;
+const XXH_PRIME64_5: u64 = 0x27D4EB2F165667C5;
+
+fn read_u64(data: &[u8]) -> u64 {
+    u64::from_le_bytes(data[..8].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes(data[..4].try_into().unwrap())
+}
+
+fn xxh64_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(XXH_PRIME64_2)).rotate_left(31).wrapping_mul(XXH_PRIME64_1)
+}
+
+fn xxh64_merge_round(acc: u64, val: u64) -> u64 {
+    (acc ^ xxh64_round(0, val)).wrapping_mul(XXH_PRIME64_1).wrapping_add(XXH_PRIME64_4)
+}
+
+// xxHash64 with seed 0
+fn xxhash64(key: &[u8]) -> u64 {
+    let len = key.len();
+    let mut data = key;
+    let mut hash = if len >= 32 {
+        let mut v1 = XXH_PRIME64_1.wrapping_add(XXH_PRIME64_2);
+        let mut v2 = XXH_PRIME64_2;
+        let mut v3 = 0;
+        let mut v4 = XXH_PRIME64_1.wrapping_neg();
+        while data.len() >= 32 {
+            v1 = xxh64_round(v1, read_u64(data));
+            v2 = xxh64_round(v2, read_u64(&data[8..]));
+            v3 = xxh64_round(v3, read_u64(&data[16..]));
+            v4 = xxh64_round(v4, read_u64(&data[24..]));
+            data = &data[32..];
+        }
+        let mut h = v1.rotate_left(1).wrapping_add(v2.rotate_left(7))
+            .wrapping_add(v3.rotate_left(12)).wrapping_add(v4.rotate_left(18));
+        h = xxh64_merge_round(h, v1);
+        h = xxh64_merge_round(h, v2);
+        h = xxh64_merge_round(h, v3);
+        xxh64_merge_round(h, v4)
+    } else {
+        XXH_PRIME64_5
+    };
+    hash = hash.wrapping_add(len as u64);
+
+    while data.len() >= 8 {
+        hash ^= xxh64_round(0, read_u64(data));
+        hash = hash.rotate_left(27).wrapping_mul(XXH_PRIME64_1).wrapping_add(XXH_PRIME64_4);
+        data = &data[8..];
+    }
+    if data.len() >= 4 {
+        hash ^= (read_u32(data) as u64).wrapping_mul(XXH_PRIME64_1);
+        hash = hash.rotate_left(23).wrapping_mul(XXH_PRIME64_2).wrapping_add(XXH_PRIME64_3);
+        data = &data[4..];
+    }
+    for c in data {
+        hash ^= (*c as u64).wrapping_mul(XXH_PRIME64_5);
+        hash = hash.rotate_left(11).wrapping_mul(XXH_PRIME64_1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(XXH_PRIME64_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(XXH_PRIME64_3);
+    hash ^ (hash >> 32)
+}
+
+impl HashBuilder for XXHashBuilder {
+    fn build_hash(&self, key: &[u8]) -> usize {
+        (xxhash64(key) % self.max_value as u64) as usize
+    }
+
+    fn get_name(&self) -> &'static str {
+        "xxhash"
+    }
+}
+
+struct CRC32HashBuilder {
+    max_value: usize
+}
+
+impl CRC32HashBuilder {
+    fn new(max_value: usize) -> CRC32HashBuilder {
+        CRC32HashBuilder{ max_value }
+    }
+}
+
+const fn build_crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 256] = build_crc32_table();
+
+// CRC-32 (IEEE 802.3)
+fn crc32(key: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFF;
+
+    for c in key {
+        crc = CRC32_TABLE[((crc ^ *c as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+
+    !crc
+}
+
+impl HashBuilder for CRC32HashBuilder {
+    fn build_hash(&self, key: &[u8]) -> usize {
+        crc32(key) as usize % self.max_value
+    }
+
+    fn get_name(&self) -> &'static str {
+        "crc32"
+    }
+}
+
+struct Murmur3HashBuilder {
+    max_value: usize
+}
+
+impl Murmur3HashBuilder {
+    fn new(max_value: usize) -> Murmur3HashBuilder {
+        Murmur3HashBuilder{ max_value }
+    }
+}
+
+fn murmur3_scramble(k: u32) -> u32 {
+    k.wrapping_mul(0xcc9e2d51).rotate_left(15).wrapping_mul(0x1b873593)
+}
+
+// MurmurHash3 x86 32 bit with seed 0
+fn murmur3(key: &[u8]) -> u32 {
+    let mut hash: u32 = 0;
+    let mut chunks = key.chunks_exact(4);
+
+    for chunk in &mut chunks {
+        hash ^= murmur3_scramble(read_u32(chunk));
+        hash = hash.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0u32, |k, c| (k << 8) | *c as u32);
+        hash ^= murmur3_scramble(k);
+    }
+
+    hash ^= key.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85ebca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2ae35);
+    hash ^ (hash >> 16)
+}
+
+impl HashBuilder for Murmur3HashBuilder {
+    fn build_hash(&self, key: &[u8]) -> usize {
+        murmur3(key) as usize % self.max_value
+    }
+
+    fn get_name(&self) -> &'static str {
+        "murmur3"
+    }
+}
+
+struct SipHashBuilder {
+    max_value: usize,
+    k0: u64,
+    k1: u64
+}
+
+impl SipHashBuilder {
+    // random per process key, so that keys landing in the same map can't be predicted
+    fn new(max_value: usize) -> SipHashBuilder {
+        let mut rng = rand::thread_rng();
+        SipHashBuilder{ max_value, k0: rng.gen(), k1: rng.gen() }
+    }
+}
+
+fn sip_round(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(13) ^ v[0];
+    v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(16) ^ v[2];
+    v[0] = v[0].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(21) ^ v[0];
+    v[2] = v[2].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(17) ^ v[2];
+    v[2] = v[2].rotate_left(32);
+}
+
+// SipHash-c-d
+fn siphash(c_rounds: usize, d_rounds: usize, k0: u64, k1: u64, key: &[u8]) -> u64 {
+    let mut v = [k0 ^ 0x736f6d6570736575, k1 ^ 0x646f72616e646f6d, k0 ^ 0x6c7967656e657261, k1 ^ 0x7465646279746573];
+    let mut chunks = key.chunks_exact(8);
+
+    for chunk in &mut chunks {
+        let m = read_u64(chunk);
+        v[3] ^= m;
+        (0..c_rounds).for_each(|_i| sip_round(&mut v));
+        v[0] ^= m;
+    }
+    // last block: remaining bytes and the key length in the highest byte
+    let m = chunks.remainder().iter().enumerate()
+        .fold((key.len() as u64) << 56, |m, (i, c)| m | ((*c as u64) << (i * 8)));
+    v[3] ^= m;
+    (0..c_rounds).for_each(|_i| sip_round(&mut v));
+    v[0] ^= m;
+
+    v[2] ^= 0xFF;
+    (0..d_rounds).for_each(|_i| sip_round(&mut v));
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+impl HashBuilder for SipHashBuilder {
+    fn build_hash(&self, key: &[u8]) -> usize {
+        (siphash(1, 3, self.k0, self.k1, key) % self.max_value as u64) as usize
+    }
+
+    fn get_name(&self) -> &'static str {
+        "siphash"
+    }
+}
+
+// number of ring points per map
+const RING_POINTS: usize = 64;
+
+// consistent hash ring: every map owns RING_POINTS points on the ring, a key belongs to the map that owns
+// the first point at or after the key hash, so changing the number of maps moves only a fraction of keys
+struct ConsistentHashBuilder {
+    name: &'static str,
+    hash: fn(&[u8]) -> u64,
+    ring: Vec<(u64, usize)>,
+}
+
+// murmur3 64 bit finalizer, spreads the hash over all bits so that ring positions do not depend on
+// the high bits quality of the underlying hash function
+fn fmix64(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^ (h >> 33)
+}
+
+impl ConsistentHashBuilder {
+    fn new(name: &'static str, hash: fn(&[u8]) -> u64, max_value: usize) -> ConsistentHashBuilder {
+        let mut ring: Vec<(u64, usize)> = (0..max_value)
+            .flat_map(|map| (0..RING_POINTS).map(move |point| (map, point)))
+            .map(|(map, point)| (fmix64(hash(format!("{}-{}", map, point).as_bytes())), map))
+            .collect();
+        ring.sort();
+        ConsistentHashBuilder{ name, hash, ring }
+    }
+}
+
+impl HashBuilder for ConsistentHashBuilder {
+    fn build_hash(&self, key: &[u8]) -> usize {
+        let hash = fmix64((self.hash)(key));
+        let idx = self.ring.partition_point(|(h, _map)| *h < hash);
+        self.ring[idx % self.ring.len()].1
+    }
+
+    fn get_name(&self) -> &'static str {
+        self.name
+    }
+}
+
 struct ZeroHashBuilder;
 
 impl ZeroHashBuilder {
@@ -127,7 +451,7 @@ impl ZeroHashBuilder {
 }
 
 impl HashBuilder for ZeroHashBuilder {
-    fn build_hash(&self, _key: &Vec<u8>) -> usize {
+    fn build_hash(&self, _key: &[u8]) -> usize {
         0
     }
 
@@ -153,6 +477,99 @@ pub fn create_hash_builder(name: String, max_value: usize) -> Result<Box<dyn Has
         "sum" => Ok(Box::new(SumHashBuilder::new(max_value))),
         "djb2" => Ok(Box::new(DJB2HashBuilder::new(max_value))),
         "sdbm" => Ok(Box::new(SDBMHashBuilder::new(max_value))),
+        "fnv1a" => Ok(Box::new(FNV1aHashBuilder::new(max_value))),
+        "xxhash" => Ok(Box::new(XXHashBuilder::new(max_value))),
+        "crc32" => Ok(Box::new(CRC32HashBuilder::new(max_value))),
+        "murmur3" => Ok(Box::new(Murmur3HashBuilder::new(max_value))),
+        "siphash" => Ok(Box::new(SipHashBuilder::new(max_value))),
         _ => Err(Error::new(ErrorKind::InvalidInput, "invalid hash builder type"))
     }
-}
\ No newline at end of file
+}
+
+// hash builder that uses a consistent hash ring, supported only for fnv1a, xxhash, crc32 and murmur3
+pub fn create_consistent_hash_builder(name: String, max_value: usize) -> Result<Box<dyn HashBuilder + Send + Sync>, Error> {
+    if max_value == 1 {
+        return Ok(Box::new(ZeroHashBuilder::new()))
+    }
+    match name.as_str() {
+        "fnv1a" => Ok(Box::new(ConsistentHashBuilder::new("fnv1a-ring", fnv1a, max_value))),
+        "xxhash" => Ok(Box::new(ConsistentHashBuilder::new("xxhash-ring", xxhash64, max_value))),
+        "crc32" => Ok(Box::new(ConsistentHashBuilder::new("crc32-ring", |key| crc32(key) as u64, max_value))),
+        "murmur3" => Ok(Box::new(ConsistentHashBuilder::new("murmur3-ring", |key| murmur3(key) as u64, max_value))),
+        _ => Err(Error::new(ErrorKind::InvalidInput, "consistent hashing is not supported by this hash builder type"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+    use crate::hash_builders::{crc32, create_consistent_hash_builder, create_hash_builder, fnv1a, murmur3, siphash,
+                               xxhash64};
+
+    #[test]
+    fn test_reference_vectors() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+
+        assert_eq!(xxhash64(b""), 0xef46db3751d8e999);
+        assert_eq!(xxhash64(b"a"), 0xd24ec4f1a98c6e5b);
+        assert_eq!(xxhash64(b"abc"), 0x44bc2cf5ad770999);
+        assert_eq!(xxhash64(b"Nobody inspects the spammish repetition"), 0xfbcea83c8a378bf1);
+
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414fa339);
+
+        assert_eq!(murmur3(b""), 0);
+        assert_eq!(murmur3(b"hello"), 0x248bfa47);
+        assert_eq!(murmur3(b"The quick brown fox jumps over the lazy dog"), 0x2e4ff723);
+
+        // SipHash-2-4 vector from the SipHash paper
+        let message: Vec<u8> = (0..15).collect();
+        assert_eq!(siphash(2, 4, 0x0706050403020100, 0x0f0e0d0c0b0a0908, &message), 0xa129ca6149be45e5);
+        // DefaultHasher::new() is SipHash-1-3 with zero keys
+        for message in [&b""[..], b"a", b"12345678", b"The quick brown fox jumps over the lazy dog"] {
+            let mut hasher = DefaultHasher::new();
+            hasher.write(message);
+            assert_eq!(siphash(1, 3, 0, 0, message), hasher.finish());
+        }
+    }
+
+    #[test]
+    fn test_distribution() {
+        for name in ["fnv1a", "xxhash", "crc32", "murmur3", "siphash"] {
+            let builder = create_hash_builder(name.to_string(), 16).unwrap();
+            let mut counts = [0; 16];
+            for i in 0..16000 {
+                counts[builder.build_hash(&format!("user:{}", i).into_bytes())] += 1;
+            }
+            assert!(counts.iter().all(|c| *c > 800 && *c < 1200), "{} {:?}", name, counts);
+        }
+    }
+
+    #[test]
+    fn test_consistent_hashing() {
+        for name in ["fnv1a", "xxhash", "crc32", "murmur3"] {
+            let builder = create_consistent_hash_builder(name.to_string(), 16).unwrap();
+            let builder2 = create_consistent_hash_builder(name.to_string(), 17).unwrap();
+            let mut counts = [0; 16];
+            let mut moved = 0;
+            for i in 0..16000 {
+                let key = format!("user:{}", i).into_bytes();
+                let idx = builder.build_hash(&key);
+                counts[idx] += 1;
+                let idx2 = builder2.build_hash(&key);
+                if idx2 != idx {
+                    // keys can move only to the new map
+                    assert_eq!(idx2, 16, "{}", name);
+                    moved += 1;
+                }
+            }
+            assert!(counts.iter().all(|c| *c > 500 && *c < 1500), "{} {:?}", name, counts);
+            assert!(moved > 0 && moved < 1600, "{} {}", name, moved);
+        }
+        assert!(create_consistent_hash_builder("sum".to_string(), 16).is_err());
+    }
+}
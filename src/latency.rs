@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+// latency samples in microseconds
+#[derive(Default, Clone)]
+pub struct LatencyStats {
+    samples: Vec<u64>,
+    sorted: bool,
+}
+
+impl LatencyStats {
+    pub fn new() -> LatencyStats {
+        LatencyStats::default()
+    }
+
+    pub fn add(&mut self, latency: Duration) {
+        self.samples.push(latency.as_micros() as u64);
+        self.sorted = false;
+    }
+
+    pub fn merge(&mut self, other: &LatencyStats) {
+        self.samples.extend_from_slice(&other.samples);
+        self.sorted = false;
+    }
+
+    pub fn count(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn min(&self) -> u64 {
+        self.samples.iter().copied().min().unwrap_or(0)
+    }
+
+    pub fn max(&self) -> u64 {
+        self.samples.iter().copied().max().unwrap_or(0)
+    }
+
+    pub fn avg(&self) -> f64 {
+        if self.samples.is_empty() {
+            0.0
+        } else {
+            self.samples.iter().sum::<u64>() as f64 / self.samples.len() as f64
+        }
+    }
+
+    // nearest rank percentile, p is in 0..=100
+    pub fn percentile(&mut self, p: f64) -> u64 {
+        if self.samples.is_empty() {
+            return 0;
+        }
+        if !self.sorted {
+            self.samples.sort_unstable();
+            self.sorted = true;
+        }
+        let rank = ((p / 100.0) * self.samples.len() as f64).ceil() as usize;
+        self.samples[rank.clamp(1, self.samples.len()) - 1]
+    }
+
+    // number of samples in power of two buckets, returns (upper bound, count) for non empty buckets
+    pub fn histogram(&self) -> Vec<(u64, usize)> {
+        let mut buckets: BTreeMap<u64, usize> = BTreeMap::new();
+        for s in &self.samples {
+            *buckets.entry(s.max(&1).next_power_of_two()).or_default() += 1;
+        }
+        buckets.into_iter().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::latency::LatencyStats;
+
+    #[test]
+    fn test_latency_stats() {
+        let mut stats = LatencyStats::new();
+        assert_eq!(stats.percentile(50.0), 0);
+        for i in (1..=100).rev() {
+            stats.add(Duration::from_micros(i));
+        }
+        assert_eq!(stats.count(), 100);
+        assert_eq!((stats.min(), stats.max()), (1, 100));
+        assert_eq!(stats.avg(), 50.5);
+        assert_eq!(stats.percentile(50.0), 50);
+        assert_eq!(stats.percentile(99.0), 99);
+        assert_eq!(stats.percentile(100.0), 100);
+        assert_eq!(stats.percentile(0.0), 1);
+        assert_eq!(stats.histogram(), vec![(1, 1), (2, 1), (4, 2), (8, 4), (16, 8), (32, 16), (64, 32), (128, 36)]);
+    }
+}
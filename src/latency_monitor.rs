@@ -0,0 +1,124 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// samples kept per event, like in Redis
+const HISTORY_SIZE: usize = 160;
+
+#[derive(Default)]
+struct EventHistory {
+    // unix time in seconds, latency in milliseconds
+    samples: VecDeque<(u64, u64)>,
+    max: u64,
+}
+
+// LATENCY LATEST/HISTORY/RESET: latency spikes of commands and eviction cycles that are not shorter than
+// the latency-monitor-threshold setting, 0 - monitoring is disabled
+#[derive(Default)]
+pub struct LatencyMonitor {
+    threshold: AtomicU64,
+    events: Mutex<HashMap<&'static str, EventHistory>>,
+}
+
+impl LatencyMonitor {
+    pub fn new() -> LatencyMonitor {
+        LatencyMonitor::default()
+    }
+
+    // milliseconds
+    pub fn threshold(&self) -> u64 {
+        self.threshold.load(Ordering::Relaxed)
+    }
+
+    pub fn set_threshold(&self, threshold: u64) {
+        self.threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    pub fn add_sample(&self, event: &'static str, latency: Duration) {
+        let threshold = self.threshold();
+        let latency = latency.as_millis() as u64;
+        if threshold == 0 || latency < threshold {
+            return;
+        }
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        self.add(event, time, latency);
+    }
+
+    // samples of the same second are merged, the highest latency is kept
+    fn add(&self, event: &'static str, time: u64, latency: u64) {
+        let mut events = self.events.lock().unwrap();
+        let history = events.entry(event).or_default();
+        history.max = history.max.max(latency);
+        match history.samples.back_mut() {
+            Some((t, l)) if *t == time => *l = (*l).max(latency),
+            _ => {
+                if history.samples.len() == HISTORY_SIZE {
+                    history.samples.pop_front();
+                }
+                history.samples.push_back((time, latency));
+            }
+        }
+    }
+
+    // event, time of the latest spike, its latency and the maximum latency, ordered by event name
+    pub fn latest(&self) -> Vec<(&'static str, u64, u64, u64)> {
+        let events = self.events.lock().unwrap();
+        let mut latest: Vec<(&'static str, u64, u64, u64)> = events.iter()
+            .filter_map(|(event, h)| h.samples.back().map(|(t, l)| (*event, *t, *l, h.max)))
+            .collect();
+        latest.sort();
+        latest
+    }
+
+    pub fn history(&self, event: &[u8]) -> Vec<(u64, u64)> {
+        self.events.lock().unwrap().iter()
+            .find(|(e, _h)| e.as_bytes().eq_ignore_ascii_case(event))
+            .map(|(_e, h)| h.samples.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    // all events when the list is empty, returns the number of removed events
+    pub fn reset(&self, events: &[&[u8]]) -> usize {
+        let mut all = self.events.lock().unwrap();
+        let count = all.len();
+        if events.is_empty() {
+            all.clear();
+        } else {
+            all.retain(|e, _h| !events.iter().any(|n| e.as_bytes().eq_ignore_ascii_case(n)));
+        }
+        count - all.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::latency_monitor::{LatencyMonitor, HISTORY_SIZE};
+
+    #[test]
+    fn test_latency_monitor() {
+        let monitor = LatencyMonitor::new();
+        monitor.add_sample("command", Duration::from_millis(100));
+        assert!(monitor.latest().is_empty());
+        monitor.set_threshold(10);
+        monitor.add_sample("command", Duration::from_millis(5));
+        assert!(monitor.latest().is_empty());
+
+        monitor.add("command", 100, 20);
+        monitor.add("command", 100, 30);
+        monitor.add("command", 101, 15);
+        monitor.add("eviction-cycle", 102, 12);
+        assert_eq!(monitor.history(b"COMMAND"), vec![(100, 30), (101, 15)]);
+        assert_eq!(monitor.latest(), vec![("command", 101, 15, 30), ("eviction-cycle", 102, 12, 12)]);
+        (0..HISTORY_SIZE as u64).for_each(|i| monitor.add("eviction-cycle", 200 + i, 11));
+        let history = monitor.history(b"eviction-cycle");
+        assert_eq!(history.len(), HISTORY_SIZE);
+        assert_eq!(history[0], (200, 11));
+
+        assert_eq!(monitor.reset(&[b"command", b"x"]), 1);
+        assert!(monitor.history(b"command").is_empty());
+        assert_eq!(monitor.reset(&[]), 1);
+        assert!(monitor.latest().is_empty());
+    }
+}
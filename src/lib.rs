@@ -0,0 +1,47 @@
+pub mod common_data;
+#[cfg(feature = "server")]
+pub mod server;
+mod resp_parser;
+mod resp_commands;
+pub mod resp_encoder;
+pub mod resp_codec;
+#[cfg(feature = "benchmark")]
+pub mod benchmark;
+pub mod common_maps;
+pub mod hash_builders;
+pub mod allocator;
+pub mod values;
+mod shared_values;
+mod event_hooks;
+mod glob;
+mod pubsub;
+pub mod connections;
+pub mod member_set;
+pub mod sorted_set;
+pub mod geo;
+pub mod bitmap;
+mod command_stats;
+mod command_table;
+mod network_stats;
+mod latency_monitor;
+#[cfg(feature = "server")]
+mod otlp;
+mod config_file;
+pub mod latency;
+#[cfg(feature = "server")]
+pub mod server_builder;
+#[cfg(feature = "server")]
+pub mod logger;
+pub mod storage_backend;
+pub mod local_client;
+#[cfg(feature = "client")]
+pub mod client;
+
+#[cfg(feature = "client")]
+pub use crate::client::Client;
+pub use crate::common_data::CommonData;
+pub use crate::common_maps::EvictionPolicy;
+pub use crate::local_client::LocalClient;
+#[cfg(feature = "server")]
+pub use crate::server_builder::{CacheServer, CacheServerBuilder};
+pub use crate::storage_backend::{StorageBackend, StorageBackendFactory};
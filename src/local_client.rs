@@ -0,0 +1,62 @@
+use std::sync::Arc;
+use crate::common_data::CommonData;
+use crate::resp_codec::{Decoder, RespToken};
+use crate::resp_parser::run_command;
+use crate::resp_parser::RespToken::{RespArray, RespBinaryString, RespError};
+
+// runs commands against the cache in the same process, without a socket
+// replies are the same as the server sends to network clients
+pub struct LocalClient {
+    common_data: Arc<CommonData>,
+    decoder: Decoder,
+}
+
+impl LocalClient {
+    pub fn new(common_data: Arc<CommonData>) -> LocalClient {
+        LocalClient { common_data, decoder: Decoder::new() }
+    }
+
+    pub fn execute(&mut self, command: RespToken) -> RespToken {
+        let mut result = Vec::new();
+        run_command(command, &mut result, self.common_data.clone(), None);
+        self.decoder.feed(&result);
+        match self.decoder.next_frame() {
+            Ok(Some(reply)) => reply,
+            _ => {
+                self.decoder.clear();
+                RespError(b"invalid reply".to_vec())
+            }
+        }
+    }
+
+    // command as a list of arguments, for example [b"set", b"key", b"value"]
+    pub fn command(&mut self, args: &[&[u8]]) -> RespToken {
+        self.execute(RespArray(args.iter().map(|a| RespBinaryString(a.to_vec())).collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::common_data::build_common_data;
+    use crate::hash_builders::create_hash_builder;
+    use crate::local_client::LocalClient;
+    use crate::resp_codec::RespToken::{RespBinaryString, RespError, RespInteger, RespNullString, RespString};
+    use crate::values::ValueSettings;
+
+    #[test]
+    fn test_local_client() {
+        let common_data = Arc::new(build_common_data(false, 100000, 4,
+                                                     create_hash_builder("fnv1a".to_string(), 4).unwrap(), false,
+                                                     ValueSettings{ compression_threshold: 0, integer_encoding: true,
+                                                         intern_threshold: 0 }));
+        let mut client = LocalClient::new(common_data.clone());
+        assert_eq!(client.command(&[b"set", b"a", b"1"]), RespString(b"OK".to_vec()));
+        assert_eq!(client.command(&[b"get", b"a"]), RespBinaryString(b"1".to_vec()));
+        assert_eq!(client.command(&[b"del", b"a", b"b"]), RespInteger(1));
+        assert_eq!(client.command(&[b"get", b"a"]), RespNullString);
+        assert_eq!(client.command(&[b"unknown"]), RespError(b"invalid command".to_vec()));
+        assert_eq!(client.execute(RespString(b"PING".to_vec())), RespString(b"PONG".to_vec()));
+        assert_eq!(common_data.size(), 0);
+    }
+}
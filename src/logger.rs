@@ -0,0 +1,122 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Error, Write};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LogFormat {
+    // time level message
+    Text,
+    // one JSON object per line with time, level, target and message fields
+    Json,
+}
+
+// log facade implementation, writes to stdout or appends to a file
+// the level is log::max_level(), so it can be changed at runtime
+pub struct Logger {
+    format: LogFormat,
+    file: Option<Mutex<File>>,
+}
+
+// installs the logger, can be called only once
+pub fn init_logger(level: LevelFilter, format: LogFormat, file_name: Option<&str>) -> Result<(), Error> {
+    let file = match file_name {
+        Some(f) => Some(Mutex::new(OpenOptions::new().create(true).append(true).open(f)?)),
+        None => None
+    };
+    log::set_boxed_logger(Box::new(Logger { format, file })).map_err(Error::other)?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format_line(self.format, SystemTime::now(), record.level(), record.target(),
+                               &record.args().to_string());
+        match &self.file {
+            Some(f) => {
+                let _ = writeln!(f.lock().unwrap(), "{}", line);
+            }
+            None => println!("{}", line)
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(f) = &self.file {
+            let _ = f.lock().unwrap().flush();
+        }
+    }
+}
+
+fn format_line(format: LogFormat, time: SystemTime, level: Level, target: &str, message: &str) -> String {
+    match format {
+        LogFormat::Text => format!("{} {:5} {}", format_time(time), level, message),
+        LogFormat::Json => format!("{{\"time\":\"{}\",\"level\":\"{}\",\"target\":{},\"message\":{}}}",
+                                   format_time(time), level, json_string(target), json_string(message))
+    }
+}
+
+// UTC time in RFC 3339 format with milliseconds
+fn format_time(time: SystemTime) -> String {
+    let d = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = d.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let s = seconds % 86400;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day, s / 3600, s % 3600 / 60, s % 60,
+            d.subsec_millis())
+}
+
+// days since 1970-01-01 to (year, month, day), Howard Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+    use log::Level;
+    use crate::logger::{format_line, LogFormat};
+
+    #[test]
+    fn test_format_line() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        assert_eq!(format_line(LogFormat::Text, time, Level::Info, "cache::server", "Server listening on port 6379"),
+                   "2023-11-14T22:13:20.123Z INFO  Server listening on port 6379");
+        assert_eq!(format_line(LogFormat::Json, time, Level::Warn, "cache::server", "read \"error\"\n"),
+                   "{\"time\":\"2023-11-14T22:13:20.123Z\",\"level\":\"WARN\",\"target\":\"cache::server\",\"message\":\"read \\\"error\\\"\\n\"}");
+        assert!(format_line(LogFormat::Text, UNIX_EPOCH + Duration::from_secs(951_782_400), Level::Error, "", "")
+            .starts_with("2000-02-29T00:00:00.000Z"));
+    }
+}
@@ -1,27 +1,29 @@
-mod common_data;
-mod server;
-mod resp_parser;
-mod resp_commands;
-mod resp_encoder;
-mod benchmark;
-mod common_maps;
-mod hash_builders;
-
 use std::env::args;
-use std::io::{Error, Read, Write};
-use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpStream};
-use std::sync::Arc;
-use std::sync::atomic::Ordering;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{stdin, stdout, BufRead, BufReader, Error, Write};
+use std::net::IpAddr;
+use std::str::FromStr;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use arguments_parser::{Arguments, IntParameter, SizeParameter, BoolParameter, Switch, StringParameter};
-use crate::common_data::build_common_data;
 use ctrlc;
-use crate::benchmark::{benchmark_mode, BenchmarkCommand};
-use crate::benchmark::BenchmarkCommand::{Get, Ping, Set, SetPX};
-use crate::hash_builders::{create_hash_builder, HashBuilder};
-use crate::resp_encoder::resp_encode_strings;
-use crate::server::server_start;
+use log::{error, info, LevelFilter};
+use cache::benchmark::{benchmark_mode, parse_command_mix, BenchmarkSettings, Payload, ResultFormat};
+use cache::latency::LatencyStats;
+use cache::logger::{init_logger, LogFormat};
+use cache::client::{format_reply, format_reply_as, pipe, split_command_line, Connection, OutputFormat};
+use cache::resp_codec::RespToken::{RespBinaryString, RespError, RespInteger, RespString};
+#[cfg(unix)]
+use cache::server::{daemonize, reload_on_sighup, sd_notify};
+use cache::server::server_stop;
+use cache::CacheServerBuilder;
+#[cfg(feature = "allocator_stats")]
+use cache::allocator::CountingAllocator;
+
+#[cfg(feature = "allocator_stats")]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
 
 fn main() -> Result<(), Error> {
     let host_parameter = StringParameter::new("127.0.0.1");
@@ -29,28 +31,131 @@ fn main() -> Result<(), Error> {
     let max_memory_parameter = SizeParameter::new(1024 * 1024 * 1024);//1G
     let verbose_parameter = BoolParameter::new();
     let client_parameter = BoolParameter::new();
+    let pipe_parameter = BoolParameter::new();
+    let script_parameter = StringParameter::new("");
+    // negative - forever
+    let repeat_parameter = IntParameter::new(1);
+    let interval_parameter = StringParameter::new("0");
+    let scan_parameter = BoolParameter::new();
+    let pattern_parameter = StringParameter::new("");
+    let bigkeys_parameter = BoolParameter::new();
+    let memkeys_parameter = BoolParameter::new();
+    let hotkeys_parameter = BoolParameter::new();
+    let latency_parameter = BoolParameter::new();
+    let latency_history_parameter = BoolParameter::new();
+    let password_parameter = StringParameter::new("");
+    let user_parameter = StringParameter::new("");
+    let connect_timeout_parameter = StringParameter::new("0");
+    let read_timeout_parameter = StringParameter::new("0");
+    let retries_parameter = IntParameter::new(1);
+    let raw_parameter = BoolParameter::new();
+    let csv_parameter = BoolParameter::new();
+    let json_parameter = BoolParameter::new();
     let benchmark_parameter = BoolParameter::new();
     let keys_parameter = IntParameter::new(50000);
     let requests_parameter = IntParameter::new(50000);
     let threads_parameter = IntParameter::new(10);
     let types_parameter = StringParameter::new("get,set,get,setpx");
     let expiration_parameter = IntParameter::new(100);
-    let vector_size_parameter = IntParameter::new(256);
-    let hash_type_parameter = StringParameter::new("sum");
+    let pipeline_parameter = IntParameter::new(1);
+    // 0 - the value is the key
+    let value_size_parameter = SizeParameter::new(0);
+    let payload_parameter = StringParameter::new("random");
+    let output_parameter = StringParameter::new("");
+    let save_baseline_parameter = StringParameter::new("");
+    let compare_baseline_parameter = StringParameter::new("");
+    let regression_threshold_parameter = StringParameter::new("10");
+    let unix_socket_parameter = StringParameter::new("");
+    let fields_parameter = IntParameter::new(10);
+    let warmup_parameter = StringParameter::new("0");
+    let duration_parameter = StringParameter::new("0");
+    let output_file_parameter = StringParameter::new("");
+    // 0 - choose automatically from the number of CPUs
+    let vector_size_parameter = IntParameter::new(0);
+    let hash_type_parameter = StringParameter::new("fnv1a");
+    let allocator_memory_parameter = BoolParameter::new();
+    let compression_threshold_parameter = IntParameter::new(0);
+    let disable_integer_encoding_parameter = BoolParameter::new();
+    let consistent_hashing_parameter = BoolParameter::new();
+    let intern_threshold_parameter = IntParameter::new(0);
+    let defrag_interval_parameter = IntParameter::new(10);
+    // empty - info, debug with -v
+    let log_level_parameter = StringParameter::new("");
+    let log_file_parameter = StringParameter::new("");
+    let log_json_parameter = BoolParameter::new();
+    let otlp_endpoint_parameter = StringParameter::new("");
+    let config_file_parameter = StringParameter::new("");
+    let daemonize_parameter = StringParameter::new("no");
+    let bind_parameter = StringParameter::new("0.0.0.0");
+    let shutdown_timeout_parameter = StringParameter::new("10");
+    let pid_file_parameter = StringParameter::new("");
     let switches = [
         Switch::new("host for client to connect", Some('h'), None, &host_parameter),
         Switch::new("port", Some('p'), None, &port_parameter),
         Switch::new("maximum memory for server", Some('m'), None, &max_memory_parameter),
         Switch::new("verbose", Some('v'), None, &verbose_parameter),
         Switch::new("client mode", Some('c'), None, &client_parameter),
+        Switch::new("client mode, send commands from stdin without waiting for replies", None, Some("pipe"), &pipe_parameter),
+        Switch::new("client mode, run commands from file, one per line (- for stdin)", None, Some("fi"), &script_parameter),
+        Switch::new("number of command repetitions in client mode", None, Some("rp"), &repeat_parameter),
+        Switch::new("interval in seconds between command repetitions in client mode", Some('i'), None, &interval_parameter),
+        Switch::new("client mode, list keys with SCAN", None, Some("scan"), &scan_parameter),
+        Switch::new("glob pattern for keys in scan mode", None, Some("pattern"), &pattern_parameter),
+        Switch::new("client mode, find the biggest keys of every type", None, Some("bigkeys"), &bigkeys_parameter),
+        Switch::new("client mode, find the keys that use most memory", None, Some("memkeys"), &memkeys_parameter),
+        Switch::new("client mode, find the most frequently used keys", None, Some("hotkeys"), &hotkeys_parameter),
+        Switch::new("client mode, measure PING latency continuously", None, Some("latency"), &latency_parameter),
+        Switch::new("client mode, print PING latency for every interval (-i, default 15 seconds)", None,
+                    Some("latency-history"), &latency_history_parameter),
+        Switch::new("password for AUTH in client mode", Some('a'), Some("pass"), &password_parameter),
+        Switch::new("user name for AUTH in client mode", None, Some("user"), &user_parameter),
+        Switch::new("connect timeout in seconds in client mode", None, Some("connect-timeout"), &connect_timeout_parameter),
+        Switch::new("reply timeout in seconds in client mode", None, Some("read-timeout"), &read_timeout_parameter),
+        Switch::new("number of reconnect attempts in client mode", None, Some("retries"), &retries_parameter),
+        Switch::new("raw output of replies in client mode", None, Some("raw"), &raw_parameter),
+        Switch::new("CSV output of replies in client mode", None, Some("csv"), &csv_parameter),
+        Switch::new("JSON output of replies in client mode", None, Some("json"), &json_parameter),
         Switch::new("benchmark mode", Some('b'), None, &benchmark_parameter),
         Switch::new("number of keys for benchmark", Some('k'), None, &keys_parameter),
         Switch::new("number of requests per thread for benchmark", Some('r'), None, &requests_parameter),
         Switch::new("number of threads for benchmark", None, Some("th"), &threads_parameter),
-        Switch::new("request types for benchmark", Some('t'), None, &types_parameter),
+        Switch::new("request types with optional weights for benchmark", Some('t'), None, &types_parameter),
         Switch::new("key expiration in ms for benchmark", None, Some("nx"), &expiration_parameter),
+        Switch::new("number of pipelined requests for benchmark", Some('P'), None, &pipeline_parameter),
+        Switch::new("value size for benchmark", Some('d'), None, &value_size_parameter),
+        Switch::new("value payload type for benchmark", None, Some("payload"), &payload_parameter),
+        Switch::new("unix socket path for benchmark", None, Some("unixsocket"), &unix_socket_parameter),
+        Switch::new("number of fields or members per key for benchmark", None, Some("fields"), &fields_parameter),
+        Switch::new("warmup time in seconds for benchmark", None, Some("warmup"), &warmup_parameter),
+        Switch::new("benchmark run time in seconds instead of a fixed number of requests", None, Some("duration"),
+                    &duration_parameter),
+        Switch::new("save benchmark results as a baseline with this name", None, Some("save-baseline"),
+                    &save_baseline_parameter),
+        Switch::new("compare benchmark results with the baseline with this name", None, Some("compare-baseline"),
+                    &compare_baseline_parameter),
+        Switch::new("allowed throughput or p99 latency regression in percent for baseline comparison", None,
+                    Some("regression-threshold"), &regression_threshold_parameter),
+        Switch::new("benchmark results file format (csv or json)", None, Some("output"), &output_parameter),
+        Switch::new("benchmark results file name", None, Some("output-file"), &output_file_parameter),
         Switch::new("numer of key maps", None, Some("km"), &vector_size_parameter),
         Switch::new("hash builder type", None, Some("hb"), &hash_type_parameter),
+        Switch::new("use consistent hash ring for key map selection", None, Some("ch"), &consistent_hashing_parameter),
+        Switch::new("use allocator statistics for maximum memory check", None, Some("am"), &allocator_memory_parameter),
+        Switch::new("value size threshold for compression", None, Some("ct"), &compression_threshold_parameter),
+        Switch::new("disable integer encoding of values", None, Some("di"), &disable_integer_encoding_parameter),
+        Switch::new("value size threshold for interning", None, Some("it"), &intern_threshold_parameter),
+        Switch::new("defragmentation check interval in seconds", None, Some("df"), &defrag_interval_parameter),
+        Switch::new("server log level (off, error, warn, info, debug, trace)", None, Some("log-level"),
+                    &log_level_parameter),
+        Switch::new("server log file name", None, Some("log-file"), &log_file_parameter),
+        Switch::new("server log in JSON format", None, Some("log-json"), &log_json_parameter),
+        Switch::new("OTLP/HTTP endpoint for command spans", None, Some("otlp-endpoint"), &otlp_endpoint_parameter),
+        Switch::new("server configuration file name", None, Some("config"), &config_file_parameter),
+        Switch::new("run the server in the background (yes or no)", None, Some("daemonize"), &daemonize_parameter),
+        Switch::new("server bind address", None, Some("bind"), &bind_parameter),
+        Switch::new("time in seconds for client connections to finish on server shutdown", None,
+                    Some("shutdown-timeout"), &shutdown_timeout_parameter),
+        Switch::new("server process id file name", None, Some("pidfile"), &pid_file_parameter),
     ];
     let mut arguments = Arguments::new("cache", &switches);
     if let Err(e) = arguments.build(args().skip(1).collect()) {
@@ -86,36 +191,173 @@ fn main() -> Result<(), Error> {
             println!("Invalid expiration value");
             return Ok(());
         }
-        let types_string = types_parameter.get_value();
-        let types: Vec<Option<BenchmarkCommand>> = types_string.split(',')
-            .map(|s|{
-                match s {
-                    "get" => Some(Get),
-                    "set" => Some(Set),
-                    "setpx" => Some(SetPX),
-                    "ping" => Some(Ping),
-                    _ => None
-                }
-            }).collect();
-        if types.len() != 4 || types[0].is_none() || types[1].is_none() || types[2].is_none() || types[3].is_none() {
-            println!("Invalid request types value");
+        let pipeline = pipeline_parameter.get_value();
+        if pipeline <= 0 {
+            println!("Invalid pipeline value");
+            return Ok(());
+        }
+        let value_size = value_size_parameter.get_value();
+        if value_size < 0 {
+            println!("Invalid value size");
             return Ok(());
         }
+        let payload = match Payload::parse(&payload_parameter.get_value()) {
+            Some(p) => p,
+            None => {
+                println!("Invalid payload value");
+                return Ok(());
+            }
+        };
+        let fields = fields_parameter.get_value();
+        if fields <= 0 {
+            println!("Invalid fields value");
+            return Ok(());
+        }
+        let (warmup, duration) = match (parse_seconds(&warmup_parameter.get_value()),
+                                        parse_seconds(&duration_parameter.get_value())) {
+            (Some(w), Some(d)) => (w, d),
+            _ => {
+                println!("Invalid warmup or duration value");
+                return Ok(());
+            }
+        };
+        let regression_threshold = match regression_threshold_parameter.get_value().parse::<f64>() {
+            Ok(t) if t >= 0.0 => t,
+            _ => {
+                println!("Invalid regression threshold value");
+                return Ok(());
+            }
+        };
+        let save_baseline = Some(save_baseline_parameter.get_value()).filter(|n| !n.is_empty());
+        let compare_baseline = Some(compare_baseline_parameter.get_value()).filter(|n| !n.is_empty());
+        let output_format = output_parameter.get_value();
+        let output = if output_format.is_empty() {
+            None
+        } else {
+            match ResultFormat::parse(&output_format) {
+                Some(f) => {
+                    let file_name = output_file_parameter.get_value();
+                    let file_name = if file_name.is_empty() { format!("benchmark.{}", output_format) } else { file_name };
+                    Some((f, file_name))
+                }
+                None => {
+                    println!("Invalid output format");
+                    return Ok(());
+                }
+            }
+        };
+        let types_string = types_parameter.get_value();
+        let types = match parse_command_mix(&types_string) {
+            Some(t) => t,
+            None => {
+                println!("Invalid request types value");
+                return Ok(());
+            }
+        };
         let host = host_parameter.get_value();
+        // comma separated host or host:port list
+        let unix_socket = unix_socket_parameter.get_value();
+        let addresses: Vec<String> = if unix_socket.is_empty() {
+            host.split(',')
+                .map(|h| if h.contains(':') { h.to_string() } else { format!("{}:{}", h, p) })
+                .collect()
+        } else {
+            vec![format!("unix:{}", unix_socket)]
+        };
         if verbose {
-            println!("Port = {}\nHost = {}\nKeys= {}\nRequests per thread = {}\nThreads = {}\nExpiration = {} ms\nRequest types = {}",
-                     port, host, keys, requests, threads, expiration, types_string);
+            println!("Port = {}\nHost = {}\nKeys= {}\nRequests per thread = {}\nThreads = {}\nExpiration = {} ms\nRequest types = {}\nPipeline = {}\nValue size = {}\nPayload = {:?}\nFields = {}\nWarmup = {:?}\nDuration = {:?}",
+                     port, host, keys, requests, threads, expiration, types_string, pipeline, value_size, payload,
+                     fields, warmup, duration);
         }
-        benchmark_mode(p, host, keys as usize, requests as usize,
-                       threads as usize, expiration as usize,
-                       [types[0].as_ref().unwrap().clone(), types[1].as_ref().unwrap().clone(),
-                           types[2].as_ref().unwrap().clone(), types[3].as_ref().unwrap().clone()])
+        benchmark_mode(BenchmarkSettings {
+            addresses,
+            keys: keys as usize,
+            requests: requests as usize,
+            threads: threads as usize,
+            expiration: expiration as usize,
+            types,
+            pipeline: pipeline as usize,
+            value_size: value_size as usize,
+            fields: fields as usize,
+            payload,
+            output,
+            save_baseline,
+            compare_baseline,
+            regression_threshold,
+            warmup,
+            duration,
+        })
+    } else if !script_parameter.get_value().is_empty() {
+        script_mode(script_parameter.get_value(), p, host_parameter.get_value())
+    } else if latency_parameter.get_value() || latency_history_parameter.get_value() {
+        let history_interval = if latency_history_parameter.get_value() {
+            match parse_seconds(&interval_parameter.get_value()) {
+                Some(i) if i.is_zero() => Some(Duration::from_secs(15)),
+                Some(i) => Some(i),
+                None => {
+                    println!("Invalid interval value");
+                    return Ok(());
+                }
+            }
+        } else {
+            None
+        };
+        latency_mode(p, host_parameter.get_value(), history_interval)
+    } else if bigkeys_parameter.get_value() {
+        bigkeys_mode(p, host_parameter.get_value())
+    } else if memkeys_parameter.get_value() || hotkeys_parameter.get_value() {
+        top_keys_mode(p, host_parameter.get_value(), hotkeys_parameter.get_value())
+    } else if scan_parameter.get_value() {
+        scan_mode(pattern_parameter.get_value(), p, host_parameter.get_value())
+    } else if pipe_parameter.get_value() {
+        pipe_mode(p, host_parameter.get_value())
     } else if client_parameter.get_value() {
         let host= host_parameter.get_value();
+        let (interval, connect_timeout, read_timeout) = match (parse_seconds(&interval_parameter.get_value()),
+                                                               parse_seconds(&connect_timeout_parameter.get_value()),
+                                                               parse_seconds(&read_timeout_parameter.get_value())) {
+            (Some(i), Some(c), Some(r)) => (i, c, r),
+            _ => {
+                println!("Invalid interval or timeout value");
+                return Ok(());
+            }
+        };
+        let retries = retries_parameter.get_value();
+        if retries < 0 {
+            println!("Invalid retries value");
+            return Ok(());
+        }
+        let repeat = repeat_parameter.get_value();
+        let format = if json_parameter.get_value() {
+            OutputFormat::Json
+        } else if csv_parameter.get_value() {
+            OutputFormat::Csv
+        } else if raw_parameter.get_value() {
+            OutputFormat::Raw
+        } else {
+            OutputFormat::Standard
+        };
         if verbose {
-            println!("Port = {}\nHost = {}", port, host);
+            println!("Port = {}\nHost = {}\nRepeat = {}\nInterval = {:?}\nOutput format = {:?}\nConnect timeout = {:?}\nRead timeout = {:?}\nRetries = {}",
+                     port, host, repeat, interval, format, connect_timeout, read_timeout, retries);
         }
-        client_mode(arguments.get_other_arguments(), p, host)
+        let other_arguments = arguments.get_other_arguments();
+        if other_arguments.is_empty() {
+            println!("No commands specified");
+            return Ok(());
+        }
+        // zero timeouts - no timeout
+        let mut connection = Connection::connect_timeout(&format!("{}:{}", host, p),
+                                                         Some(connect_timeout).filter(|t| !t.is_zero()))?;
+        connection.set_read_timeout(Some(read_timeout).filter(|t| !t.is_zero()))?;
+        connection.set_retries(retries as usize);
+        let password = password_parameter.get_value();
+        if !password.is_empty() {
+            let user = user_parameter.get_value();
+            let user = if user.is_empty() { None } else { Some(user.as_bytes()) };
+            connection.auth(user, password.as_bytes())?;
+        }
+        client_mode(other_arguments, connection, repeat, interval, format)
     } else {
         let max_memory = max_memory_parameter.get_value();
         if max_memory <= 0 {
@@ -123,60 +365,366 @@ fn main() -> Result<(), Error> {
             return Ok(());
         }
         let vector_size = vector_size_parameter.get_value();
-        if vector_size <= 0 {
+        if vector_size < 0 {
             println!("Invalid vector size value");
             return Ok(());
         }
-        let vs = vector_size as usize;
-        let hash_builder = create_hash_builder(hash_type_parameter.get_value(), vs)?;
-        if verbose {
-            println!("Port = {}\nMaximum memory = {}\nVector size = {}\nHash builder = {}", port,
-                     max_memory, vector_size, hash_builder.get_name());
+        let compression_threshold = compression_threshold_parameter.get_value();
+        if compression_threshold < 0 {
+            println!("Invalid compression threshold value");
+            return Ok(());
+        }
+        let intern_threshold = intern_threshold_parameter.get_value();
+        if intern_threshold < 0 {
+            println!("Invalid intern threshold value");
+            return Ok(());
         }
-        server_mode(verbose, max_memory as usize, p, vs, hash_builder)
+        let defrag_interval = defrag_interval_parameter.get_value();
+        if defrag_interval < 0 {
+            println!("Invalid defragmentation interval value");
+            return Ok(());
+        }
+        let log_level = log_level_parameter.get_value();
+        let log_level = if log_level.is_empty() {
+            if verbose { LevelFilter::Debug } else { LevelFilter::Info }
+        } else {
+            match LevelFilter::from_str(&log_level) {
+                Ok(l) => l,
+                Err(_e) => {
+                    println!("Invalid log level value");
+                    return Ok(());
+                }
+            }
+        };
+        let bind_address = match IpAddr::from_str(&bind_parameter.get_value()) {
+            Ok(a) => a,
+            Err(_e) => {
+                println!("Invalid bind address value");
+                return Ok(());
+            }
+        };
+        let shutdown_timeout = match parse_seconds(&shutdown_timeout_parameter.get_value()) {
+            Some(t) => t,
+            None => {
+                println!("Invalid shutdown timeout value");
+                return Ok(());
+            }
+        };
+        let background = match daemonize_parameter.get_value().as_str() {
+            "yes" => true,
+            "no" => false,
+            _ => {
+                println!("Invalid daemonize value");
+                return Ok(());
+            }
+        };
+        let log_file = log_file_parameter.get_value();
+        #[cfg(unix)]
+        if background {
+            daemonize()?;
+        }
+        #[cfg(not(unix))]
+        if background {
+            println!("Daemonize mode is supported on unix only");
+            return Ok(());
+        }
+        init_logger(log_level, if log_json_parameter.get_value() { LogFormat::Json } else { LogFormat::Text },
+                    if log_file.is_empty() { None } else { Some(&log_file) })?;
+        let mut builder = CacheServerBuilder::new()
+            .bind(bind_address)
+            .port(p)
+            .max_memory(max_memory as usize)
+            .key_maps(vector_size as usize)
+            .hash_builder(&hash_type_parameter.get_value())
+            .consistent_hashing(consistent_hashing_parameter.get_value())
+            .verbose(verbose)
+            .allocator_stats(allocator_memory_parameter.get_value())
+            .compression_threshold(compression_threshold as usize)
+            .integer_encoding(!disable_integer_encoding_parameter.get_value())
+            .intern_threshold(intern_threshold as usize)
+            .defrag_interval(defrag_interval as u64)
+            .shutdown_timeout(shutdown_timeout);
+        let otlp_endpoint = otlp_endpoint_parameter.get_value();
+        if !otlp_endpoint.is_empty() {
+            builder = builder.otlp_endpoint(&otlp_endpoint);
+        }
+        let config_file = config_file_parameter.get_value();
+        if !config_file.is_empty() {
+            builder = builder.config_file(&config_file);
+        }
+        let pid_file = pid_file_parameter.get_value();
+        server_mode(builder, if pid_file.is_empty() { None } else { Some(&pid_file) })
     }
 }
 
-fn client_mode(other_arguments: &Vec<String>, port: u16, host: String) -> Result<(), Error> {
-    if other_arguments.len() != 0 {
-        let data = resp_encode_strings(other_arguments);
-        let mut connection = TcpStream::connect(format!("{}:{}", host, port))?;
-        connection.write_all(data.as_slice())?;
-        let mut buffer = [0; 10000];
-        let amt = connection.read(&mut buffer)?;
-        match String::from_utf8(Vec::from(&buffer[0..amt])) {
-            Ok(s) => print!("{}", s),
-            Err(e) => println!("{}", e)
-        };
+// the command is sent repeat times (forever when repeat is negative) with interval between repetitions
+fn client_mode(other_arguments: &[String], mut connection: Connection, repeat: isize, interval: Duration,
+               format: OutputFormat) -> Result<(), Error> {
+    let args: Vec<&[u8]> = other_arguments.iter().map(|a| a.as_bytes()).collect();
+    let name = other_arguments[0].to_ascii_lowercase();
+    if name == "subscribe" || name == "psubscribe" {
+        if format == OutputFormat::Standard {
+            println!("Reading messages... (press Ctrl-C to quit)");
+        }
+        return connection.subscribe(&args, &mut |message| {
+            println!("{}", format_reply_as(&message, format));
+            true
+        });
+    }
+    let mut i = 0;
+    while repeat < 0 || i < repeat {
+        if i > 0 && !interval.is_zero() {
+            thread::sleep(interval);
+        }
+        let reply = connection.command(&args)?;
+        match reply {
+            // info is printed as is, like redis-cli does
+            RespBinaryString(s) if format == OutputFormat::Standard && name == "info" =>
+                print!("{}", String::from_utf8_lossy(&s)),
+            _ => println!("{}", format_reply_as(&reply, format))
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+// seconds, can be fractional, None - invalid or negative value
+fn parse_seconds(value: &str) -> Option<Duration> {
+    match value.parse::<f64>() {
+        Ok(v) if v >= 0.0 => Some(Duration::from_secs_f64(v)),
+        _ => None
+    }
+}
+
+fn scan_mode(pattern: String, port: u16, host: String) -> Result<(), Error> {
+    let mut connection = Connection::connect(&format!("{}:{}", host, port))?;
+    let pattern = if pattern.is_empty() { None } else { Some(pattern.as_bytes()) };
+    connection.scan(pattern, &mut |key| println!("{}", String::from_utf8_lossy(&key)))
+}
+
+#[derive(Default)]
+struct TypeStats {
+    keys: usize,
+    memory: usize,
+    biggest_key: Vec<u8>,
+    biggest_memory: usize,
+}
+
+// key sizes are taken from MEMORY USAGE, keys are queried with a separate connection while scanning
+fn bigkeys_mode(port: u16, host: String) -> Result<(), Error> {
+    let address = format!("{}:{}", host, port);
+    let mut scanner = Connection::connect(&address)?;
+    let mut connection = Connection::connect(&address)?;
+    let mut stats: BTreeMap<String, TypeStats> = BTreeMap::new();
+    let mut keys = 0;
+    let mut key_length = 0;
+    let mut error = None;
+    println!("# Scanning the entire keyspace to find biggest keys");
+    scanner.scan(None, &mut |key| {
+        if error.is_some() {
+            return;
+        }
+        match connection.pipeline(&[vec![b"type", &key], vec![b"memory", b"usage", &key]]) {
+            Ok(replies) => {
+                // the key can be removed between scan and type commands
+                if let [RespString(key_type), RespInteger(memory)] = replies.as_slice() {
+                    let key_type = String::from_utf8_lossy(key_type).to_string();
+                    let memory = *memory as usize;
+                    keys += 1;
+                    key_length += key.len();
+                    let s = stats.entry(key_type.clone()).or_default();
+                    s.keys += 1;
+                    s.memory += memory;
+                    if memory > s.biggest_memory {
+                        println!("Biggest {} found so far '{}' with {} bytes",
+                                 key_type, format_reply(&RespBinaryString(key.clone())), memory);
+                        s.biggest_memory = memory;
+                        s.biggest_key = key;
+                    }
+                }
+            }
+            Err(e) => error = Some(e)
+        }
+    })?;
+    if let Some(e) = error {
+        return Err(e);
+    }
+    println!("\n-------- summary -------\n");
+    println!("Sampled {} keys in the keyspace!", keys);
+    println!("Total key length in bytes is {} (avg len {:.2})\n", key_length,
+             if keys == 0 { 0.0 } else { key_length as f64 / keys as f64 });
+    for (key_type, s) in &stats {
+        println!("Biggest {} found '{}' has {} bytes", key_type,
+                 format_reply(&RespBinaryString(s.biggest_key.clone())), s.biggest_memory);
+    }
+    println!();
+    for (key_type, s) in &stats {
+        println!("{} {}s with {} bytes ({:.2}% of keys, avg size {:.2})", s.keys, key_type, s.memory,
+                 s.keys as f64 * 100.0 / keys as f64, s.memory as f64 / s.keys as f64);
+    }
+    Ok(())
+}
+
+const TOP_KEYS: usize = 16;
+
+// keys with the biggest MEMORY USAGE or, when hot is set, with the biggest OBJECT FREQ counter
+fn top_keys_mode(port: u16, host: String, hot: bool) -> Result<(), Error> {
+    let address = format!("{}:{}", host, port);
+    let mut scanner = Connection::connect(&address)?;
+    let mut connection = Connection::connect(&address)?;
+    let (name, unit, command): (&str, &str, [&[u8]; 2]) = if hot {
+        ("Hot", "counter", [b"object", b"freq"])
     } else {
-        println!("No commands specified");
+        ("Big", "bytes", [b"memory", b"usage"])
+    };
+    // sorted by value in descending order
+    let mut top: Vec<(isize, Vec<u8>)> = Vec::new();
+    let mut keys = 0;
+    let mut error = None;
+    println!("# Scanning the entire keyspace to find {} keys", name.to_lowercase());
+    scanner.scan(None, &mut |key| {
+        if error.is_some() {
+            return;
+        }
+        match connection.command(&[command[0], command[1], &key]) {
+            Ok(RespInteger(v)) => {
+                keys += 1;
+                if top.len() < TOP_KEYS || v > top[top.len() - 1].0 {
+                    println!("{} key found so far '{}' with {} {}", name,
+                             format_reply(&RespBinaryString(key.clone())), v, unit);
+                    let idx = top.partition_point(|(t, _k)| *t >= v);
+                    top.insert(idx, (v, key));
+                    top.truncate(TOP_KEYS);
+                }
+            }
+            Ok(RespError(e)) => error = Some(Error::other(String::from_utf8_lossy(&e).to_string())),
+            // the key can be removed between scan and the command
+            Ok(_) => {}
+            Err(e) => error = Some(e)
+        }
+    })?;
+    if let Some(e) = error {
+        return Err(e);
     }
+    println!("\n-------- summary -------\n");
+    println!("Sampled {} keys in the keyspace!", keys);
+    for (v, key) in &top {
+        println!("{} key '{}' has {} {}", name, format_reply(&RespBinaryString(key.clone())), v, unit);
+    }
+    Ok(())
+}
+
+const LATENCY_SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+fn format_latency(stats: &mut LatencyStats) -> String {
+    format!("min: {:.2}, max: {:.2}, avg: {:.2}, p50: {:.2}, p99: {:.2} ({} samples)",
+            stats.min() as f64 / 1000.0, stats.max() as f64 / 1000.0, stats.avg() / 1000.0,
+            stats.percentile(50.0) as f64 / 1000.0, stats.percentile(99.0) as f64 / 1000.0, stats.count())
+}
+
+// sends PING every 10 ms until interrupted, latencies are printed in milliseconds
+// history_interval - statistics are printed on a new line and reset after every interval
+fn latency_mode(port: u16, host: String, history_interval: Option<Duration>) -> Result<(), Error> {
+    let mut connection = Connection::connect(&format!("{}:{}", host, port))?;
+    let mut stats = LatencyStats::new();
+    let mut interval_start = Instant::now();
+    loop {
+        let start = Instant::now();
+        connection.ping()?;
+        stats.add(start.elapsed());
+        match history_interval {
+            Some(interval) => if interval_start.elapsed() >= interval {
+                println!("{} -- {:.2} seconds range", format_latency(&mut stats), interval.as_secs_f64());
+                stats.clear();
+                interval_start = Instant::now();
+            },
+            None => {
+                print!("\r{}", format_latency(&mut stats));
+                stdout().flush()?;
+            }
+        }
+        thread::sleep(LATENCY_SAMPLE_INTERVAL);
+    }
+}
+
+fn pipe_mode(port: u16, host: String) -> Result<(), Error> {
+    let summary = pipe(&format!("{}:{}", host, port), &mut stdin().lock())?;
+    println!("All data transferred. commands: {}, replies: {}, errors: {}",
+             summary.commands, summary.replies, summary.errors);
     Ok(())
 }
 
-fn server_mode(verbose: bool, max_memory: usize, port: u16, vector_size: usize,
-               hash_builder: Box<dyn HashBuilder + Sync + Send>) -> Result<(), Error> {
-    let common_data = Arc::new(build_common_data(verbose, max_memory, vector_size, hash_builder));
-    let c = common_data.clone();
+const SCRIPT_BATCH_SIZE: usize = 1000;
+
+// empty lines and lines starting with # are skipped, failed lines are reported with their numbers
+fn script_mode(file_name: String, port: u16, host: String) -> Result<(), Error> {
+    let reader: Box<dyn BufRead> = if file_name == "-" {
+        Box::new(stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(file_name)?))
+    };
+    let mut connection = Connection::connect(&format!("{}:{}", host, port))?;
+    let mut batch = Vec::new();
+    let mut commands = 0;
+    let mut errors = 0;
+    let mut lines = reader.lines().enumerate().peekable();
+    while let Some((idx, line)) = lines.next() {
+        let line = line?;
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            batch.push((idx + 1, split_command_line(trimmed)));
+        }
+        if batch.len() == SCRIPT_BATCH_SIZE || (lines.peek().is_none() && !batch.is_empty()) {
+            let pipeline: Vec<Vec<&[u8]>> = batch.iter()
+                .filter_map(|(_n, args)| args.as_ref().ok())
+                .map(|args| args.iter().map(|a| a.as_slice()).collect())
+                .collect();
+            let mut replies = connection.pipeline(&pipeline)?.into_iter();
+            for (n, args) in &batch {
+                match args {
+                    Ok(_args) => {
+                        commands += 1;
+                        if let Some(reply @ RespError(_)) = replies.next() {
+                            println!("line {}: {}", n, format_reply(&reply));
+                            errors += 1;
+                        }
+                    }
+                    Err(e) => {
+                        println!("line {}: {}", n, e);
+                        errors += 1;
+                    }
+                }
+            }
+            batch.clear();
+        }
+    }
+    println!("commands: {}, errors: {}", commands, errors);
+    Ok(())
+}
+
+fn server_mode(builder: CacheServerBuilder, pid_file: Option<&str>) -> Result<(), Error> {
+    let server = builder.start().inspect_err(|e| error!("Server start error {}", e))?;
+    if let Some(file_name) = pid_file {
+        std::fs::write(file_name, format!("{}\n", std::process::id()))?;
+    }
+    let address = server.address();
+    let c = server.common_data();
+    // Ctrl-C, SIGTERM
     ctrlc::set_handler(move || {
-        c.exit_flag.store(true, Ordering::Relaxed);
         //stopping the server
-        TcpStream::connect(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port)).unwrap();
+        #[cfg(unix)]
+        sd_notify("STOPPING=1");
+        server_stop(address, &c);
     }).unwrap();
-    server_start(port, common_data.clone())?;
-    println!("Waiting for all threads to be finished...");
-    let v: Vec<usize> = common_data.threads.read().unwrap().iter()
-        .map(|(k, _v)|*k)
-        .collect();
-    for idx in v  {
-        if let Some(t) = common_data.threads.read().unwrap().get(&idx) {
-            let _ = t.lock().unwrap().shutdown(Shutdown::Both);
-        }
+    #[cfg(unix)]
+    {
+        reload_on_sighup(server.common_data());
+        sd_notify("READY=1");
     }
-    let d = Duration::from_millis(500);
-    while common_data.threads.read().unwrap().len() > 0 {
-        thread::sleep(d);
+    server.wait()?;
+    if let Some(file_name) = pid_file {
+        let _ = std::fs::remove_file(file_name);
     }
-    println!("Exiting...");
+    info!("Exiting...");
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+use rand::Rng;
+use rand::seq::IteratorRandom;
+use crate::glob::glob_match;
+
+#[derive(Clone, Copy)]
+pub enum SetOperation {
+    Intersection,
+    Union,
+    Difference,
+}
+
+// hash table entry and vector header of one member
+const MEMBER_OVERHEAD: usize = 16;
+
+// SRANDMEMBER with a negative count: the reply is built under the key map lock, bigger counts are rejected
+pub const MAX_REPEATED_MEMBERS: usize = 1 << 20;
+// the smallest part of a set returned by one SSCAN call
+const SCAN_MIN_PART: usize = 16;
+
+// members of a set value and the memory used by them, the size is kept up to date,
+// so memory accounting does not have to iterate the members
+#[derive(Default)]
+pub struct MemberSet {
+    members: HashSet<Vec<u8>>,
+    size: usize,
+}
+
+impl MemberSet {
+    pub fn new() -> MemberSet {
+        MemberSet::default()
+    }
+
+    // returns true when the member was added
+    pub fn insert(&mut self, member: &[u8]) -> bool {
+        if self.members.contains(member) {
+            return false;
+        }
+        self.size += member.len() + MEMBER_OVERHEAD;
+        self.members.insert(member.to_vec())
+    }
+
+    // returns true when the member was removed
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        if self.members.remove(member) {
+            self.size -= member.len() + MEMBER_OVERHEAD;
+            return true;
+        }
+        false
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        self.members.contains(member)
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // applies the operation to the result of the previous steps
+    pub fn apply(&self, operation: SetOperation, members: &mut HashSet<Vec<u8>>) {
+        match operation {
+            SetOperation::Intersection => members.retain(|m| self.members.contains(m)),
+            SetOperation::Union => members.extend(self.members.iter().cloned()),
+            SetOperation::Difference => members.retain(|m| !self.members.contains(m))
+        }
+    }
+
+    // removes up to count random members
+    pub fn pop_random(&mut self, count: usize) -> Vec<Vec<u8>> {
+        // choose_multiple allocates count elements, the count comes from the client
+        let count = count.min(self.members.len());
+        let selected: Vec<Vec<u8>> = self.members.iter().choose_multiple(&mut rand::thread_rng(), count)
+            .into_iter().cloned().collect();
+        selected.iter().for_each(|m| { self.remove(m); });
+        selected
+    }
+
+    // SRANDMEMBER: count > 0 - up to count distinct members, count < 0 - -count members that can repeat,
+    // -count is limited by MAX_REPEATED_MEMBERS
+    pub fn random(&self, count: isize) -> Vec<Vec<u8>> {
+        let mut rng = rand::thread_rng();
+        if count >= 0 {
+            return self.members.iter().choose_multiple(&mut rng, (count as usize).min(self.members.len()))
+                .into_iter().cloned().collect();
+        }
+        if self.members.is_empty() {
+            return Vec::new();
+        }
+        let members: Vec<&Vec<u8>> = self.members.iter().collect();
+        (0..count.unsigned_abs().min(MAX_REPEATED_MEMBERS)).map(|_i| members[rng.gen_range(0..members.len())].clone()).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.members.iter()
+    }
+
+    // members can be missed or returned twice when the set is resized, returns the next cursor, 0 - the iteration is finished,
+    // every call walks cursor members again, so count is raised to 1/SCAN_MIN_PART of the set to keep the whole
+    // iteration within SCAN_MIN_PART + 1 calls and O(SCAN_MIN_PART * set size)
+    pub fn scan(&self, cursor: usize, count: usize, pattern: Option<&[u8]>, members: &mut Vec<Vec<u8>>) -> usize {
+        let count = count.max(self.members.len() / SCAN_MIN_PART);
+        let mut visited = 0;
+        for member in self.members.iter().skip(cursor).take(count) {
+            visited += 1;
+            if pattern.map(|p| glob_match(p, member)).unwrap_or(true) {
+                members.push(member.clone());
+            }
+        }
+        if cursor + visited >= self.members.len() {
+            0
+        } else {
+            cursor + visited
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::member_set::{MemberSet, SetOperation, MAX_REPEATED_MEMBERS, MEMBER_OVERHEAD};
+
+    #[test]
+    fn test_member_set() {
+        let mut set = MemberSet::new();
+        assert!(set.insert(b"a"));
+        assert!(set.insert(b"bc"));
+        assert!(!set.insert(b"a"));
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.size(), 3 + 2 * MEMBER_OVERHEAD);
+        assert!(set.contains(b"bc"));
+        assert!(set.remove(b"bc"));
+        assert!(!set.remove(b"bc"));
+        assert_eq!(set.size(), 1 + MEMBER_OVERHEAD);
+
+        (0..25).for_each(|i| { set.insert(format!("m{}", i).as_bytes()); });
+        let mut members = Vec::new();
+        let mut cursor = 0;
+        loop {
+            cursor = set.scan(cursor, 10, Some(b"m*"), &mut members);
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(members.len(), 25);
+
+        assert_eq!(set.random(5).len(), 5);
+        assert_eq!(set.random(100).len(), 26);
+        assert_eq!(set.random(-100).len(), 100);
+        assert_eq!(set.random(isize::MAX).len(), 26);
+        assert_eq!(set.random(isize::MIN).len(), MAX_REPEATED_MEMBERS);
+        let popped = set.pop_random(3);
+        assert_eq!(popped.len(), 3);
+        assert_eq!(set.len(), 23);
+        assert!(popped.iter().all(|m| !set.contains(m)));
+        let mut small = MemberSet::new();
+        small.insert(b"a");
+        assert_eq!(small.pop_random(usize::MAX), vec![b"a".to_vec()]);
+        assert!(small.is_empty());
+        let set_size = set.iter().map(|m| m.len() + MEMBER_OVERHEAD).sum::<usize>();
+        assert_eq!(set.size(), set_size);
+
+        // "a" could be popped
+        set.insert(b"a");
+        let mut other = MemberSet::new();
+        other.insert(b"a");
+        other.insert(b"x");
+        let mut result: HashSet<Vec<u8>> = set.iter().cloned().collect();
+        other.apply(SetOperation::Intersection, &mut result);
+        assert_eq!(result, HashSet::from([b"a".to_vec()]));
+        other.apply(SetOperation::Union, &mut result);
+        assert_eq!(result.len(), 2);
+        set.apply(SetOperation::Difference, &mut result);
+        assert_eq!(result, HashSet::from([b"x".to_vec()]));
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+// number of samples used to calculate instantaneous_ops_per_sec
+#[cfg(any(feature = "server", test))]
+const OPS_SAMPLES: usize = 16;
+
+// connection, traffic and command counters reported by INFO stats
+pub struct NetworkStats {
+    connections_received: AtomicU64,
+    rejected_connections: AtomicU64,
+    net_input_bytes: AtomicU64,
+    net_output_bytes: AtomicU64,
+    commands_processed: AtomicU64,
+    // (sample time, commands_processed), the oldest sample first
+    ops_samples: Mutex<VecDeque<(Instant, u64)>>,
+}
+
+impl NetworkStats {
+    pub fn new() -> NetworkStats {
+        NetworkStats {
+            connections_received: AtomicU64::new(0),
+            rejected_connections: AtomicU64::new(0),
+            net_input_bytes: AtomicU64::new(0),
+            net_output_bytes: AtomicU64::new(0),
+            commands_processed: AtomicU64::new(0),
+            ops_samples: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    #[cfg(any(feature = "server", test))]
+    pub fn connection_received(&self) {
+        self.connections_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(any(feature = "server", test))]
+    pub fn connection_rejected(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(any(feature = "server", test))]
+    pub fn add_input(&self, bytes: usize) {
+        self.net_input_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_output(&self, bytes: usize) {
+        self.net_output_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn command_processed(&self) {
+        self.commands_processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(any(feature = "server", test))]
+    // called periodically by the stats job, keeps the last OPS_SAMPLES samples
+    pub fn sample(&self, now: Instant) {
+        let mut samples = self.ops_samples.lock().unwrap();
+        if samples.len() == OPS_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back((now, self.commands_processed.load(Ordering::Relaxed)));
+    }
+
+    // average number of commands per second over the sample window
+    pub fn ops_per_sec(&self) -> u64 {
+        let samples = self.ops_samples.lock().unwrap();
+        match (samples.front(), samples.back()) {
+            (Some((t1, c1)), Some((t2, c2))) if t2 > t1 => {
+                (c2.saturating_sub(*c1) as f64 / t2.duration_since(*t1).as_secs_f64()) as u64
+            }
+            _ => 0
+        }
+    }
+
+    pub fn reset(&self) {
+        self.connections_received.store(0, Ordering::Relaxed);
+        self.rejected_connections.store(0, Ordering::Relaxed);
+        self.net_input_bytes.store(0, Ordering::Relaxed);
+        self.net_output_bytes.store(0, Ordering::Relaxed);
+        self.commands_processed.store(0, Ordering::Relaxed);
+        self.ops_samples.lock().unwrap().clear();
+    }
+
+    pub fn build_info(&self, info: &mut String) {
+        info.push_str(&format!("total_connections_received:{}\r\n", self.connections_received.load(Ordering::Relaxed)));
+        info.push_str(&format!("total_commands_processed:{}\r\n", self.commands_processed.load(Ordering::Relaxed)));
+        info.push_str(&format!("instantaneous_ops_per_sec:{}\r\n", self.ops_per_sec()));
+        info.push_str(&format!("total_net_input_bytes:{}\r\n", self.net_input_bytes.load(Ordering::Relaxed)));
+        info.push_str(&format!("total_net_output_bytes:{}\r\n", self.net_output_bytes.load(Ordering::Relaxed)));
+        info.push_str(&format!("rejected_connections:{}\r\n", self.rejected_connections.load(Ordering::Relaxed)));
+    }
+}
+
+impl Default for NetworkStats {
+    fn default() -> Self {
+        NetworkStats::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::network_stats::NetworkStats;
+
+    #[test]
+    fn test_network_stats() {
+        let stats = NetworkStats::new();
+        stats.connection_received();
+        stats.add_input(10);
+        stats.add_output(5);
+        let start = Instant::now();
+        stats.sample(start);
+        (0..50).for_each(|_i| stats.command_processed());
+        stats.sample(start + Duration::from_millis(500));
+        assert_eq!(stats.ops_per_sec(), 100);
+        let mut info = String::new();
+        stats.build_info(&mut info);
+        assert_eq!(info, "total_connections_received:1\r\ntotal_commands_processed:50\r\ninstantaneous_ops_per_sec:100\r\n\
+total_net_input_bytes:10\r\ntotal_net_output_bytes:5\r\nrejected_connections:0\r\n");
+        stats.reset();
+        assert_eq!(stats.ops_per_sec(), 0);
+    }
+}
@@ -0,0 +1,232 @@
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use log::warn;
+use rand::Rng;
+use crate::command_table::find_command;
+use crate::resp_parser::RespToken;
+use crate::resp_parser::RespToken::{RespArray, RespBinaryString};
+
+// spans waiting for export, new spans are dropped when the queue is full
+const QUEUE_SIZE: usize = 10000;
+const BATCH_SIZE: usize = 512;
+const BATCH_TIMEOUT: Duration = Duration::from_secs(1);
+// connect, read and write timeout of a collector request
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// one command execution
+pub struct Span {
+    pub command: &'static str,
+    pub keys: usize,
+    // key map of the first key
+    pub shard: Option<usize>,
+    pub start: SystemTime,
+    pub duration: Duration,
+    pub failed: bool,
+}
+
+// sends spans to an OTLP/HTTP collector (JSON encoding) from a background thread
+pub struct SpanExporter {
+    sender: SyncSender<Span>,
+    // spans dropped since the last export, reported by the export thread
+    dropped: Arc<AtomicUsize>,
+}
+
+impl SpanExporter {
+    // endpoint - http://host:port[/path], the default path is /v1/traces
+    pub fn start(endpoint: &str) -> Result<SpanExporter, Error> {
+        let (address, path) = parse_endpoint(endpoint)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid OTLP endpoint, expected http://host:port[/path]"))?;
+        let (sender, receiver) = sync_channel(QUEUE_SIZE);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let job_dropped = dropped.clone();
+        thread::spawn(move || export_job(receiver, job_dropped, address, path));
+        Ok(SpanExporter { sender, dropped })
+    }
+
+    // dropped spans are only counted here, so a slow collector does not flood the log
+    pub fn export(&self, span: Span) {
+        if let Err(TrySendError::Full(_s)) = self.sender.try_send(span) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// number of keys and the first key of the command, key positions are taken from the command table
+pub fn command_keys<'a>(command: &str, token: &'a RespToken) -> (usize, Option<&'a [u8]>) {
+    let args = match token {
+        RespArray(v) => v.as_slice(),
+        _ => return (0, None)
+    };
+    let info = match find_command(command.as_bytes()) {
+        Some(info) if info.first_key > 0 => info,
+        _ => return (0, None)
+    };
+    let first = info.first_key;
+    let len = args.len() as isize;
+    let last = if info.last_key < 0 { len + info.last_key } else { info.last_key.min(len - 1) };
+    if last < first {
+        return (0, None);
+    }
+    let keys = &args[first as usize..=last as usize];
+    let first = match keys.first() {
+        Some(RespBinaryString(k)) => Some(k.as_slice()),
+        _ => None
+    };
+    (keys.len().div_ceil(info.step as usize), first)
+}
+
+fn parse_endpoint(endpoint: &str) -> Option<(String, String)> {
+    let rest = endpoint.strip_prefix("http://")?;
+    let (address, path) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, "/v1/traces")
+    };
+    if address.is_empty() {
+        return None;
+    }
+    let address = if address.contains(':') { address.to_string() } else { format!("{}:4318", address) };
+    Some((address, path.to_string()))
+}
+
+fn export_job(receiver: Receiver<Span>, dropped: Arc<AtomicUsize>, address: String, path: String) {
+    // the job is finished when the exporter is dropped
+    while let Ok(span) = receiver.recv() {
+        let mut spans = vec![span];
+        let deadline = Instant::now() + BATCH_TIMEOUT;
+        while spans.len() < BATCH_SIZE {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(span) => spans.push(span),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => break
+            }
+        }
+        if let Err(e) = post(&address, &path, format_spans(&spans).as_bytes()) {
+            warn!("OTLP export error {}", e);
+        }
+        let count = dropped.swap(0, Ordering::Relaxed);
+        if count > 0 {
+            warn!("OTLP span queue was full, {} spans dropped", count);
+        }
+    }
+}
+
+fn post(address: &str, path: &str, body: &[u8]) -> Result<(), Error> {
+    let socket_address = address.to_socket_addrs()?.next()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "collector address is not resolved"))?;
+    let mut stream = TcpStream::connect_timeout(&socket_address, POST_TIMEOUT)?;
+    stream.set_read_timeout(Some(POST_TIMEOUT))?;
+    stream.set_write_timeout(Some(POST_TIMEOUT))?;
+    let header = format!("POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                         path, address, body.len());
+    stream.write_all(header.as_bytes())?;
+    stream.write_all(body)?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    // HTTP/1.1 200 OK
+    match response.get(9..12) {
+        Some(status) if status[0] == b'2' => Ok(()),
+        Some(status) => Err(Error::other(format!("collector returned status {}", String::from_utf8_lossy(status)))),
+        None => Err(Error::other("invalid collector response"))
+    }
+}
+
+fn hex_id(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..bytes).map(|_i| format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+fn nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0)
+}
+
+fn string_attribute(key: &str, value: &str) -> String {
+    format!("{{\"key\":\"{}\",\"value\":{{\"stringValue\":\"{}\"}}}}", key, value)
+}
+
+fn int_attribute(key: &str, value: usize) -> String {
+    format!("{{\"key\":\"{}\",\"value\":{{\"intValue\":\"{}\"}}}}", key, value)
+}
+
+// OTLP JSON trace export request, names and values are ASCII identifiers, so they are not escaped
+fn format_spans(spans: &[Span]) -> String {
+    let spans: Vec<String> = spans.iter()
+        .map(|s| {
+            let mut attributes = vec![
+                string_attribute("db.system", "redis"),
+                string_attribute("db.operation", s.command),
+                int_attribute("db.redis.database_index", 0),
+                int_attribute("cache.key_count", s.keys),
+            ];
+            if let Some(shard) = s.shard {
+                attributes.push(int_attribute("cache.shard", shard));
+            }
+            attributes.push(int_attribute("cache.duration_us", s.duration.as_micros() as usize));
+            attributes.push(string_attribute("cache.outcome", if s.failed { "error" } else { "ok" }));
+            let start = nanos(s.start);
+            // status code 1 - ok, 2 - error, kind 2 - server
+            format!("{{\"traceId\":\"{}\",\"spanId\":\"{}\",\"name\":\"{}\",\"kind\":2,\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":[{}],\"status\":{{\"code\":{}}}}}",
+                    hex_id(16), hex_id(8), s.command, start, start + s.duration.as_nanos(), attributes.join(","),
+                    if s.failed { 2 } else { 1 })
+        })
+        .collect();
+    format!("{{\"resourceSpans\":[{{\"resource\":{{\"attributes\":[{}]}},\"scopeSpans\":[{{\"scope\":{{\"name\":\"cache\"}},\"spans\":[{}]}}]}}]}}",
+            string_attribute("service.name", "cache"), spans.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::{Duration, UNIX_EPOCH};
+    use crate::otlp::{command_keys, format_spans, parse_endpoint, Span, SpanExporter};
+    use crate::resp_parser::RespToken::{RespArray, RespBinaryString};
+
+    #[test]
+    fn test_format_spans() {
+        assert_eq!(parse_endpoint("http://collector"), Some(("collector:4318".to_string(), "/v1/traces".to_string())));
+        assert_eq!(parse_endpoint("http://localhost:1234/traces"), Some(("localhost:1234".to_string(), "/traces".to_string())));
+        assert_eq!(parse_endpoint("https://collector"), None);
+
+        let token = RespArray(vec![RespBinaryString(b"del".to_vec()), RespBinaryString(b"a".to_vec()),
+                                   RespBinaryString(b"b".to_vec())]);
+        assert_eq!(command_keys("del", &token), (2, Some(b"a".as_slice())));
+        assert_eq!(command_keys("ping", &token), (0, None));
+        assert_eq!(command_keys("mset", &token), (1, Some(b"a".as_slice())));
+        assert_eq!(command_keys("memory", &token), (1, Some(b"b".as_slice())));
+        let token = RespArray(vec![RespBinaryString(b"memory".to_vec()), RespBinaryString(b"stats".to_vec())]);
+        assert_eq!(command_keys("memory", &token), (0, None));
+
+        let span = Span { command: "get", keys: 1, shard: Some(3), start: UNIX_EPOCH + Duration::from_secs(1),
+            duration: Duration::from_micros(5), failed: true };
+        let json = format_spans(&[span]);
+        assert!(json.starts_with("{\"resourceSpans\":[{\"resource\":{\"attributes\":[{\"key\":\"service.name\",\"value\":{\"stringValue\":\"cache\"}}]}"));
+        assert!(json.contains("\"name\":\"get\",\"kind\":2,\"startTimeUnixNano\":\"1000000000\",\"endTimeUnixNano\":\"1000005000\""));
+        assert!(json.contains("{\"key\":\"cache.shard\",\"value\":{\"intValue\":\"3\"}}"));
+        assert!(json.contains("{\"key\":\"cache.outcome\",\"value\":{\"stringValue\":\"error\"}}],\"status\":{\"code\":2}}"));
+    }
+
+    #[test]
+    fn test_export() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let exporter = SpanExporter::start(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        exporter.export(Span { command: "set", keys: 1, shard: None, start: UNIX_EPOCH, duration: Duration::ZERO,
+            failed: false });
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        while !String::from_utf8_lossy(&request).ends_with("]}]}]}") {
+            let amt = stream.read(&mut buffer).unwrap();
+            assert!(amt > 0);
+            request.extend_from_slice(&buffer[..amt]);
+        }
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+        let request = String::from_utf8(request).unwrap();
+        assert!(request.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+        assert!(request.contains("\"name\":\"set\""));
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+use crate::glob::glob_match;
+use crate::resp_encoder::{resp_encode_array_len, resp_encode_binary_string, resp_encode_int};
+
+// subscriptions of one kind (channels or patterns)
+#[derive(Default)]
+struct Registry {
+    // channel or pattern -> ids of the subscribed connections
+    names: HashMap<Vec<u8>, HashSet<usize>>,
+    // connection id -> channels or patterns
+    clients: HashMap<usize, HashSet<Vec<u8>>>,
+}
+
+impl Registry {
+    fn add(&mut self, client: usize, name: &[u8]) {
+        self.names.entry(name.to_vec()).or_default().insert(client);
+        self.clients.entry(client).or_default().insert(name.to_vec());
+    }
+
+    fn remove(&mut self, client: usize, name: &[u8]) {
+        if let Some(clients) = self.names.get_mut(name) {
+            clients.remove(&client);
+            if clients.is_empty() {
+                self.names.remove(name);
+            }
+        }
+        if let Some(names) = self.clients.get_mut(&client) {
+            names.remove(name);
+            if names.is_empty() {
+                self.clients.remove(&client);
+            }
+        }
+    }
+
+    fn count(&self, client: usize) -> usize {
+        self.clients.get(&client).map(|n| n.len()).unwrap_or(0)
+    }
+}
+
+#[derive(Default)]
+struct Subscriptions {
+    channels: Registry,
+    patterns: Registry,
+}
+
+impl Subscriptions {
+    fn registry(&mut self, pattern: bool) -> &mut Registry {
+        if pattern { &mut self.patterns } else { &mut self.channels }
+    }
+
+    // channels and patterns together, as in the subscribe and unsubscribe replies
+    fn count(&self, client: usize) -> usize {
+        self.channels.count(client) + self.patterns.count(client)
+    }
+}
+
+// channel and pattern subscriptions of client connections, both directions are kept, so PUBLISH finds the receivers
+// and UNSUBSCRIBE without channels finds the channels of a connection without scanning
+#[derive(Default)]
+pub struct PubSub {
+    subscriptions: RwLock<Subscriptions>,
+}
+
+impl PubSub {
+    pub fn new() -> PubSub {
+        PubSub::default()
+    }
+
+    // pattern = true - glob pattern subscription, returns the number of channels and patterns
+    // the connection is subscribed to
+    pub fn subscribe(&self, client: usize, name: &[u8], pattern: bool) -> usize {
+        let mut s = self.subscriptions.write().unwrap();
+        s.registry(pattern).add(client, name);
+        s.count(client)
+    }
+
+    // returns the number of channels and patterns the connection is still subscribed to
+    pub fn unsubscribe(&self, client: usize, name: &[u8], pattern: bool) -> usize {
+        let mut s = self.subscriptions.write().unwrap();
+        s.registry(pattern).remove(client, name);
+        s.count(client)
+    }
+
+    // channels or patterns of the connection
+    pub fn subscriptions(&self, client: usize, pattern: bool) -> Vec<Vec<u8>> {
+        let s = self.subscriptions.read().unwrap();
+        let registry = if pattern { &s.patterns } else { &s.channels };
+        registry.clients.get(&client)
+            .map(|c| c.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    // number of channels and patterns the connection is subscribed to
+    pub fn count(&self, client: usize) -> usize {
+        self.subscriptions.read().unwrap().count(client)
+    }
+
+    pub fn is_subscribed(&self, client: usize) -> bool {
+        self.count(client) > 0
+    }
+
+    // connections that receive a message published to the channel with the matched pattern,
+    // None - the connection is subscribed to the channel itself, every pattern is checked
+    pub fn subscribers(&self, channel: &[u8]) -> Vec<(usize, Option<Vec<u8>>)> {
+        let s = self.subscriptions.read().unwrap();
+        let mut result: Vec<(usize, Option<Vec<u8>>)> = s.channels.names.get(channel)
+            .map(|c| c.iter().map(|client| (*client, None)).collect())
+            .unwrap_or_default();
+        for (pattern, clients) in &s.patterns.names {
+            if glob_match(pattern, channel) {
+                result.extend(clients.iter().map(|client| (*client, Some(pattern.clone()))));
+            }
+        }
+        result
+    }
+
+    // called when the connection is closed
+    #[cfg(any(feature = "server", test))]
+    pub fn remove_client(&self, client: usize) {
+        let mut s = self.subscriptions.write().unwrap();
+        for pattern in [false, true] {
+            let registry = s.registry(pattern);
+            let names: Vec<Vec<u8>> = registry.clients.get(&client).map(|n| n.iter().cloned().collect()).unwrap_or_default();
+            names.iter().for_each(|n| registry.remove(client, n));
+        }
+    }
+}
+
+// subscribe/unsubscribe confirmation: kind, channel or pattern, number of subscriptions of the connection
+pub fn encode_subscription(kind: &[u8], name: &[u8], count: usize, result: &mut Vec<u8>) {
+    resp_encode_array_len(3, result);
+    resp_encode_binary_string(kind, result);
+    resp_encode_binary_string(name, result);
+    resp_encode_int(count as isize, result);
+}
+
+// message for a channel subscription, pmessage with the pattern for a pattern subscription
+pub fn encode_message(pattern: Option<&[u8]>, channel: &[u8], message: &[u8], result: &mut Vec<u8>) {
+    match pattern {
+        Some(pattern) => {
+            resp_encode_array_len(4, result);
+            resp_encode_binary_string(b"pmessage", result);
+            resp_encode_binary_string(pattern, result);
+        }
+        None => {
+            resp_encode_array_len(3, result);
+            resp_encode_binary_string(b"message", result);
+        }
+    }
+    resp_encode_binary_string(channel, result);
+    resp_encode_binary_string(message, result);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pubsub::{encode_message, PubSub};
+
+    #[test]
+    fn test_pubsub() {
+        let pubsub = PubSub::new();
+        assert_eq!(pubsub.subscribe(1, b"a", false), 1);
+        assert_eq!(pubsub.subscribe(1, b"b", false), 2);
+        assert_eq!(pubsub.subscribe(1, b"a", false), 2);
+        assert_eq!(pubsub.subscribe(2, b"a", false), 1);
+        let mut subscribers = pubsub.subscribers(b"a");
+        subscribers.sort();
+        assert_eq!(subscribers, vec![(1, None), (2, None)]);
+        assert!(pubsub.is_subscribed(1));
+        assert_eq!(pubsub.unsubscribe(2, b"a", false), 0);
+        assert!(!pubsub.is_subscribed(2));
+        assert_eq!(pubsub.unsubscribe(2, b"a", false), 0);
+        assert_eq!(pubsub.subscribers(b"a"), vec![(1, None)]);
+
+        assert_eq!(pubsub.subscribe(2, b"a*", true), 1);
+        assert_eq!(pubsub.subscribe(1, b"?", true), 3);
+        let mut subscribers = pubsub.subscribers(b"a");
+        subscribers.sort();
+        assert_eq!(subscribers, vec![(1, None), (1, Some(b"?".to_vec())), (2, Some(b"a*".to_vec()))]);
+        assert_eq!(pubsub.subscribers(b"ab"), vec![(2, Some(b"a*".to_vec()))]);
+        assert_eq!(pubsub.subscriptions(1, true), vec![b"?".to_vec()]);
+        assert_eq!(pubsub.unsubscribe(2, b"a*", true), 0);
+
+        pubsub.remove_client(1);
+        assert!(pubsub.subscribers(b"a").is_empty());
+        assert!(!pubsub.is_subscribed(1));
+
+        let mut result = Vec::new();
+        encode_message(None, b"ch", b"hi", &mut result);
+        assert_eq!(result, b"*3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$2\r\nhi\r\n");
+        result.clear();
+        encode_message(Some(b"c*"), b"ch", b"hi", &mut result);
+        assert_eq!(result, b"*4\r\n$8\r\npmessage\r\n$2\r\nc*\r\n$2\r\nch\r\n$2\r\nhi\r\n");
+    }
+}
@@ -0,0 +1,156 @@
+use crate::resp_encoder::{resp_encode_array_len, resp_encode_binary_string, resp_encode_int};
+use crate::resp_parser::{parse_token, ParseError, INVALID_COMMAND_ERROR};
+use crate::resp_parser::RespToken::{RespArray, RespBinaryString, RespError, RespInteger, RespNullArray, RespNullString, RespString};
+
+pub use crate::resp_parser::RespToken;
+
+// incremental RESP decoder, data can be fed in chunks of any size
+#[derive(Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+    // start of the first frame that is not decoded yet
+    position: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        if self.position == self.buffer.len() {
+            self.buffer.clear();
+            self.position = 0;
+        } else if self.position > 0 {
+            self.buffer.drain(..self.position);
+            self.position = 0;
+        }
+        self.buffer.extend_from_slice(data);
+    }
+
+    // returns the next complete frame, None - more data is needed
+    pub fn next_frame(&mut self) -> Result<Option<RespToken>, &'static str> {
+        match parse_token(&self.buffer, self.position, self.buffer.len()) {
+            Ok((idx, token)) => {
+                self.position = idx;
+                Ok(Some(token))
+            }
+            Err(ParseError::Incomplete) => Ok(None),
+            Err(ParseError::Invalid) => Err(INVALID_COMMAND_ERROR)
+        }
+    }
+
+    // number of received bytes that are not decoded yet
+    pub fn pending(&self) -> usize {
+        self.buffer.len() - self.position
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.position = 0;
+    }
+}
+
+// RESP encoder, frames are appended to the internal buffer
+#[derive(Default)]
+pub struct Encoder {
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Encoder {
+        Encoder::default()
+    }
+
+    pub fn encode(&mut self, token: &RespToken) -> &mut Encoder {
+        encode_token(token, &mut self.buffer);
+        self
+    }
+
+    // command as an array of binary strings
+    pub fn encode_command(&mut self, args: &[&[u8]]) -> &mut Encoder {
+        resp_encode_array_len(args.len(), &mut self.buffer);
+        args.iter().for_each(|a| resp_encode_binary_string(a, &mut self.buffer));
+        self
+    }
+
+    // returns encoded data and clears the buffer
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+fn encode_line(prefix: u8, value: &[u8], result: &mut Vec<u8>) {
+    result.push(prefix);
+    result.extend_from_slice(value);
+    result.extend_from_slice(b"\r\n");
+}
+
+fn encode_token(token: &RespToken, result: &mut Vec<u8>) {
+    match token {
+        RespArray(v) => {
+            resp_encode_array_len(v.len(), result);
+            v.iter().for_each(|t| encode_token(t, result));
+        }
+        RespString(s) => encode_line(b'+', s, result),
+        RespBinaryString(s) => resp_encode_binary_string(s, result),
+        RespError(s) => encode_line(b'-', s, result),
+        RespInteger(n) => resp_encode_int(*n, result),
+        RespNullArray => result.extend_from_slice(b"*-1\r\n"),
+        RespNullString => result.extend_from_slice(b"$-1\r\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resp_codec::{Decoder, Encoder};
+    use crate::resp_codec::RespToken::{RespArray, RespBinaryString, RespError, RespInteger, RespNullArray, RespNullString, RespString};
+
+    #[test]
+    fn test_round_trip() {
+        let tokens = vec![
+            RespArray(vec![RespBinaryString(b"set".to_vec()), RespBinaryString(b"a".to_vec()), RespBinaryString(Vec::new())]),
+            RespString(b"OK".to_vec()),
+            RespError(b"ERR unknown".to_vec()),
+            RespInteger(-12),
+            RespNullArray,
+            RespNullString,
+        ];
+        let mut encoder = Encoder::new();
+        tokens.iter().for_each(|t| { encoder.encode(t); });
+        let data = encoder.take();
+        assert_eq!(&data[..35], b"*3\r\n$3\r\nset\r\n$1\r\na\r\n$0\r\n\r\n+OK\r\n-ERR");
+
+        // one byte at a time
+        let mut decoder = Decoder::new();
+        let mut decoded = Vec::new();
+        for b in &data {
+            decoder.feed(&[*b]);
+            while let Some(token) = decoder.next_frame().unwrap() {
+                decoded.push(token);
+            }
+        }
+        assert_eq!(decoded, tokens);
+        assert_eq!(decoder.pending(), 0);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"*2\r\n$3\r\nget\r\n$5\r\nab");
+        assert_eq!(decoder.next_frame(), Ok(None));
+        assert_eq!(decoder.pending(), 19);
+        decoder.feed(b"cde\r\nPING\r\n");
+        assert_eq!(decoder.next_frame(), Ok(Some(RespArray(vec![RespBinaryString(b"get".to_vec()),
+                                                                RespBinaryString(b"abcde".to_vec())]))));
+        assert_eq!(decoder.next_frame(), Ok(Some(RespString(b"PING".to_vec()))));
+        assert_eq!(decoder.next_frame(), Ok(None));
+
+        decoder.feed(b"*x\r\n");
+        assert!(decoder.next_frame().is_err());
+
+        let mut encoder = Encoder::new();
+        encoder.encode_command(&[b"get", b"key"]);
+        assert_eq!(encoder.take(), b"*2\r\n$3\r\nget\r\n$3\r\nkey\r\n");
+    }
+}
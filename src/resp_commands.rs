@@ -1,13 +1,28 @@
 use std::sync::Arc;
-use crate::resp_encoder::{resp_encode_array2, resp_encode_binary_string, resp_encode_int};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::allocator::{allocated_memory, allocator_name, resident_memory};
+use crate::bitmap::BitOperation;
+use crate::resp_encoder::{resp_encode_array2, resp_encode_array_len, resp_encode_binary_string, resp_encode_error,
+                          resp_encode_int, resp_encode_string};
 use crate::resp_parser::{check_name, INVALID_COMMAND_ERROR, RespToken};
 use crate::resp_parser::RespToken::{RespBinaryString, RespInteger};
-use crate::common_data::CommonData;
+use crate::command_stats::{CommandStats, COMMANDS};
+use crate::command_table::{find_command, CommandInfo, COMMAND_TABLE};
+use crate::common_data::{CommonData, SetOptions, ZAddOptions, DATABASES};
+use crate::member_set::{SetOperation, MAX_REPEATED_MEMBERS};
+use crate::geo;
+use crate::pubsub::encode_subscription;
+use crate::geo::{unit_to_meters, valid_coordinates, GeoShape};
+use crate::sorted_set::{parse_score, parse_score_bound};
+use crate::storage_backend::ShardStats;
+use crate::values::{format_float, format_score, parse_float};
 
 static NULL_STRING: &[u8] = "$-1\r\n".as_bytes();
 static PONG: &[u8] = "+PONG\r\n".as_bytes();
 static OK: &[u8] = "+OK\r\n".as_bytes();
 static NULL_ARRAY: &[u8] = "*-1\r\n".as_bytes();
+static SYNTAX_ERROR: &str = "ERR syntax error";
+static OOM_ERROR: &[u8] = "-OOM command not allowed when used memory > 'maxmemory'\r\n".as_bytes();
 
 pub fn run_ping_command(v: Vec<RespToken>, result: &mut Vec<u8>) {
     if v.len() >= 2 {
@@ -19,8 +34,18 @@ pub fn run_ping_command(v: Vec<RespToken>, result: &mut Vec<u8>) {
     result.extend_from_slice(PONG);
 }
 
-pub fn run_select_command(result: &mut Vec<u8>) {
-    result.extend_from_slice(OK);
+// select index - there is only one keyspace, indexes 0..DATABASES-1 are accepted for compatibility with
+// clients that select a database during the handshake, all of them refer to the same keys
+pub fn run_select_command(v: Vec<RespToken>, result: &mut Vec<u8>) {
+    if v.len() != 2 {
+        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+        return;
+    }
+    match parse_usize(&v[1]) {
+        Some(idx) if idx < DATABASES => result.extend_from_slice(OK),
+        Some(_idx) => resp_encode_error("ERR DB index is out of range", result),
+        None => resp_encode_error("ERR value is not an integer or out of range", result)
+    }
 }
 
 pub fn run_flush_command(result: &mut Vec<u8>, common_data: Arc<CommonData>) {
@@ -45,6 +70,69 @@ pub fn run_del_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc
         }
         let removed = common_data.removekeys(keys);
         resp_encode_int(removed, result);
+        return;
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+pub fn run_exists_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() >= 2 {
+        let mut keys = Vec::new();
+        for i in 1..v.len() {
+            if let RespBinaryString(v) = &v[i] {
+                keys.push(v);
+            } else {
+                result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                return;
+            }
+        }
+        resp_encode_int(common_data.exists(keys), result);
+        return;
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+pub fn run_incrbyfloat_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 3 {
+        if let (RespBinaryString(key), RespBinaryString(increment)) = (&v[1], &v[2]) {
+            match parse_float(increment) {
+                Some(increment) => match common_data.incr_by_float(key, increment) {
+                    Ok(value) => resp_encode_string(&format_float(value), result),
+                    Err(e) => resp_encode_error(e, result)
+                },
+                None => resp_encode_error("ERR value is not a valid float", result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+pub fn run_touch_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() >= 2 {
+        let mut keys = Vec::new();
+        for token in &v[1..] {
+            if let RespBinaryString(key) = token {
+                keys.push(key);
+            } else {
+                result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                return;
+            }
+        }
+        resp_encode_int(common_data.touch(keys), result);
+        return;
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+pub fn run_type_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 2 {
+        if let RespBinaryString(key) = &v[1] {
+            result.push(b'+');
+            result.extend_from_slice(common_data.key_type(key).unwrap_or("none").as_bytes());
+            result.extend_from_slice(b"\r\n");
+            return;
+        }
     }
     result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
 }
@@ -61,104 +149,1926 @@ pub fn run_get_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc
     result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
 }
 
-fn set_with_result(k: &Vec<u8>, vv: &Vec<u8>, e: isize, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
-    if e <= 0 {
-        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+pub fn run_mget_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() >= 2 {
+        let mut keys = Vec::new();
+        for token in &v[1..] {
+            if let RespBinaryString(key) = token {
+                keys.push(key);
+            } else {
+                result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                return;
+            }
+        }
+        resp_encode_array_len(keys.len(), result);
+        for key in keys {
+            if !common_data.get_string(key, result) {
+                result.extend_from_slice(NULL_STRING);
+            }
+        }
+        return;
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+fn parse_key_values(v: &[RespToken]) -> Option<Vec<(&Vec<u8>, &Vec<u8>)>> {
+    if v.is_empty() || !v.len().is_multiple_of(2) {
+        return None;
+    }
+    v.chunks(2)
+        .map(|kv| match (&kv[0], &kv[1]) {
+            (RespBinaryString(k), RespBinaryString(v)) => Some((k, v)),
+            _ => None
+        })
+        .collect()
+}
+
+// nx - MSETNX, the keys are set only when none of them exists
+pub fn run_mset_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>, nx: bool) {
+    let values = match parse_key_values(&v[1..]) {
+        Some(values) => values,
+        None => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    if nx && common_data.exists(values.iter().map(|(k, _v)| *k).collect()) > 0 {
+        resp_encode_int(0, result);
+    } else if !common_data.mset(values) {
+        result.extend_from_slice(OOM_ERROR);
+    } else if nx {
+        resp_encode_int(1, result);
     } else {
-        common_data.set(k, vv, Some(e as u64));
         result.extend_from_slice(OK);
     }
 }
 
-fn parse_number_from_vec(v: &Vec<u8>) -> Option<isize> {
-    let mut sign = 1;
-    let mut result = 0;
-    for c in v {
-        match *c as char {
-            '-' => sign = -sign,
-            '0'..='9' => result = result * 10 + (c - '0' as u8) as isize,
-            _ => return None
+pub fn run_setnx_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 3 {
+        if let (RespBinaryString(key), RespBinaryString(value)) = (&v[1], &v[2]) {
+            match common_data.set_nx(key, value) {
+                Ok(set) => resp_encode_int(set as isize, result),
+                Err(e) => resp_encode_error(e, result)
+            }
+            return;
         }
     }
-    Some(result)
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
 }
 
-pub fn run_set_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
-    let l = v.len();
-    if l >= 3 {
-        if let RespBinaryString(k) = &v[1] {
-            if let RespBinaryString(vv) = &v[2] {
-                if l == 3 {
-                    common_data.set(k, vv, None);
-                    result.extend_from_slice(OK);
-                    return;
-                } else if l == 5 {
-                    if let RespBinaryString(option) = &v[3] {
-                        if option.len() == 2 {
-                            let c2 = option[1];
-                            if c2 == 'x' as u8 || c2 == 'X' as u8 {
-                                match option[0] as char {
-                                    'e' | 'E' => {
-                                        match &v[4] {
-                                            RespInteger(ex) => {
-                                                set_with_result(k, vv, *ex * 1000, result, common_data);
-                                            }
-                                            RespBinaryString(v) => {
-                                                if let Some(ex) = parse_number_from_vec(v) {
-                                                    set_with_result(k, vv, ex * 1000, result, common_data);
-                                                } else {
-                                                    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
-                                                }
-                                            }
-                                            _ => result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes())
-                                        }
-                                        return;
-                                    }
-                                    'p'|'P' => {
-                                        match &v[4] {
-                                            RespInteger(ex) => {
-                                                set_with_result(k, vv, *ex, result, common_data);
-                                            }
-                                            RespBinaryString(v) => {
-                                                if let Some(ex) = parse_number_from_vec(v) {
-                                                    set_with_result(k, vv, ex, result, common_data);
-                                                } else {
-                                                    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
-                                                }
-                                            }
-                                            _ => result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes())
-                                        }
-                                        return;
-                                    }
-                                    _ => {
-                                        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
-                                        return;
-                                    }
-                                }
-                            }
+// SETEX key seconds value (multiplier = 1000), PSETEX key milliseconds value (multiplier = 1)
+pub fn run_setex_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>, multiplier: isize) {
+    if v.len() == 4 {
+        if let (RespBinaryString(key), RespBinaryString(value)) = (&v[1], &v[3]) {
+            let expiry = match &v[2] {
+                RespInteger(e) => Some(*e),
+                RespBinaryString(e) => parse_number_from_vec(e),
+                _ => None
+            };
+            match expiry.and_then(|e| e.checked_mul(multiplier)) {
+                Some(e) if e <= 0 => {
+                    let name = if multiplier == 1 { "psetex" } else { "setex" };
+                    resp_encode_error(&format!("ERR invalid expire time in '{}' command", name), result);
+                }
+                Some(e) => set_and_reply(key, value, Some(e as u64), result, common_data),
+                None => resp_encode_error("ERR value is not an integer or out of range", result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+fn set_and_reply(k: &Vec<u8>, vv: &Vec<u8>, expiry: Option<u64>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if common_data.set(k, vv, expiry) {
+        result.extend_from_slice(OK);
+    } else {
+        result.extend_from_slice(OOM_ERROR);
+    }
+}
+
+// decimal integer with an optional leading minus, None when it does not fit into isize
+fn parse_number_from_vec(v: &Vec<u8>) -> Option<isize> {
+    if v.first() == Some(&b'+') {
+        return None;
+    }
+    std::str::from_utf8(v).ok()?.parse::<isize>().ok()
+}
+
+fn parse_isize(token: &RespToken) -> Option<isize> {
+    match token {
+        RespInteger(n) => Some(*n),
+        RespBinaryString(s) if !s.is_empty() => parse_number_from_vec(s),
+        _ => None
+    }
+}
+
+// non-negative number from an integer or a binary string
+fn parse_usize(token: &RespToken) -> Option<usize> {
+    match token {
+        RespInteger(n) if *n >= 0 => Some(*n as usize),
+        RespBinaryString(s) if !s.is_empty() => match parse_number_from_vec(s) {
+            Some(n) if n >= 0 => Some(n as usize),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+// scan cursor [match pattern] [count count]
+pub fn run_scan_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() >= 2 && v.len().is_multiple_of(2) {
+        if let Some(cursor) = parse_usize(&v[1]) {
+            let mut pattern = None;
+            let mut count = 10;
+            for option in v[2..].chunks(2) {
+                match (&option[0], &option[1]) {
+                    (RespBinaryString(name), RespBinaryString(p)) if check_name(name, 0, "match") => pattern = Some(p.as_slice()),
+                    (RespBinaryString(name), value) if check_name(name, 0, "count") => match parse_usize(value) {
+                        Some(n) if n > 0 => count = n,
+                        _ => {
+                            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                            return;
                         }
+                    },
+                    _ => {
+                        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                        return;
                     }
                 }
             }
+            let (next, keys) = common_data.scan(cursor, count, pattern);
+            resp_encode_array_len(2, result);
+            resp_encode_string(&next.to_string(), result);
+            resp_encode_array_len(keys.len(), result);
+            keys.iter().for_each(|k| resp_encode_binary_string(k, result));
+            return;
         }
     }
     result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
 }
 
-pub fn run_config_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+fn parse_members(v: &[RespToken]) -> Option<Vec<&Vec<u8>>> {
+    if v.is_empty() {
+        return None;
+    }
+    v.iter()
+        .map(|m| match m {
+            RespBinaryString(m) => Some(m),
+            _ => None
+        })
+        .collect()
+}
+
+// SADD key member [member ...] (add = true), SREM key member [member ...]
+pub fn run_sadd_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>, add: bool) {
+    if let (Some(RespBinaryString(key)), Some(members)) = (v.get(1), v.get(2..).and_then(parse_members)) {
+        let count = if add { common_data.sadd(key, &members) } else { common_data.srem(key, &members) };
+        match count {
+            Ok(count) => resp_encode_int(count as isize, result),
+            Err(e) => resp_encode_error(e, result)
+        }
+        return;
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+pub fn run_smembers_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 2 {
+        if let RespBinaryString(key) = &v[1] {
+            let mut reply = Vec::new();
+            let found = common_data.read_set(key, &mut |set| {
+                resp_encode_array_len(set.len(), &mut reply);
+                set.iter().for_each(|m| resp_encode_binary_string(m, &mut reply));
+            });
+            match found {
+                Ok(true) => result.extend_from_slice(&reply),
+                Ok(false) => resp_encode_array_len(0, result),
+                Err(e) => resp_encode_error(e, result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+pub fn run_scard_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 2 {
+        if let RespBinaryString(key) = &v[1] {
+            let mut len = 0;
+            match common_data.read_set(key, &mut |set| len = set.len()) {
+                Ok(_found) => resp_encode_int(len as isize, result),
+                Err(e) => resp_encode_error(e, result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+pub fn run_sismember_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
     if v.len() == 3 {
-        if let RespBinaryString(subcommand) = &v[1] {
-            if check_name(subcommand, 0, "get") {
-                if let RespBinaryString(key) = &v[2] {
-                    if let Some(v) = common_data.configuration.get(key) {
-                        resp_encode_array2(key, v, result);
+        if let (RespBinaryString(key), RespBinaryString(member)) = (&v[1], &v[2]) {
+            let mut is_member = false;
+            match common_data.read_set(key, &mut |set| is_member = set.contains(member)) {
+                Ok(_found) => resp_encode_int(is_member as isize, result),
+                Err(e) => resp_encode_error(e, result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// SMISMEMBER key member [member ...]
+pub fn run_smismember_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if let (Some(RespBinaryString(key)), Some(members)) = (v.get(1), v.get(2..).and_then(parse_members)) {
+        let mut flags = vec![false; members.len()];
+        let found = common_data.read_set(key, &mut |set| {
+            members.iter().zip(flags.iter_mut()).for_each(|(m, f)| *f = set.contains(m))
+        });
+        match found {
+            Ok(_found) => {
+                resp_encode_array_len(flags.len(), result);
+                flags.iter().for_each(|f| resp_encode_int(*f as isize, result));
+            }
+            Err(e) => resp_encode_error(e, result)
+        }
+        return;
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// SINTER/SUNION/SDIFF key [key ...], with store - SINTERSTORE/SUNIONSTORE/SDIFFSTORE destination key [key ...]
+pub fn run_set_operation_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>,
+                                 operation: SetOperation, store: bool) {
+    let first = if store { 2 } else { 1 };
+    let keys = match v.get(first..).and_then(parse_members) {
+        Some(keys) => keys,
+        None => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let members = match common_data.set_operation(operation, &keys) {
+        Ok(members) => members,
+        Err(e) => {
+            resp_encode_error(e, result);
+            return;
+        }
+    };
+    if store {
+        if let RespBinaryString(destination) = &v[1] {
+            match common_data.store_set(destination, members) {
+                Ok(len) => resp_encode_int(len as isize, result),
+                Err(e) => resp_encode_error(e, result)
+            }
+        } else {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+        }
+    } else {
+        resp_encode_array_len(members.len(), result);
+        members.iter().for_each(|m| resp_encode_binary_string(m, result));
+    }
+}
+
+// SPOP key [count] (pop = true), SRANDMEMBER key [count], without count - one member or null
+pub fn run_spop_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>, pop: bool) {
+    let key = match (v.get(1), v.len()) {
+        (Some(RespBinaryString(key)), 2 | 3) => key,
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let count = match v.get(2).map(parse_isize) {
+        Some(Some(count)) if pop && count < 0 => {
+            resp_encode_error("ERR value is out of range, must be positive", result);
+            return;
+        }
+        Some(Some(count)) if count < 0 && count.unsigned_abs() > MAX_REPEATED_MEMBERS => {
+            resp_encode_error("ERR value is out of range", result);
+            return;
+        }
+        Some(Some(count)) => Some(count),
+        Some(None) => {
+            resp_encode_error("ERR value is not an integer or out of range", result);
+            return;
+        }
+        None => None
+    };
+    let members = if pop {
+        common_data.spop(key, count.unwrap_or(1) as usize)
+    } else {
+        let mut members = Vec::new();
+        common_data.read_set(key, &mut |set| members = set.random(count.unwrap_or(1))).map(|_found| members)
+    };
+    match (members, count) {
+        (Ok(members), Some(_count)) => {
+            resp_encode_array_len(members.len(), result);
+            members.iter().for_each(|m| resp_encode_binary_string(m, result));
+        }
+        (Ok(members), None) => match members.first() {
+            Some(member) => resp_encode_binary_string(member, result),
+            None => result.extend_from_slice(NULL_STRING)
+        },
+        (Err(e), _count) => resp_encode_error(e, result)
+    }
+}
+
+// SSCAN key cursor [MATCH pattern] [COUNT count]
+pub fn run_sscan_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() >= 3 && !v.len().is_multiple_of(2) {
+        if let (RespBinaryString(key), Some(cursor)) = (&v[1], parse_usize(&v[2])) {
+            let mut pattern = None;
+            let mut count = 10;
+            for option in v[3..].chunks(2) {
+                match (&option[0], &option[1]) {
+                    (RespBinaryString(name), RespBinaryString(p)) if check_name(name, 0, "match") => pattern = Some(p.as_slice()),
+                    (RespBinaryString(name), value) if check_name(name, 0, "count") => match parse_usize(value) {
+                        Some(n) if n > 0 => count = n,
+                        _ => {
+                            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                            return;
+                        }
+                    },
+                    _ => {
+                        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
                         return;
                     }
-                    result.extend_from_slice(NULL_ARRAY);
+                }
+            }
+            let mut members = Vec::new();
+            let mut next = 0;
+            if let Err(e) = common_data.read_set(key, &mut |set| next = set.scan(cursor, count, pattern, &mut members)) {
+                resp_encode_error(e, result);
+                return;
+            }
+            resp_encode_array_len(2, result);
+            resp_encode_string(&next.to_string(), result);
+            resp_encode_array_len(members.len(), result);
+            members.iter().for_each(|m| resp_encode_binary_string(m, result));
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+fn parse_zadd_options(v: &[RespToken]) -> Result<(ZAddOptions, usize), &'static str> {
+    let mut options = ZAddOptions::default();
+    let mut i = 0;
+    while let Some(RespBinaryString(option)) = v.get(i) {
+        match option.to_ascii_lowercase().as_slice() {
+            b"nx" => options.nx = true,
+            b"xx" => options.xx = true,
+            b"gt" => options.gt = true,
+            b"lt" => options.lt = true,
+            b"ch" => options.ch = true,
+            _ => break
+        }
+        i += 1;
+    }
+    if options.nx && options.xx {
+        return Err("ERR XX and NX options at the same time are not compatible");
+    }
+    if (options.gt && options.lt) || (options.nx && (options.gt || options.lt)) {
+        return Err("ERR GT, LT, and/or NX options at the same time are not compatible");
+    }
+    Ok((options, i))
+}
+
+// ZADD key [NX | XX] [GT | LT] [CH] score member [score member ...]
+pub fn run_zadd_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    let key = match v.get(1) {
+        Some(RespBinaryString(key)) if v.len() >= 4 => key,
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let (options, skip) = match parse_zadd_options(&v[2..]) {
+        Ok(options) => options,
+        Err(e) => {
+            resp_encode_error(e, result);
+            return;
+        }
+    };
+    let pairs = &v[2 + skip..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        resp_encode_error(SYNTAX_ERROR, result);
+        return;
+    }
+    let mut members = Vec::new();
+    for pair in pairs.chunks(2) {
+        match (&pair[0], &pair[1]) {
+            (RespBinaryString(score), RespBinaryString(member)) => match parse_score(score) {
+                Some(score) => members.push((score, member)),
+                None => {
+                    resp_encode_error("ERR value is not a valid float", result);
                     return;
                 }
+            },
+            _ => {
+                result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                return;
+            }
+        }
+    }
+    match common_data.zadd(key, &options, &members) {
+        Ok(count) => resp_encode_int(count as isize, result),
+        Err(e) => resp_encode_error(e, result)
+    }
+}
+
+pub fn run_zscore_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 3 {
+        if let (RespBinaryString(key), RespBinaryString(member)) = (&v[1], &v[2]) {
+            let mut score = None;
+            match common_data.read_sorted_set(key, &mut |set| score = set.score(member)) {
+                Ok(_found) => match score {
+                    Some(score) => resp_encode_string(&format_score(score), result),
+                    None => result.extend_from_slice(NULL_STRING)
+                },
+                Err(e) => resp_encode_error(e, result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+pub fn run_zcard_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 2 {
+        if let RespBinaryString(key) = &v[1] {
+            let mut len = 0;
+            match common_data.read_sorted_set(key, &mut |set| len = set.len()) {
+                Ok(_found) => resp_encode_int(len as isize, result),
+                Err(e) => resp_encode_error(e, result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// ZINCRBY key increment member
+pub fn run_zincrby_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 4 {
+        if let (RespBinaryString(key), RespBinaryString(increment), RespBinaryString(member)) = (&v[1], &v[2], &v[3]) {
+            match parse_score(increment) {
+                Some(increment) => match common_data.zincrby(key, increment, member) {
+                    Ok(score) => resp_encode_string(&format_score(score), result),
+                    Err(e) => resp_encode_error(e, result)
+                },
+                None => resp_encode_error("ERR value is not a valid float", result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// ZRANK key member, with reverse - ZREVRANK key member
+pub fn run_zrank_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>, reverse: bool) {
+    if v.len() == 3 {
+        if let (RespBinaryString(key), RespBinaryString(member)) = (&v[1], &v[2]) {
+            let mut rank = None;
+            let found = common_data.read_sorted_set(key, &mut |set| {
+                rank = set.rank(member).map(|r| if reverse { set.len() - 1 - r } else { r })
+            });
+            match found {
+                Ok(_found) => match rank {
+                    Some(rank) => resp_encode_int(rank as isize, result),
+                    None => result.extend_from_slice(NULL_STRING)
+                },
+                Err(e) => resp_encode_error(e, result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// ZREM key member [member ...]
+pub fn run_zrem_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if let (Some(RespBinaryString(key)), Some(members)) = (v.get(1), v.get(2..).and_then(parse_members)) {
+        match common_data.zrem(key, &members) {
+            Ok(count) => resp_encode_int(count as isize, result),
+            Err(e) => resp_encode_error(e, result)
+        }
+        return;
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// ZPOPMIN key [count], with max - ZPOPMAX key [count], returns member and score pairs
+pub fn run_zpop_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>, max: bool) {
+    let key = match (v.get(1), v.len()) {
+        (Some(RespBinaryString(key)), 2 | 3) => key,
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let count = match v.get(2).map(parse_isize) {
+        Some(Some(count)) if count < 0 => {
+            resp_encode_error("ERR value is out of range, must be positive", result);
+            return;
+        }
+        Some(Some(count)) => count as usize,
+        Some(None) => {
+            resp_encode_error("ERR value is not an integer or out of range", result);
+            return;
+        }
+        None => 1
+    };
+    match common_data.zpop(key, count, max) {
+        Ok(popped) => encode_scored_members(popped.iter().map(|(m, s)| (m, *s)).collect(), true, result),
+        Err(e) => resp_encode_error(e, result)
+    }
+}
+
+// GEOADD key [NX | XX] [CH] longitude latitude member [longitude latitude member ...]
+pub fn run_geoadd_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    let key = match v.get(1) {
+        Some(RespBinaryString(key)) if v.len() >= 5 => key,
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let (options, skip) = match parse_zadd_options(&v[2..]) {
+        Ok((options, _skip)) if options.gt || options.lt => {
+            resp_encode_error(SYNTAX_ERROR, result);
+            return;
+        }
+        Ok(options) => options,
+        Err(e) => {
+            resp_encode_error(e, result);
+            return;
+        }
+    };
+    let items = &v[2 + skip..];
+    if items.is_empty() || !items.len().is_multiple_of(3) {
+        resp_encode_error(SYNTAX_ERROR, result);
+        return;
+    }
+    let mut members = Vec::new();
+    for item in items.chunks(3) {
+        let member = match &item[2] {
+            RespBinaryString(member) => member,
+            _ => {
+                result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                return;
+            }
+        };
+        match (parse_token_float(&item[0]), parse_token_float(&item[1])) {
+            (Some(longitude), Some(latitude)) if valid_coordinates(longitude, latitude) =>
+                members.push((geo::encode(longitude, latitude) as f64, member)),
+            (Some(longitude), Some(latitude)) => {
+                resp_encode_error(&format!("ERR invalid longitude,latitude pair {:.6},{:.6}", longitude, latitude), result);
+                return;
+            }
+            _ => {
+                resp_encode_error("ERR value is not a valid float", result);
+                return;
             }
         }
     }
+    match common_data.zadd(key, &options, &members) {
+        Ok(count) => resp_encode_int(count as isize, result),
+        Err(e) => resp_encode_error(e, result)
+    }
+}
+
+fn parse_token_float(token: &RespToken) -> Option<f64> {
+    match token {
+        RespBinaryString(value) => parse_float(value),
+        _ => None
+    }
+}
+
+fn parse_unit(token: Option<&RespToken>) -> Result<f64, &'static str> {
+    match token {
+        Some(RespBinaryString(unit)) => unit_to_meters(unit).ok_or("ERR unsupported unit provided. please use M, KM, FT, MI"),
+        _ => Err(SYNTAX_ERROR)
+    }
+}
+
+// GEODIST key member1 member2 [M | KM | FT | MI]
+pub fn run_geodist_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 4 || v.len() == 5 {
+        if let (RespBinaryString(key), RespBinaryString(member1), RespBinaryString(member2)) = (&v[1], &v[2], &v[3]) {
+            let unit = if v.len() == 5 { parse_unit(v.get(4)) } else { Ok(1.0) };
+            let unit = match unit {
+                Ok(unit) => unit,
+                Err(e) => {
+                    resp_encode_error(e, result);
+                    return;
+                }
+            };
+            let mut d = None;
+            let found = common_data.read_sorted_set(key, &mut |set| {
+                if let (Some(s1), Some(s2)) = (set.score(member1), set.score(member2)) {
+                    let ((lon1, lat1), (lon2, lat2)) = (geo::decode(s1 as u64), geo::decode(s2 as u64));
+                    d = Some(geo::distance(lon1, lat1, lon2, lat2));
+                }
+            });
+            match (found, d) {
+                (Ok(_found), Some(d)) => resp_encode_string(&format!("{:.4}", d / unit), result),
+                (Ok(_found), None) => result.extend_from_slice(NULL_STRING),
+                (Err(e), _) => resp_encode_error(e, result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+#[derive(Default)]
+struct GeoSearchOptions<'a> {
+    member: Option<&'a [u8]>,
+    center: Option<(f64, f64)>,
+    shape: Option<GeoShape>,
+    // meters in one unit of the shape size
+    unit: f64,
+    descending: Option<bool>,
+    count: Option<usize>,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+}
+
+fn parse_geosearch_options(v: &[RespToken]) -> Result<GeoSearchOptions<'_>, &'static str> {
+    let mut options = GeoSearchOptions { unit: 1.0, ..GeoSearchOptions::default() };
+    let mut i = 0;
+    while let Some(token) = v.get(i) {
+        let option = match token {
+            RespBinaryString(option) => option.to_ascii_lowercase(),
+            _ => return Err(SYNTAX_ERROR)
+        };
+        match option.as_slice() {
+            b"frommember" if options.member.is_none() && options.center.is_none() => {
+                match v.get(i + 1) {
+                    Some(RespBinaryString(member)) => options.member = Some(member),
+                    _ => return Err(SYNTAX_ERROR)
+                }
+                i += 2;
+            }
+            b"fromlonlat" if options.member.is_none() && options.center.is_none() => {
+                match (v.get(i + 1).and_then(parse_token_float), v.get(i + 2).and_then(parse_token_float)) {
+                    (Some(longitude), Some(latitude)) if valid_coordinates(longitude, latitude) =>
+                        options.center = Some((longitude, latitude)),
+                    (Some(_), Some(_)) => return Err("ERR invalid longitude,latitude pair"),
+                    _ => return Err("ERR value is not a valid float")
+                }
+                i += 3;
+            }
+            b"frommember" | b"fromlonlat" =>
+                return Err("ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH"),
+            b"byradius" if options.shape.is_none() => {
+                let radius = v.get(i + 1).and_then(parse_token_float).filter(|r| *r >= 0.0)
+                    .ok_or("ERR need numeric radius")?;
+                options.unit = parse_unit(v.get(i + 2))?;
+                options.shape = Some(GeoShape::Radius(radius * options.unit));
+                i += 3;
+            }
+            b"bybox" if options.shape.is_none() => {
+                let (width, height) = match (v.get(i + 1).and_then(parse_token_float), v.get(i + 2).and_then(parse_token_float)) {
+                    (Some(width), Some(height)) if width >= 0.0 && height >= 0.0 => (width, height),
+                    _ => return Err("ERR need numeric width and height")
+                };
+                options.unit = parse_unit(v.get(i + 3))?;
+                options.shape = Some(GeoShape::Box(width * options.unit, height * options.unit));
+                i += 4;
+            }
+            b"byradius" | b"bybox" =>
+                return Err("ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH"),
+            b"asc" => {
+                options.descending = Some(false);
+                i += 1;
+            }
+            b"desc" => {
+                options.descending = Some(true);
+                i += 1;
+            }
+            b"count" => {
+                match v.get(i + 1).and_then(parse_isize) {
+                    Some(count) if count > 0 => options.count = Some(count as usize),
+                    _ => return Err("ERR COUNT must be > 0")
+                }
+                i += 2;
+            }
+            b"withcoord" => {
+                options.with_coord = true;
+                i += 1;
+            }
+            b"withdist" => {
+                options.with_dist = true;
+                i += 1;
+            }
+            b"withhash" => {
+                options.with_hash = true;
+                i += 1;
+            }
+            _ => return Err(SYNTAX_ERROR)
+        }
+    }
+    if options.member.is_none() && options.center.is_none() {
+        return Err("ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH");
+    }
+    if options.shape.is_none() {
+        return Err("ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH");
+    }
+    Ok(options)
+}
+
+// GEOSEARCH key FROMMEMBER member | FROMLONLAT longitude latitude BYRADIUS radius unit | BYBOX width height unit
+// [ASC | DESC] [COUNT count] [WITHCOORD] [WITHDIST] [WITHHASH], every member of the set is checked
+pub fn run_geosearch_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    let key = match v.get(1) {
+        Some(RespBinaryString(key)) => key,
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let options = match parse_geosearch_options(&v[2..]) {
+        Ok(options) => options,
+        Err(e) => {
+            resp_encode_error(e, result);
+            return;
+        }
+    };
+    let mut reply = Vec::new();
+    let mut member_found = true;
+    let found = common_data.read_sorted_set(key, &mut |set| {
+        let center = match (options.center, options.member) {
+            (Some(center), _) => center,
+            (None, Some(member)) => match set.score(member) {
+                Some(score) => geo::decode(score as u64),
+                None => {
+                    member_found = false;
+                    return;
+                }
+            },
+            (None, None) => return
+        };
+        let mut items = geo::search(set, center, options.shape.unwrap_or(GeoShape::Radius(0.0)));
+        // COUNT without an order returns the nearest members
+        match options.descending.or(options.count.map(|_c| false)) {
+            Some(false) => items.sort_by(|a, b| a.1.total_cmp(&b.1)),
+            Some(true) => items.sort_by(|a, b| b.1.total_cmp(&a.1)),
+            None => {}
+        }
+        items.truncate(options.count.unwrap_or(usize::MAX));
+        let fields = 1 + options.with_dist as usize + options.with_hash as usize + options.with_coord as usize;
+        resp_encode_array_len(items.len(), &mut reply);
+        for (member, d, hash) in items {
+            if fields > 1 {
+                resp_encode_array_len(fields, &mut reply);
+            }
+            resp_encode_binary_string(member, &mut reply);
+            if options.with_dist {
+                resp_encode_string(&format!("{:.4}", d / options.unit), &mut reply);
+            }
+            if options.with_hash {
+                resp_encode_int(hash as isize, &mut reply);
+            }
+            if options.with_coord {
+                let (longitude, latitude) = geo::decode(hash);
+                resp_encode_array2(&format_score(longitude).into_bytes(), &format_score(latitude).into_bytes(), &mut reply);
+            }
+        }
+    });
+    match found {
+        Ok(true) if !member_found => resp_encode_error("ERR could not decode requested zset member", result),
+        Ok(true) => result.extend_from_slice(&reply),
+        Ok(false) => resp_encode_array_len(0, result),
+        Err(e) => resp_encode_error(e, result)
+    }
+}
+
+fn encode_scored_members(members: Vec<(&Vec<u8>, f64)>, with_scores: bool, result: &mut Vec<u8>) {
+    resp_encode_array_len(if with_scores { 2 * members.len() } else { members.len() }, result);
+    for (member, score) in members {
+        resp_encode_binary_string(member, result);
+        if with_scores {
+            resp_encode_string(&format_score(score), result);
+        }
+    }
+}
+
+// ZRANGE key start stop [WITHSCORES]
+pub fn run_zrange_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    let with_scores = match v.get(4) {
+        None => false,
+        Some(RespBinaryString(option)) if v.len() == 5 && check_name(option, 0, "withscores") => true,
+        _ => {
+            resp_encode_error(SYNTAX_ERROR, result);
+            return;
+        }
+    };
+    if let (Some(RespBinaryString(key)), Some(start), Some(stop)) = (v.get(1), v.get(2), v.get(3)) {
+        let (start, stop) = match (parse_isize(start), parse_isize(stop)) {
+            (Some(start), Some(stop)) => (start, stop),
+            _ => {
+                resp_encode_error("ERR value is not an integer or out of range", result);
+                return;
+            }
+        };
+        let mut reply = Vec::new();
+        let found = common_data.read_sorted_set(key, &mut |set| {
+            encode_scored_members(set.range(start, stop), with_scores, &mut reply)
+        });
+        match found {
+            Ok(true) => result.extend_from_slice(&reply),
+            Ok(false) => resp_encode_array_len(0, result),
+            Err(e) => resp_encode_error(e, result)
+        }
+        return;
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count], a negative count returns all the members after offset
+pub fn run_zrangebyscore_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    let (key, min, max) = match (v.get(1), v.get(2), v.get(3)) {
+        (Some(RespBinaryString(key)), Some(RespBinaryString(min)), Some(RespBinaryString(max))) => (key, min, max),
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let (min, max) = match (parse_score_bound(min), parse_score_bound(max)) {
+        (Some(min), Some(max)) => (min, max),
+        _ => {
+            resp_encode_error("ERR min or max is not a float", result);
+            return;
+        }
+    };
+    let mut with_scores = false;
+    let mut offset = 0;
+    let mut count = None;
+    let mut i = 4;
+    while let Some(option) = v.get(i) {
+        match option {
+            RespBinaryString(option) if check_name(option, 0, "withscores") => {
+                with_scores = true;
+                i += 1;
+            }
+            RespBinaryString(option) if check_name(option, 0, "limit") => {
+                match (v.get(i + 1).and_then(parse_isize), v.get(i + 2).and_then(parse_isize)) {
+                    (Some(o), Some(c)) => {
+                        // a negative offset gives an empty result
+                        offset = if o < 0 { usize::MAX } else { o as usize };
+                        count = if c < 0 { None } else { Some(c as usize) };
+                    }
+                    _ => {
+                        resp_encode_error(SYNTAX_ERROR, result);
+                        return;
+                    }
+                }
+                i += 3;
+            }
+            _ => {
+                resp_encode_error(SYNTAX_ERROR, result);
+                return;
+            }
+        }
+    }
+    let mut reply = Vec::new();
+    let found = common_data.read_sorted_set(key, &mut |set| {
+        encode_scored_members(set.range_by_score(min, max, offset, count), with_scores, &mut reply)
+    });
+    match found {
+        Ok(true) => result.extend_from_slice(&reply),
+        Ok(false) => resp_encode_array_len(0, result),
+        Err(e) => resp_encode_error(e, result)
+    }
+}
+
+// SETBIT key offset value
+pub fn run_setbit_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 4 {
+        if let RespBinaryString(key) = &v[1] {
+            let bit = match parse_isize(&v[3]) {
+                Some(bit @ (0 | 1)) => bit == 1,
+                _ => {
+                    resp_encode_error("ERR bit is not an integer or out of range", result);
+                    return;
+                }
+            };
+            match parse_usize(&v[2]).map(|offset| common_data.set_bit(key, offset, bit)) {
+                Some(Ok(old)) => resp_encode_int(old as isize, result),
+                Some(Err(e)) => resp_encode_error(e, result),
+                None => resp_encode_error("ERR bit offset is not an integer or out of range", result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+pub fn run_getbit_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 3 {
+        if let RespBinaryString(key) = &v[1] {
+            match parse_usize(&v[2]).map(|offset| common_data.get_bit(key, offset)) {
+                Some(Ok(bit)) => resp_encode_int(bit as isize, result),
+                Some(Err(e)) => resp_encode_error(e, result),
+                None => resp_encode_error("ERR bit offset is not an integer or out of range", result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// BITCOUNT key [start end [BYTE | BIT]]
+pub fn run_bitcount_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    let key = match (v.get(1), v.len()) {
+        (Some(RespBinaryString(key)), 2..=5) => key,
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let bit_unit = match v.get(4) {
+        None => false,
+        Some(RespBinaryString(unit)) if check_name(unit, 0, "byte") => false,
+        Some(RespBinaryString(unit)) if check_name(unit, 0, "bit") => true,
+        _ => {
+            resp_encode_error(SYNTAX_ERROR, result);
+            return;
+        }
+    };
+    let range = match (v.get(2).map(parse_isize), v.get(3).map(parse_isize)) {
+        (None, None) => None,
+        (Some(Some(start)), Some(Some(end))) => Some((start, end)),
+        (Some(_), None) => {
+            resp_encode_error(SYNTAX_ERROR, result);
+            return;
+        }
+        _ => {
+            resp_encode_error("ERR value is not an integer or out of range", result);
+            return;
+        }
+    };
+    match common_data.bit_count(key, range, bit_unit) {
+        Ok(count) => resp_encode_int(count as isize, result),
+        Err(e) => resp_encode_error(e, result)
+    }
+}
+
+// BITOP AND | OR | XOR | NOT destkey key [key ...], returns the length of the stored value
+pub fn run_bitop_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    let (operation, destination, keys) = match (v.get(1), v.get(2), v.get(3..).and_then(parse_members)) {
+        (Some(RespBinaryString(operation)), Some(RespBinaryString(destination)), Some(keys)) =>
+            (operation, destination, keys),
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let operation = match operation.to_ascii_lowercase().as_slice() {
+        b"and" => BitOperation::And,
+        b"or" => BitOperation::Or,
+        b"xor" => BitOperation::Xor,
+        b"not" if keys.len() == 1 => BitOperation::Not,
+        b"not" => {
+            resp_encode_error("ERR BITOP NOT must be called with a single source key.", result);
+            return;
+        }
+        _ => {
+            resp_encode_error(SYNTAX_ERROR, result);
+            return;
+        }
+    };
+    match common_data.bit_operation(operation, destination, &keys) {
+        Ok(len) => resp_encode_int(len as isize, result),
+        Err(e) => resp_encode_error(e, result)
+    }
+}
+
+// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds |
+// KEEPTTL]
+pub fn run_set_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    let (k, vv) = match (v.get(1), v.get(2)) {
+        (Some(RespBinaryString(k)), Some(RespBinaryString(vv))) => (k, vv),
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    if v.len() == 3 {
+        set_and_reply(k, vv, None, result, common_data);
+        return;
+    }
+    let options = match parse_set_options(&v[3..]) {
+        Ok(options) => options,
+        Err(e) => {
+            resp_encode_error(e, result);
+            return;
+        }
+    };
+    if !options.nx && !options.xx && !options.keep_ttl && !options.get {
+        set_and_reply(k, vv, options.expiry, result, common_data);
+        return;
+    }
+    let mut old_value = Vec::new();
+    match common_data.set_with_options(k, vv, &options, &mut old_value) {
+        Ok((_set, true)) if options.get => result.extend_from_slice(&old_value),
+        Ok((_set, false)) if options.get => result.extend_from_slice(NULL_STRING),
+        Ok((true, _found)) => result.extend_from_slice(OK),
+        Ok((false, _found)) => result.extend_from_slice(NULL_STRING),
+        Err(e) => resp_encode_error(e, result)
+    }
+}
+
+fn parse_set_options(v: &[RespToken]) -> Result<SetOptions, &'static str> {
+    let mut options = SetOptions::default();
+    let mut has_expiry = false;
+    let mut i = 0;
+    while i < v.len() {
+        let option = match &v[i] {
+            RespBinaryString(option) => option.to_ascii_lowercase(),
+            _ => return Err(SYNTAX_ERROR)
+        };
+        match option.as_slice() {
+            b"nx" if !options.xx => options.nx = true,
+            b"xx" if !options.nx => options.xx = true,
+            b"get" => options.get = true,
+            b"keepttl" if !has_expiry => {
+                options.keep_ttl = true;
+                has_expiry = true;
+            }
+            b"ex" | b"px" | b"exat" | b"pxat" if !has_expiry => {
+                i += 1;
+                let multiplier = if option[0] == b'e' { 1000 } else { 1 };
+                let time = v.get(i)
+                    .and_then(parse_isize)
+                    .ok_or("ERR value is not an integer or out of range")?;
+                let ms = match time.checked_mul(multiplier) {
+                    Some(ms) if ms > 0 => ms as u64,
+                    _ => return Err("ERR invalid expire time in 'set' command")
+                };
+                options.expiry = Some(if option.len() == 4 { unix_time_to_ttl(ms) } else { ms });
+                has_expiry = true;
+            }
+            _ => return Err(SYNTAX_ERROR)
+        }
+        i += 1;
+    }
+    Ok(options)
+}
+
+// SUBSCRIBE channel [channel ...], with subscribe = false - UNSUBSCRIBE [channel ...], without channels
+// the connection is unsubscribed from all its channels, with pattern = true - PSUBSCRIBE/PUNSUBSCRIBE for glob patterns
+pub fn run_subscribe_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>,
+                             client: Option<usize>, subscribe: bool, pattern: bool) {
+    let client = match client {
+        Some(client) => client,
+        None => {
+            resp_encode_error("ERR pub/sub needs a client connection", result);
+            return;
+        }
+    };
+    let kind: &[u8] = match (subscribe, pattern) {
+        (true, false) => b"subscribe",
+        (true, true) => b"psubscribe",
+        (false, false) => b"unsubscribe",
+        (false, true) => b"punsubscribe"
+    };
+    let mut names = match v.get(1..).and_then(parse_members) {
+        Some(names) => names.into_iter().cloned().collect(),
+        None if subscribe => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+        None => common_data.pubsub.subscriptions(client, pattern)
+    };
+    if subscribe {
+        for name in names {
+            let count = common_data.pubsub.subscribe(client, &name, pattern);
+            encode_subscription(kind, &name, count, result);
+        }
+        return;
+    }
+    if names.is_empty() {
+        // not subscribed to any channel or pattern
+        resp_encode_array_len(3, result);
+        resp_encode_binary_string(kind, result);
+        result.extend_from_slice(NULL_STRING);
+        resp_encode_int(common_data.pubsub.count(client) as isize, result);
+        return;
+    }
+    names.sort();
+    for name in names {
+        let count = common_data.pubsub.unsubscribe(client, &name, pattern);
+        encode_subscription(kind, &name, count, result);
+    }
+}
+
+// PUBLISH channel message, returns the number of connections that received the message
+pub fn run_publish_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 3 {
+        if let (RespBinaryString(channel), RespBinaryString(message)) = (&v[1], &v[2]) {
+            resp_encode_int(common_data.publish(channel, message) as isize, result);
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// DEBUG SLEEP seconds, DEBUG SET-ACTIVE-EXPIRE 0|1, DEBUG OBJECT key - SLEEP blocks only the calling connection,
+// keys expire on access and during eviction, there is no active expiration cycle, so SET-ACTIVE-EXPIRE changes nothing
+pub fn run_debug_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 3 {
+        if let (RespBinaryString(subcommand), RespBinaryString(value)) = (&v[1], &v[2]) {
+            if check_name(subcommand, 0, "sleep") {
+                match parse_float(value) {
+                    Some(seconds) if seconds >= 0.0 => match std::time::Duration::try_from_secs_f64(seconds) {
+                        Ok(duration) => {
+                            std::thread::sleep(duration);
+                            result.extend_from_slice(OK);
+                        }
+                        Err(_) => resp_encode_error("ERR timeout is out of range", result)
+                    },
+                    _ => resp_encode_error("ERR value is not a valid float", result)
+                }
+                return;
+            }
+            if check_name(subcommand, 0, "set-active-expire") {
+                if value == b"0" || value == b"1" {
+                    result.extend_from_slice(OK);
+                } else {
+                    resp_encode_error("ERR value is out of range", result);
+                }
+                return;
+            }
+            if check_name(subcommand, 0, "object") {
+                match common_data.object_info(value) {
+                    // a status reply, like in Redis
+                    Some((encoding, size)) => result.extend_from_slice(
+                        format!("+Value encoding:{} serializedlength:{}\r\n", encoding, size).as_bytes()),
+                    None => resp_encode_error("ERR no such key", result)
+                }
+                return;
+            }
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// CLIENT KILL filters, connections that match all given filters are closed
+#[derive(Default)]
+struct ClientKillFilter {
+    id: Option<usize>,
+    address: Option<Vec<u8>>,
+    skip_me: bool,
+}
+
+// KILL ID id, KILL ADDR ip:port, KILL SKIPME yes|no - the filters can be combined
+fn parse_client_kill_filter(args: &[RespToken]) -> Option<ClientKillFilter> {
+    let mut filter = ClientKillFilter { skip_me: true, ..ClientKillFilter::default() };
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return None;
+    }
+    for pair in args.chunks(2) {
+        let (name, value) = match (&pair[0], &pair[1]) {
+            (RespBinaryString(name), RespBinaryString(value)) => (name, value),
+            _ => return None
+        };
+        if check_name(name, 0, "id") {
+            filter.id = Some(parse_usize(&pair[1])?);
+        } else if check_name(name, 0, "addr") {
+            filter.address = Some(value.clone());
+        } else if check_name(name, 0, "skipme") {
+            filter.skip_me = match value.to_ascii_lowercase().as_slice() {
+                b"yes" => true,
+                b"no" => false,
+                _ => return None
+            };
+        } else {
+            return None;
+        }
+    }
+    Some(filter)
+}
+
+// CLIENT ID, CLIENT SETNAME name, CLIENT GETNAME, CLIENT LIST [ID id ...], CLIENT KILL ip:port,
+// CLIENT KILL [ID id] [ADDR ip:port] [SKIPME yes|no]
+pub fn run_client_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>, client: Option<usize>) {
+    let subcommand = match v.get(1) {
+        Some(RespBinaryString(subcommand)) => subcommand,
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    if check_name(subcommand, 0, "list") {
+        let ids = match v.get(2) {
+            None => Some(Vec::new()),
+            Some(RespBinaryString(id)) if check_name(id, 0, "id") && v.len() > 3 => v[3..].iter().map(parse_usize).collect(),
+            _ => None
+        };
+        match ids {
+            Some(ids) => resp_encode_string(&common_data.client_list(&ids), result),
+            None => result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes())
+        }
+        return;
+    }
+    if check_name(subcommand, 0, "kill") {
+        // the old form with an address replies with OK or an error
+        if v.len() == 3 {
+            if let RespBinaryString(address) = &v[2] {
+                let killed = common_data.kill_clients(&|_id, c| c.address().as_bytes() == address.as_slice());
+                if killed > 0 {
+                    result.extend_from_slice(OK);
+                } else {
+                    resp_encode_error("ERR No such client", result);
+                }
+                return;
+            }
+        }
+        match parse_client_kill_filter(&v[2..]) {
+            Some(filter) => {
+                let killed = common_data.kill_clients(&|id, c| {
+                    filter.id.is_none_or(|i| i == id) &&
+                        filter.address.as_ref().is_none_or(|a| c.address().as_bytes() == a.as_slice()) &&
+                        !(filter.skip_me && client == Some(id))
+                });
+                resp_encode_int(killed as isize, result);
+            }
+            None => result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes())
+        }
+        return;
+    }
+    let (id, connection) = match client.and_then(|c| common_data.connection(c).map(|connection| (c, connection))) {
+        Some(c) => c,
+        None => {
+            resp_encode_error("ERR client commands need a client connection", result);
+            return;
+        }
+    };
+    if check_name(subcommand, 0, "id") && v.len() == 2 {
+        resp_encode_int(id as isize, result);
+    } else if check_name(subcommand, 0, "getname") && v.len() == 2 {
+        let name = connection.name();
+        if name.is_empty() {
+            result.extend_from_slice(NULL_STRING);
+        } else {
+            resp_encode_binary_string(&name, result);
+        }
+    } else if check_name(subcommand, 0, "setname") && v.len() == 3 {
+        if let RespBinaryString(name) = &v[2] {
+            if name.iter().any(|c| *c <= b' ' || *c > b'~') {
+                resp_encode_error("ERR Client names cannot contain spaces, newlines or special characters.", result);
+            } else {
+                connection.set_name(name);
+                result.extend_from_slice(OK);
+            }
+            return;
+        }
+        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+    } else {
+        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+    }
+}
+
+// Redis 7 reply: name, arity, flags, first key, last key, step, ACL categories, tips, key specifications, subcommands,
+// the last four are always empty
+fn encode_command_info(info: &CommandInfo, result: &mut Vec<u8>) {
+    resp_encode_array_len(10, result);
+    resp_encode_binary_string(info.name.as_bytes(), result);
+    resp_encode_int(info.arity, result);
+    resp_encode_array_len(info.flags.len(), result);
+    info.flags.iter().for_each(|f| result.extend_from_slice(format!("+{}\r\n", f).as_bytes()));
+    resp_encode_int(info.first_key, result);
+    resp_encode_int(info.last_key, result);
+    resp_encode_int(info.step, result);
+    (0..4).for_each(|_i| resp_encode_array_len(0, result));
+}
+
+// commands with the names from the arguments, all commands when there are no names
+fn selected_commands(names: &[RespToken]) -> Option<Vec<Option<&'static CommandInfo>>> {
+    if names.is_empty() {
+        return Some(COMMAND_TABLE.iter().map(Some).collect());
+    }
+    names.iter()
+        .map(|t| match t {
+            RespBinaryString(name) => Some(find_command(name)),
+            _ => None
+        })
+        .collect()
+}
+
+// COMMAND, COMMAND COUNT, COMMAND INFO [command ...], COMMAND DOCS [command ...]
+pub fn run_command_command(v: Vec<RespToken>, result: &mut Vec<u8>) {
+    if v.len() == 1 {
+        resp_encode_array_len(COMMAND_TABLE.len(), result);
+        COMMAND_TABLE.iter().for_each(|info| encode_command_info(info, result));
+        return;
+    }
+    if let RespBinaryString(subcommand) = &v[1] {
+        if check_name(subcommand, 0, "count") && v.len() == 2 {
+            resp_encode_int(COMMAND_TABLE.len() as isize, result);
+            return;
+        }
+        if check_name(subcommand, 0, "info") {
+            if let Some(commands) = selected_commands(&v[2..]) {
+                resp_encode_array_len(commands.len(), result);
+                for info in commands {
+                    match info {
+                        Some(info) => encode_command_info(info, result),
+                        None => result.extend_from_slice(NULL_ARRAY)
+                    }
+                }
+                return;
+            }
+        }
+        // unknown commands are skipped, like in Redis
+        if check_name(subcommand, 0, "docs") {
+            if let Some(commands) = selected_commands(&v[2..]) {
+                let commands: Vec<&CommandInfo> = commands.into_iter().flatten().collect();
+                resp_encode_array_len(commands.len() * 2, result);
+                for info in commands {
+                    resp_encode_binary_string(info.name.as_bytes(), result);
+                    resp_encode_array_len(4, result);
+                    resp_encode_binary_string(b"summary", result);
+                    resp_encode_binary_string(info.summary.as_bytes(), result);
+                    resp_encode_binary_string(b"group", result);
+                    resp_encode_binary_string(info.group.as_bytes(), result);
+                }
+                return;
+            }
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+pub fn run_config_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 2 {
+        if let RespBinaryString(subcommand) = &v[1] {
+            if check_name(subcommand, 0, "resetstat") {
+                common_data.reset_stats();
+                result.extend_from_slice(OK);
+                return;
+            }
+            if check_name(subcommand, 0, "reload") {
+                match common_data.reload_configuration() {
+                    Ok(()) => result.extend_from_slice(OK),
+                    Err(e) => resp_encode_error(&e, result)
+                }
+                return;
+            }
+            if check_name(subcommand, 0, "rewrite") {
+                match common_data.rewrite_configuration() {
+                    Ok(()) => result.extend_from_slice(OK),
+                    Err(e) => resp_encode_error(&e, result)
+                }
+                return;
+            }
+        }
+    } else if v.len() == 3 {
+        if let RespBinaryString(subcommand) = &v[1] {
+            if check_name(subcommand, 0, "get") {
+                if let RespBinaryString(key) = &v[2] {
+                    if let Some(v) = common_data.get_configuration(key) {
+                        resp_encode_array2(key, &v, result);
+                        return;
+                    }
+                    result.extend_from_slice(NULL_ARRAY);
+                    return;
+                }
+            }
+        }
+    } else if v.len() == 4 {
+        if let RespBinaryString(subcommand) = &v[1] {
+            if check_name(subcommand, 0, "set") {
+                if let (RespBinaryString(key), RespBinaryString(value)) = (&v[2], &v[3]) {
+                    match common_data.set_configuration(key, value) {
+                        Ok(()) => result.extend_from_slice(OK),
+                        Err(e) => resp_encode_error(e, result)
+                    }
+                    return;
+                }
+            }
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+fn info_section_requested(section: Option<&Vec<u8>>, name: &str) -> bool {
+    match section {
+        Some(s) => check_name(s, 0, name) || check_name(s, 0, "all") || check_name(s, 0, "default") ||
+            check_name(s, 0, "everything"),
+        None => true
+    }
+}
+
+fn build_server_info(info: &mut String, common_data: &Arc<CommonData>) {
+    info.push_str("# Server\r\n");
+    info.push_str(&format!("redis_version:{}\r\n", env!("CARGO_PKG_VERSION")));
+    info.push_str(&format!("process_id:{}\r\n", std::process::id()));
+    let uptime = common_data.uptime();
+    info.push_str(&format!("uptime_in_seconds:{}\r\n", uptime));
+    info.push_str(&format!("uptime_in_days:{}\r\n", uptime / 86400));
+}
+
+fn build_clients_info(info: &mut String, common_data: &Arc<CommonData>) {
+    info.push_str("# Clients\r\n");
+    info.push_str(&format!("connected_clients:{}\r\n", common_data.connected_clients()));
+}
+
+// there is one database, like in Redis it is not reported when it is empty
+fn build_keyspace_info(info: &mut String, common_data: &Arc<CommonData>) {
+    info.push_str("# Keyspace\r\n");
+    let (keys, expires) = common_data.key_counts();
+    if keys > 0 {
+        info.push_str(&format!("db0:keys={},expires={},avg_ttl=0\r\n", keys, expires));
+    }
+}
+
+fn build_memory_info(info: &mut String, common_data: &Arc<CommonData>) {
+    let dataset = common_data.memory();
+    info.push_str("# Memory\r\n");
+    let used_memory = allocated_memory().unwrap_or(dataset);
+    info.push_str(&format!("used_memory:{}\r\n", used_memory));
+    if let Some(rss) = resident_memory() {
+        info.push_str(&format!("used_memory_rss:{}\r\n", rss));
+        if used_memory > 0 {
+            info.push_str(&format!("mem_fragmentation_ratio:{:.2}\r\n", rss as f64 / used_memory as f64));
+        }
+    }
+    info.push_str(&format!("used_memory_dataset:{}\r\n", dataset));
+    info.push_str(&format!("maxmemory:{}\r\n", common_data.max_memory()));
+    info.push_str(&format!("maxmemory_policy:{}\r\n", common_data.eviction_policy().get_name()));
+    info.push_str(&format!("maxmemory_source:{}\r\n",
+                           if common_data.use_allocator_stats { "allocator" } else { "estimate" }));
+    info.push_str(&format!("mem_allocator:{}\r\n", allocator_name()));
+    let (compressed_values, original_size, compressed_size) = common_data.compression_stats();
+    info.push_str(&format!("compressed_values:{}\r\n", compressed_values));
+    info.push_str(&format!("compressed_values_original_size:{}\r\n", original_size));
+    info.push_str(&format!("compressed_values_size:{}\r\n", compressed_size));
+    let ratio = if compressed_size == 0 { 1.0 } else { original_size as f64 / compressed_size as f64 };
+    info.push_str(&format!("compression_ratio:{:.2}\r\n", ratio));
+    let (shared_values, shared_values_memory) = common_data.shared_values_stats();
+    info.push_str(&format!("shared_values:{}\r\n", shared_values));
+    info.push_str(&format!("shared_values_memory:{}\r\n", shared_values_memory));
+    info.push_str(&format!("defragmented_maps:{}\r\n", common_data.defragmented_maps()));
+}
+
+fn build_stats_info(info: &mut String, common_data: &Arc<CommonData>) {
+    let (expired_keys, evicted_keys) = common_data.removal_stats();
+    info.push_str("# Stats\r\n");
+    info.push_str(&format!("expired_keys:{}\r\n", expired_keys));
+    info.push_str(&format!("evicted_keys:{}\r\n", evicted_keys));
+    let (hits, misses) = common_data.keyspace_stats();
+    info.push_str(&format!("keyspace_hits:{}\r\n", hits));
+    info.push_str(&format!("keyspace_misses:{}\r\n", misses));
+    common_data.network_stats.build_info(info);
+}
+
+pub fn run_info_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    let section = match v.len() {
+        1 => None,
+        2 => {
+            if let RespBinaryString(s) = &v[1] {
+                Some(s)
+            } else {
+                result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                return;
+            }
+        }
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let mut info = String::new();
+    if info_section_requested(section, "server") {
+        build_server_info(&mut info, &common_data);
+    }
+    if info_section_requested(section, "clients") {
+        build_clients_info(&mut info, &common_data);
+    }
+    if info_section_requested(section, "memory") {
+        build_memory_info(&mut info, &common_data);
+    }
+    if info_section_requested(section, "stats") {
+        build_stats_info(&mut info, &common_data);
+    }
+    // commandstats is not a part of the default sections
+    if let Some(s) = section {
+        if check_name(s, 0, "commandstats") || check_name(s, 0, "all") || check_name(s, 0, "everything") {
+            common_data.command_stats.build_info(&mut info);
+        }
+    }
+    if info_section_requested(section, "keyspace") {
+        build_keyspace_info(&mut info, &common_data);
+    }
+    resp_encode_string(&info, result);
+}
+
+// per key map statistics: key count, memory, lock acquisitions and the hottest key maps
+pub fn run_shardstats_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    let hottest_count = match v.len() {
+        1 => 5,
+        2 => match &v[1] {
+            RespInteger(n) if *n > 0 => *n as usize,
+            RespBinaryString(s) => match parse_number_from_vec(s) {
+                Some(n) if n > 0 => n as usize,
+                _ => {
+                    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                    return;
+                }
+            },
+            _ => {
+                result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                return;
+            }
+        },
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let stats = common_data.shard_stats();
+    let keys_min = stats.iter().map(|s| s.keys).min().unwrap_or(0);
+    let keys_max = stats.iter().map(|s| s.keys).max().unwrap_or(0);
+    let mut info = "# Shards\r\n".to_string();
+    info.push_str(&format!("shards:{}\r\n", stats.len()));
+    info.push_str(&format!("keys_min:{}\r\n", keys_min));
+    info.push_str(&format!("keys_max:{}\r\n", keys_max));
+    info.push_str(&format!("keys_avg:{:.2}\r\n",
+                           stats.iter().map(|s| s.keys).sum::<usize>() as f64 / stats.len() as f64));
+    for (idx, s) in stats.iter().enumerate() {
+        info.push_str(&format!("shard{}:keys={},memory={},reads={},writes={},contended={}\r\n",
+                               idx, s.keys, s.memory, s.reads, s.writes, s.contended));
+    }
+    info.push_str("\r\n# Hottest\r\n");
+    let mut hottest: Vec<(usize, &ShardStats)> = stats.iter().enumerate().collect();
+    hottest.sort_by_key(|(_idx, s)| std::cmp::Reverse(s.reads + s.writes));
+    for (idx, s) in hottest.into_iter().take(hottest_count) {
+        info.push_str(&format!("shard{}:locks={},contended={}\r\n", idx, s.reads + s.writes, s.contended));
+    }
+    resp_encode_string(&info, result);
+}
+
+// memory stats: flat array of name -> value pairs like in Redis, db.0 and shards are nested arrays
+fn encode_memory_stats(result: &mut Vec<u8>, common_data: &Arc<CommonData>) {
+    let dataset = common_data.memory();
+    let used_memory = allocated_memory().unwrap_or(dataset);
+    let (keys, expires) = common_data.key_counts();
+    let stats = common_data.shard_stats();
+    let rss = resident_memory();
+    resp_encode_array_len(if rss.is_some() { 20 } else { 18 }, result);
+    resp_encode_binary_string(b"total.allocated", result);
+    resp_encode_int(used_memory as isize, result);
+    if let Some(rss) = rss {
+        resp_encode_binary_string(b"allocator.resident", result);
+        resp_encode_int(rss as isize, result);
+    }
+    resp_encode_binary_string(b"maxmemory", result);
+    resp_encode_int(common_data.max_memory() as isize, result);
+    resp_encode_binary_string(b"dataset.bytes", result);
+    resp_encode_int(dataset as isize, result);
+    resp_encode_binary_string(b"dataset.percentage", result);
+    let percentage = if used_memory == 0 { 0.0 } else { dataset as f64 * 100.0 / used_memory as f64 };
+    resp_encode_string(&format!("{:.2}", percentage), result);
+    resp_encode_binary_string(b"shared.values.bytes", result);
+    resp_encode_int(common_data.shared_values_stats().1 as isize, result);
+    resp_encode_binary_string(b"keys.count", result);
+    resp_encode_int(keys as isize, result);
+    resp_encode_binary_string(b"keys.bytes-per-key", result);
+    resp_encode_int(dataset.checked_div(keys).unwrap_or(0) as isize, result);
+    resp_encode_binary_string(b"db.0", result);
+    resp_encode_array_len(4, result);
+    resp_encode_binary_string(b"keys", result);
+    resp_encode_int(keys as isize, result);
+    resp_encode_binary_string(b"expires", result);
+    resp_encode_int(expires as isize, result);
+    resp_encode_binary_string(b"shards", result);
+    resp_encode_array_len(stats.len(), result);
+    for s in &stats {
+        resp_encode_array_len(4, result);
+        resp_encode_binary_string(b"keys", result);
+        resp_encode_int(s.keys as isize, result);
+        resp_encode_binary_string(b"bytes", result);
+        resp_encode_int(s.memory as isize, result);
+    }
+}
+
+// memory doctor: one line per found issue
+fn build_memory_report(common_data: &Arc<CommonData>) -> String {
+    let (keys, _expires) = common_data.key_counts();
+    if keys == 0 {
+        return "The instance is empty, there is nothing to diagnose.\r\n".to_string();
+    }
+    let dataset = common_data.memory();
+    let used_memory = allocated_memory().unwrap_or(dataset);
+    let max_memory = common_data.max_memory();
+    let mut report = String::new();
+    if max_memory > 0 && used_memory as u128 * 100 >= max_memory as u128 * 90 {
+        report.push_str(&format!("Used memory ({} bytes) is over 90% of maxmemory ({} bytes), keys are evicted by the {} policy or writes are rejected with noeviction, consider raising maxmemory.\r\n",
+                                 used_memory, max_memory, common_data.eviction_policy().get_name()));
+    }
+    if let Some(rss) = resident_memory() {
+        // small instances always have a high ratio because of the fixed process memory
+        if used_memory > 0 && rss > 64 * 1024 * 1024 && rss as f64 / used_memory as f64 > 1.5 {
+            report.push_str(&format!("High fragmentation: resident memory ({} bytes) is {:.2} times the used memory, memory purge releases unused capacity of the key maps.\r\n",
+                                     rss, rss as f64 / used_memory as f64));
+        }
+    }
+    let stats = common_data.shard_stats();
+    if stats.len() > 1 {
+        let average = stats.iter().map(|s| s.memory).sum::<usize>() / stats.len();
+        let (idx, biggest) = stats.iter().enumerate().max_by_key(|(_idx, s)| s.memory).unwrap();
+        if average > 0 && biggest.memory > average * 2 {
+            report.push_str(&format!("Key maps are unbalanced: shard{} uses {} bytes while the average is {} bytes, check the key distribution or the hash builder (--hb).\r\n",
+                                     idx, biggest.memory, average));
+        }
+    }
+    if report.is_empty() {
+        report.push_str("No memory issues found.\r\n");
+    }
+    report
+}
+
+pub fn run_memory_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 2 {
+        if let RespBinaryString(subcommand) = &v[1] {
+            if check_name(subcommand, 0, "purge") {
+                common_data.defrag(true);
+                result.extend_from_slice(OK);
+                return;
+            }
+            if check_name(subcommand, 0, "stats") {
+                encode_memory_stats(result, &common_data);
+                return;
+            }
+            if check_name(subcommand, 0, "doctor") {
+                resp_encode_string(&build_memory_report(&common_data), result);
+                return;
+            }
+        }
+    } else if v.len() == 3 || v.len() == 5 {
+        // memory usage key [samples count], samples are ignored because set and sorted set sizes are
+        // tracked exactly
+        if let (RespBinaryString(subcommand), RespBinaryString(key)) = (&v[1], &v[2]) {
+            if check_name(subcommand, 0, "usage") {
+                if v.len() == 5 {
+                    match &v[3] {
+                        RespBinaryString(option) if check_name(option, 0, "samples") && parse_usize(&v[4]).is_some() => {}
+                        _ => {
+                            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                            return;
+                        }
+                    }
+                }
+                match common_data.memory_usage(key) {
+                    Some(m) => resp_encode_int(m as isize, result),
+                    None => result.extend_from_slice(NULL_STRING)
+                }
+                return;
+            }
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// latency histogram [command ...] - map of command name -> calls and cumulative power of two histogram,
+// encoded as flat arrays, commands without calls are skipped
+pub fn run_latency_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() >= 2 {
+        if let RespBinaryString(subcommand) = &v[1] {
+            if check_name(subcommand, 0, "histogram") {
+                let mut commands = Vec::new();
+                if v.len() == 2 {
+                    commands.extend(0..COMMANDS.len());
+                } else {
+                    for t in &v[2..] {
+                        if let RespBinaryString(name) = t {
+                            if let Some(idx) = CommandStats::find(name) {
+                                commands.push(idx);
+                            }
+                        }
+                    }
+                }
+                let stats = &common_data.command_stats;
+                commands.retain(|idx| stats.calls(*idx) > 0);
+                resp_encode_array_len(commands.len() * 2, result);
+                for idx in commands {
+                    resp_encode_binary_string(COMMANDS[idx].as_bytes(), result);
+                    resp_encode_array_len(4, result);
+                    resp_encode_binary_string(b"calls", result);
+                    resp_encode_int(stats.calls(idx) as isize, result);
+                    resp_encode_binary_string(b"histogram_usec", result);
+                    let histogram = stats.histogram(idx);
+                    resp_encode_array_len(histogram.len() * 2, result);
+                    for (bound, count) in histogram {
+                        resp_encode_int(bound as isize, result);
+                        resp_encode_int(count as isize, result);
+                    }
+                }
+                return;
+            }
+            let monitor = &common_data.latency_monitor;
+            if check_name(subcommand, 0, "latest") && v.len() == 2 {
+                let latest = monitor.latest();
+                resp_encode_array_len(latest.len(), result);
+                for (event, time, latency, max) in latest {
+                    resp_encode_array_len(4, result);
+                    resp_encode_binary_string(event.as_bytes(), result);
+                    resp_encode_int(time as isize, result);
+                    resp_encode_int(latency as isize, result);
+                    resp_encode_int(max as isize, result);
+                }
+                return;
+            }
+            if check_name(subcommand, 0, "history") && v.len() == 3 {
+                if let RespBinaryString(event) = &v[2] {
+                    let history = monitor.history(event);
+                    resp_encode_array_len(history.len(), result);
+                    for (time, latency) in history {
+                        resp_encode_array_len(2, result);
+                        resp_encode_int(time as isize, result);
+                        resp_encode_int(latency as isize, result);
+                    }
+                    return;
+                }
+            }
+            if check_name(subcommand, 0, "reset") {
+                if let Some(events) = parse_members(&v[2..]) {
+                    let events: Vec<&[u8]> = events.into_iter().map(|e| e.as_slice()).collect();
+                    resp_encode_int(monitor.reset(&events) as isize, result);
+                    return;
+                }
+                if v.len() == 2 {
+                    resp_encode_int(monitor.reset(&[]) as isize, result);
+                    return;
+                }
+            }
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// expire key seconds, pexpire key milliseconds (multiplier = 1000 or 1), a key with not positive time to live is removed
+pub fn run_expire_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>, multiplier: isize) {
+    if v.len() == 3 {
+        if let RespBinaryString(key) = &v[1] {
+            match parse_isize(&v[2]) {
+                Some(ttl) if ttl <= 0 => resp_encode_int(common_data.removekeys(vec![key]), result),
+                Some(ttl) => match ttl.checked_mul(multiplier) {
+                    Some(ms) => resp_encode_int(common_data.expire(key, Some(ms as u64)) as isize, result),
+                    None => resp_encode_error("ERR invalid expire time", result)
+                },
+                None => resp_encode_error("ERR value is not an integer or out of range", result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// GETRANGE key start end
+pub fn run_getrange_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 4 {
+        if let RespBinaryString(key) = &v[1] {
+            match (parse_isize(&v[2]), parse_isize(&v[3])) {
+                (Some(start), Some(end)) => match common_data.get_range(key, start, end) {
+                    Ok(range) => resp_encode_binary_string(&range, result),
+                    Err(e) => resp_encode_error(e, result)
+                },
+                _ => resp_encode_error("ERR value is not an integer or out of range", result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// SETRANGE key offset value, returns the new value length
+pub fn run_setrange_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 4 {
+        if let (RespBinaryString(key), RespBinaryString(value)) = (&v[1], &v[3]) {
+            match parse_isize(&v[2]) {
+                Some(offset) if offset >= 0 => match common_data.set_range(key, offset as usize, value) {
+                    Ok(len) => resp_encode_int(len as isize, result),
+                    Err(e) => resp_encode_error(e, result)
+                },
+                Some(_) => resp_encode_error("ERR offset is out of range", result),
+                None => resp_encode_error("ERR value is not an integer or out of range", result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// time to live in ms for an absolute UNIX time in ms, 0 - the time is in the past
+pub fn unix_time_to_ttl(unix_time: u64) -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+    unix_time.saturating_sub(now)
+}
+
+// EXPIREAT key unix-time-seconds (multiplier = 1000), PEXPIREAT key unix-time-milliseconds (multiplier = 1),
+// a time in the past removes the key
+pub fn run_expireat_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>, multiplier: isize) {
+    if v.len() == 3 {
+        if let RespBinaryString(key) = &v[1] {
+            match parse_isize(&v[2]).map(|t| t.max(0).checked_mul(multiplier)) {
+                Some(Some(time)) => match unix_time_to_ttl(time as u64) {
+                    0 => resp_encode_int(common_data.removekeys(vec![key]), result),
+                    ttl => resp_encode_int(common_data.expire(key, Some(ttl)) as isize, result)
+                },
+                Some(None) => resp_encode_error("ERR invalid expire time", result),
+                None => resp_encode_error("ERR value is not an integer or out of range", result)
+            }
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | PERSIST]
+pub fn run_getex_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    let key = match v.get(1) {
+        Some(RespBinaryString(key)) => key,
+        _ => {
+            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+            return;
+        }
+    };
+    let option = match v.get(2) {
+        Some(RespBinaryString(option)) => option.to_ascii_lowercase(),
+        Some(_) => {
+            resp_encode_error(SYNTAX_ERROR, result);
+            return;
+        }
+        None => {
+            run_get_command(v, result, common_data);
+            return;
+        }
+    };
+    let ttl = match (option.as_slice(), v.len()) {
+        (b"persist", 3) => None,
+        (b"ex" | b"px" | b"exat" | b"pxat", 4) => {
+            let multiplier = if option[0] == b'e' { 1000 } else { 1 };
+            match parse_isize(&v[3]).and_then(|t| t.checked_mul(multiplier)) {
+                Some(t) if t > 0 => {
+                    if option.len() == 4 {
+                        Some(unix_time_to_ttl(t as u64))
+                    } else {
+                        Some(t as u64)
+                    }
+                }
+                Some(_) => {
+                    resp_encode_error("ERR invalid expire time in 'getex' command", result);
+                    return;
+                }
+                None => {
+                    resp_encode_error("ERR value is not an integer or out of range", result);
+                    return;
+                }
+            }
+        }
+        _ => {
+            resp_encode_error(SYNTAX_ERROR, result);
+            return;
+        }
+    };
+    match common_data.get_ex(key, ttl, result) {
+        Ok(true) => {}
+        Ok(false) => result.extend_from_slice(NULL_STRING),
+        Err(e) => resp_encode_error(e, result)
+    }
+}
+
+pub fn run_persist_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+    if v.len() == 2 {
+        if let RespBinaryString(key) = &v[1] {
+            resp_encode_int(common_data.persist(key) as isize, result);
+            return;
+        }
+    }
+    result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+}
+
+// ttl key, pttl key (divisor = 1000 or 1), -2 - the key is not found, -1 - the key does not expire
+pub fn run_ttl_command(v: Vec<RespToken>, result: &mut Vec<u8>, common_data: Arc<CommonData>, divisor: u64) {
+    if v.len() == 2 {
+        if let RespBinaryString(key) = &v[1] {
+            let ttl = match common_data.ttl(key) {
+                Some(Some(ttl)) => ((ttl + divisor / 2) / divisor) as isize,
+                Some(None) => -1,
+                None => -2
+            };
+            resp_encode_int(ttl, result);
+            return;
+        }
+    }
     result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
 }
@@ -19,7 +19,13 @@ pub fn resp_encode_array2(c1: &Vec<u8>, c2: &Vec<u8>, result: &mut Vec<u8>) {
     resp_encode_binary_string(c2, result);
 }
 
-pub fn resp_encode_binary_string(string: &Vec<u8>, result: &mut Vec<u8>) {
+pub fn resp_encode_array_len(len: usize, result: &mut Vec<u8>) {
+    result.push(b'*');
+    result.extend(len.to_string().into_bytes());
+    result.extend_from_slice(RN);
+}
+
+pub fn resp_encode_binary_string(string: &[u8], result: &mut Vec<u8>) {
     result.push('$' as u8);
     result.extend(string.len().to_string().into_bytes());
     result.extend_from_slice(RN);
@@ -35,6 +41,12 @@ pub fn resp_encode_string(string: &String, result: &mut Vec<u8>) {
     result.extend_from_slice(RN);
 }
 
+pub fn resp_encode_error(message: &str, result: &mut Vec<u8>) {
+    result.push(b'-');
+    result.extend_from_slice(message.as_bytes());
+    result.extend_from_slice(RN);
+}
+
 pub fn resp_encode_strings(commands: &Vec<String>) -> Vec<u8> {
     let mut result = Vec::new();
     result.push('*' as u8);
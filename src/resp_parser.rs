@@ -1,17 +1,29 @@
 use std::sync::Arc;
-use crate::resp_commands::{run_config_command, run_dbsize_command, run_del_command, run_flush_command, run_get_command, run_ping_command, run_select_command, run_set_command};
-use crate::resp_parser::RespToken::{RespArray, RespBinaryString, RespInteger, RespNullArray, RespNullString, RespString};
+use std::time::Instant;
+#[cfg(feature = "server")]
+use std::time::SystemTime;
+use crate::resp_commands::{run_bitcount_command, run_bitop_command, run_client_command, run_command_command, run_config_command, run_dbsize_command, run_debug_command, run_del_command, run_exists_command, run_expire_command, run_expireat_command, run_flush_command, run_geoadd_command, run_geodist_command, run_geosearch_command, run_getbit_command, run_getex_command, run_getrange_command, run_incrbyfloat_command, run_mget_command, run_mset_command, run_persist_command, run_publish_command, run_get_command, run_info_command, run_latency_command, run_memory_command, run_ping_command, run_sadd_command, run_scan_command, run_scard_command, run_set_operation_command, run_select_command, run_set_command, run_setbit_command, run_setex_command, run_setnx_command, run_setrange_command, run_shardstats_command, run_sismember_command, run_smembers_command, run_smismember_command, run_spop_command, run_sscan_command, run_subscribe_command, run_touch_command, run_ttl_command, run_type_command, run_zadd_command, run_zcard_command, run_zincrby_command, run_zpop_command, run_zrange_command, run_zrank_command, run_zrem_command, run_zrangebyscore_command, run_zscore_command};
+use crate::member_set::SetOperation;
+use crate::resp_parser::RespToken::{RespArray, RespBinaryString, RespError, RespInteger, RespNullArray, RespNullString, RespString};
+use crate::resp_parser::ParseError::{Incomplete, Invalid};
+use crate::command_stats::{CommandStats, COMMANDS};
 use crate::common_data::CommonData;
+use crate::resp_codec::Decoder;
+#[cfg(feature = "server")]
+use crate::otlp::{command_keys, Span};
+use crate::resp_encoder::resp_encode_error;
 
 pub trait RespCommand {
     fn run(&self, common_data: Arc<CommonData>) -> Vec<u8>;
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum RespToken {
     RespArray(Vec<RespToken>),
+    // simple string or inline command
     RespString(Vec<u8>),
     RespBinaryString(Vec<u8>),
+    RespError(Vec<u8>),
     RespInteger(isize),
     RespNullArray,
     RespNullString
@@ -19,14 +31,22 @@ pub enum RespToken {
 
 pub static INVALID_COMMAND_ERROR: &str = "-invalid command\r\n";
 
-pub fn resp_parse(buffer: &[u8], amt: usize, common_data: Arc<CommonData>) -> Vec<u8> {
-    let tokens = match parse_tokens(buffer, amt) {
-        Ok(t) => t,
-        Err(e) => return Vec::from(e)
-    };
+// runs all complete commands from the decoder, an incomplete command stays in the decoder
+// until the rest of it is received, after a parse error the decoder buffer is cleared
+// client - connection id in CommonData::threads, needed by the pub/sub and client commands
+#[cfg(any(feature = "server", test))]
+pub fn resp_run(decoder: &mut Decoder, common_data: Arc<CommonData>, client: Option<usize>) -> Vec<u8> {
     let mut result = Vec::new();
-    for token in tokens {
-        run_command(token, &mut result, common_data.clone());
+    loop {
+        match decoder.next_frame() {
+            Ok(Some(token)) => run_command(token, &mut result, common_data.clone(), client),
+            Ok(None) => break,
+            Err(e) => {
+                result.extend_from_slice(e.as_bytes());
+                decoder.clear();
+                break;
+            }
+        }
     }
     result
 }
@@ -46,7 +66,61 @@ pub fn check_name(s: &Vec<u8>, idx: usize, expected: &str) -> bool {
     false
 }
 
-fn run_command(token: RespToken, result: &mut Vec<u8>, common_data: Arc<CommonData>) {
+// commands a connection can run while it is subscribed to channels or patterns
+fn allowed_when_subscribed(token: &RespToken) -> bool {
+    let name = match token {
+        RespArray(v) => match v.first() {
+            Some(RespBinaryString(s)) => s,
+            _ => return false
+        },
+        RespString(s) => s,
+        _ => return false
+    };
+    ["subscribe", "unsubscribe", "psubscribe", "punsubscribe", "ping", "quit"].iter().any(|c| c.as_bytes().eq_ignore_ascii_case(name))
+}
+
+// runs the command and updates the command statistics, an error reply counts as a failed call
+pub fn run_command(token: RespToken, result: &mut Vec<u8>, common_data: Arc<CommonData>, client: Option<usize>) {
+    common_data.network_stats.command_processed();
+    if client.is_some_and(|c| common_data.pubsub.is_subscribed(c)) && !allowed_when_subscribed(&token) {
+        resp_encode_error("ERR only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT are allowed in this context", result);
+        return;
+    }
+    let command = match &token {
+        RespArray(v) => match v.first() {
+            Some(RespBinaryString(s)) => CommandStats::find(s),
+            _ => None
+        },
+        RespString(s) => CommandStats::find(s),
+        _ => None
+    };
+    match command {
+        Some(idx) => {
+            if let Some(connection) = client.and_then(|c| common_data.connection(c)) {
+                connection.command_started(COMMANDS[idx]);
+            }
+            #[cfg(feature = "server")]
+            let span = common_data.span_exporter().map(|_e| {
+                let (keys, first_key) = command_keys(COMMANDS[idx], &token);
+                (keys, first_key.map(|k| common_data.key_map_index(k)), SystemTime::now())
+            });
+            let start = result.len();
+            let now = Instant::now();
+            dispatch_command(token, result, common_data.clone(), client);
+            let duration = now.elapsed();
+            let failed = result.get(start) == Some(&b'-');
+            common_data.command_stats.add(idx, duration, failed);
+            common_data.latency_monitor.add_sample("command", duration);
+            #[cfg(feature = "server")]
+            if let (Some(exporter), Some((keys, shard, start))) = (common_data.span_exporter(), span) {
+                exporter.export(Span { command: COMMANDS[idx], keys, shard, start, duration, failed });
+            }
+        }
+        None => dispatch_command(token, result, common_data, client)
+    }
+}
+
+fn dispatch_command(token: RespToken, result: &mut Vec<u8>, common_data: Arc<CommonData>, client: Option<usize>) {
     match token {
         RespArray(v) => {
             if v.len() > 0 {
@@ -54,9 +128,22 @@ fn run_command(token: RespToken, result: &mut Vec<u8>, common_data: Arc<CommonDa
                     RespBinaryString(s) => {
                         if s.len() > 0 {
                             match s[0] as char {
+                                'b'|'B' => {
+                                    if check_name(s, 1, "itcount") {
+                                        run_bitcount_command(v, result, common_data);
+                                    } else if check_name(s, 1, "itop") {
+                                        run_bitop_command(v, result, common_data);
+                                    } else {
+                                        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                    }
+                                },
                                 'c'|'C' => {
                                     if check_name(s, 1, "onfig") {
                                         run_config_command(v, result, common_data);
+                                    } else if check_name(s, 1, "ommand") {
+                                        run_command_command(v, result);
+                                    } else if check_name(s, 1, "lient") {
+                                        run_client_command(v, result, common_data, client);
                                     } else {
                                         result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
                                     }
@@ -68,6 +155,11 @@ fn run_command(token: RespToken, result: &mut Vec<u8>, common_data: Arc<CommonDa
                                         } else {
                                             result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
                                         },
+                                        5 => if check_name(s, 1, "ebug") {
+                                            run_debug_command(v, result, common_data);
+                                        } else {
+                                            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                        },
                                         6 => if check_name(s, 1, "bsize") {
                                             run_dbsize_command(result, common_data);
                                         } else {
@@ -76,6 +168,17 @@ fn run_command(token: RespToken, result: &mut Vec<u8>, common_data: Arc<CommonDa
                                         _ => result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes())
                                     }
                                 },
+                                'e'|'E' => {
+                                    if check_name(s, 1, "xpire") {
+                                        run_expire_command(v, result, common_data, 1000);
+                                    } else if check_name(s, 1, "xpireat") {
+                                        run_expireat_command(v, result, common_data, 1000);
+                                    } else if check_name(s, 1, "xists") {
+                                        run_exists_command(v, result, common_data);
+                                    } else {
+                                        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                    }
+                                },
                                 'f'|'F' => {
                                     match s.len() {
                                         7 => {
@@ -98,6 +201,47 @@ fn run_command(token: RespToken, result: &mut Vec<u8>, common_data: Arc<CommonDa
                                 'g'|'G' => {
                                     if check_name(s, 1, "et") {
                                         run_get_command(v, result, common_data);
+                                    } else if check_name(s, 1, "etex") {
+                                        run_getex_command(v, result, common_data);
+                                    } else if check_name(s, 1, "etrange") {
+                                        run_getrange_command(v, result, common_data);
+                                    } else if check_name(s, 1, "etbit") {
+                                        run_getbit_command(v, result, common_data);
+                                    } else if check_name(s, 1, "eoadd") {
+                                        run_geoadd_command(v, result, common_data);
+                                    } else if check_name(s, 1, "eodist") {
+                                        run_geodist_command(v, result, common_data);
+                                    } else if check_name(s, 1, "eosearch") {
+                                        run_geosearch_command(v, result, common_data);
+                                    } else {
+                                        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                    }
+                                },
+                                'i'|'I' => {
+                                    if check_name(s, 1, "nfo") {
+                                        run_info_command(v, result, common_data);
+                                    } else if check_name(s, 1, "ncrbyfloat") {
+                                        run_incrbyfloat_command(v, result, common_data);
+                                    } else {
+                                        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                    }
+                                },
+                                'l'|'L' => {
+                                    if check_name(s, 1, "atency") {
+                                        run_latency_command(v, result, common_data);
+                                    } else {
+                                        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                    }
+                                },
+                                'm'|'M' => {
+                                    if check_name(s, 1, "emory") {
+                                        run_memory_command(v, result, common_data);
+                                    } else if check_name(s, 1, "get") {
+                                        run_mget_command(v, result, common_data);
+                                    } else if check_name(s, 1, "set") {
+                                        run_mset_command(v, result, common_data, false);
+                                    } else if check_name(s, 1, "setnx") {
+                                        run_mset_command(v, result, common_data, true);
                                     } else {
                                         result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
                                     }
@@ -109,17 +253,140 @@ fn run_command(token: RespToken, result: &mut Vec<u8>, common_data: Arc<CommonDa
                                         } else {
                                             result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
                                         },
+                                        4 => if check_name(s, 1, "can") {
+                                            run_scan_command(v, result, common_data);
+                                        } else if check_name(s, 1, "add") {
+                                            run_sadd_command(v, result, common_data, true);
+                                        } else if check_name(s, 1, "rem") {
+                                            run_sadd_command(v, result, common_data, false);
+                                        } else if check_name(s, 1, "pop") {
+                                            run_spop_command(v, result, common_data, true);
+                                        } else {
+                                            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                        },
+                                        5 => if check_name(s, 1, "etnx") {
+                                            run_setnx_command(v, result, common_data);
+                                        } else if check_name(s, 1, "etex") {
+                                            run_setex_command(v, result, common_data, 1000);
+                                        } else if check_name(s, 1, "card") {
+                                            run_scard_command(v, result, common_data);
+                                        } else if check_name(s, 1, "scan") {
+                                            run_sscan_command(v, result, common_data);
+                                        } else if check_name(s, 1, "diff") {
+                                            run_set_operation_command(v, result, common_data, SetOperation::Difference, false);
+                                        } else {
+                                            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                        },
                                         6 => if check_name(s, 1, "elect") {
-                                            run_select_command(result);
+                                            run_select_command(v, result);
+                                        } else if check_name(s, 1, "inter") {
+                                            run_set_operation_command(v, result, common_data, SetOperation::Intersection, false);
+                                        } else if check_name(s, 1, "union") {
+                                            run_set_operation_command(v, result, common_data, SetOperation::Union, false);
+                                        } else if check_name(s, 1, "etbit") {
+                                            run_setbit_command(v, result, common_data);
+                                        } else {
+                                            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                        },
+                                        8 => if check_name(s, 1, "etrange") {
+                                            run_setrange_command(v, result, common_data);
+                                        } else if check_name(s, 1, "members") {
+                                            run_smembers_command(v, result, common_data);
+                                        } else {
+                                            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                        },
+                                        9 => if check_name(s, 1, "ismember") {
+                                            run_sismember_command(v, result, common_data);
+                                        } else if check_name(s, 1, "ubscribe") {
+                                            run_subscribe_command(v, result, common_data, client, true, false);
+                                        } else {
+                                            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                        },
+                                        10 => if check_name(s, 1, "hardstats") {
+                                            run_shardstats_command(v, result, common_data);
+                                        } else if check_name(s, 1, "diffstore") {
+                                            run_set_operation_command(v, result, common_data, SetOperation::Difference, true);
+                                        } else if check_name(s, 1, "mismember") {
+                                            run_smismember_command(v, result, common_data);
+                                        } else {
+                                            result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                        },
+                                        11 => if check_name(s, 1, "randmember") {
+                                            run_spop_command(v, result, common_data, false);
+                                        } else if check_name(s, 1, "interstore") {
+                                            run_set_operation_command(v, result, common_data, SetOperation::Intersection, true);
+                                        } else if check_name(s, 1, "unionstore") {
+                                            run_set_operation_command(v, result, common_data, SetOperation::Union, true);
                                         } else {
                                             result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
                                         },
                                         _ => result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes())
                                     }
                                 },
+                                't'|'T' => {
+                                    if check_name(s, 1, "ype") {
+                                        run_type_command(v, result, common_data);
+                                    } else if check_name(s, 1, "tl") {
+                                        run_ttl_command(v, result, common_data, 1000);
+                                    } else if check_name(s, 1, "ouch") {
+                                        run_touch_command(v, result, common_data);
+                                    } else {
+                                        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                    }
+                                },
+                                'u'|'U' => {
+                                    if check_name(s, 1, "nsubscribe") {
+                                        run_subscribe_command(v, result, common_data, client, false, false);
+                                    } else {
+                                        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                    }
+                                },
                                 'p'|'P' => {
                                     if check_name(s, 1, "ing") {
                                         run_ping_command(v, result);
+                                    } else if check_name(s, 1, "ublish") {
+                                        run_publish_command(v, result, common_data);
+                                    } else if check_name(s, 1, "ttl") {
+                                        run_ttl_command(v, result, common_data, 1);
+                                    } else if check_name(s, 1, "expire") {
+                                        run_expire_command(v, result, common_data, 1);
+                                    } else if check_name(s, 1, "expireat") {
+                                        run_expireat_command(v, result, common_data, 1);
+                                    } else if check_name(s, 1, "setex") {
+                                        run_setex_command(v, result, common_data, 1);
+                                    } else if check_name(s, 1, "ersist") {
+                                        run_persist_command(v, result, common_data);
+                                    } else if check_name(s, 1, "subscribe") {
+                                        run_subscribe_command(v, result, common_data, client, true, true);
+                                    } else if check_name(s, 1, "unsubscribe") {
+                                        run_subscribe_command(v, result, common_data, client, false, true);
+                                    } else {
+                                        result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
+                                    }
+                                },
+                                'z'|'Z' => {
+                                    if check_name(s, 1, "add") {
+                                        run_zadd_command(v, result, common_data);
+                                    } else if check_name(s, 1, "score") {
+                                        run_zscore_command(v, result, common_data);
+                                    } else if check_name(s, 1, "card") {
+                                        run_zcard_command(v, result, common_data);
+                                    } else if check_name(s, 1, "range") {
+                                        run_zrange_command(v, result, common_data);
+                                    } else if check_name(s, 1, "rangebyscore") {
+                                        run_zrangebyscore_command(v, result, common_data);
+                                    } else if check_name(s, 1, "incrby") {
+                                        run_zincrby_command(v, result, common_data);
+                                    } else if check_name(s, 1, "rank") {
+                                        run_zrank_command(v, result, common_data, false);
+                                    } else if check_name(s, 1, "revrank") {
+                                        run_zrank_command(v, result, common_data, true);
+                                    } else if check_name(s, 1, "rem") {
+                                        run_zrem_command(v, result, common_data);
+                                    } else if check_name(s, 1, "popmin") {
+                                        run_zpop_command(v, result, common_data, false);
+                                    } else if check_name(s, 1, "popmax") {
+                                        run_zpop_command(v, result, common_data, true);
                                     } else {
                                         result.extend_from_slice(INVALID_COMMAND_ERROR.as_bytes());
                                     }
@@ -156,82 +423,82 @@ fn run_command(token: RespToken, result: &mut Vec<u8>, common_data: Arc<CommonDa
     }
 }
 
-fn parse_tokens(buffer: &[u8], amt: usize) -> Result<Vec<RespToken>, &'static str>  {
-    let mut idx = 0;
-    let mut tokens = Vec::new();
-    while idx < amt {
-        let (new_idx, token) = parse_token(buffer, idx, amt)?;
-        idx = new_idx;
-        tokens.push(token);
-    }
-    if idx != amt {
-        return Err(INVALID_COMMAND_ERROR);
-    }
-    Ok(tokens)
+// Incomplete - the buffer ends in the middle of a token, Invalid - the buffer is not valid RESP
+#[derive(PartialEq, Debug)]
+pub enum ParseError {
+    Incomplete,
+    Invalid,
 }
 
-fn parse_token(buffer: &[u8], idx: usize, amt: usize) -> Result<(usize, RespToken), &'static str> {
+pub fn parse_token(buffer: &[u8], idx: usize, amt: usize) -> Result<(usize, RespToken), ParseError> {
     if idx < amt {
-        match buffer[idx] as char {
-            '*' => {
-                let (new_idx, token) = parse_array(buffer, idx + 1, amt)?;
-                Ok((new_idx, token))
-            }
-            '$' => {
-                let (new_idx, token) = parse_binary_string(buffer, idx + 1, amt)?;
-                Ok((new_idx, token))
-            }
-            ':' => {
+        match buffer[idx] {
+            b'*' => parse_array(buffer, idx + 1, amt),
+            b'$' => parse_binary_string(buffer, idx + 1, amt),
+            b':' => {
                 let (new_idx, n) = parse_number(buffer, idx + 1, amt)?;
                 Ok((new_idx, RespInteger(n)))
             }
+            b'+' => {
+                let (new_idx, s) = parse_string(buffer, idx + 1, amt)?;
+                Ok((new_idx, RespString(s)))
+            }
+            b'-' => {
+                let (new_idx, s) = parse_string(buffer, idx + 1, amt)?;
+                Ok((new_idx, RespError(s)))
+            }
+            // inline command
             _ => {
                 let (new_idx, s) = parse_string(buffer, idx, amt)?;
                 Ok((new_idx, RespString(s)))
             }
         }
     } else {
-        Err(INVALID_COMMAND_ERROR)
+        Err(Incomplete)
     }
 }
 
-fn parse_string(buffer: &[u8], idx: usize, amt: usize) -> Result<(usize, Vec<u8>), &'static str> {
+fn parse_string(buffer: &[u8], idx: usize, amt: usize) -> Result<(usize, Vec<u8>), ParseError> {
     let mut new_idx = idx;
     while new_idx < amt {
-        if buffer[new_idx] == '\r' as u8 {
+        if buffer[new_idx] == b'\r' {
+            if new_idx + 2 > amt {
+                return Err(Incomplete);
+            }
             return Ok((new_idx+2, Vec::from(&buffer[idx..new_idx])));
         }
+        // inline commands can be terminated by \n only
+        if buffer[new_idx] == b'\n' {
+            return Ok((new_idx+1, Vec::from(&buffer[idx..new_idx])));
+        }
         new_idx += 1;
     }
-    Err(INVALID_COMMAND_ERROR)
+    Err(Incomplete)
 }
 
-fn parse_binary_string(buffer: &[u8], idx: usize, amt: usize) -> Result<(usize, RespToken), &'static str> {
+fn parse_binary_string(buffer: &[u8], idx: usize, amt: usize) -> Result<(usize, RespToken), ParseError> {
     let (new_idx, count) = parse_number(buffer, idx, amt)?;
     if count == -1 {
         return Ok((new_idx, RespNullString));
     }
-    if count == 0 {
-        return Ok((new_idx + 2, RespBinaryString(Vec::new())))
-    }
     if count < 0 {
-        return Err(INVALID_COMMAND_ERROR);
+        return Err(Invalid);
     }
     let string_end = new_idx + (count as usize);
     let end = string_end + 2;
     if end > amt {
-        return Err(INVALID_COMMAND_ERROR);
+        return Err(Incomplete);
     }
     Ok((end, RespBinaryString(Vec::from(&buffer[new_idx..string_end]))))
 }
 
-fn parse_array(buffer: &[u8], idx: usize, amt: usize) -> Result<(usize, RespToken), &'static str> {
+fn parse_array(buffer: &[u8], idx: usize, amt: usize) -> Result<(usize, RespToken), ParseError> {
     let (mut new_idx, n) = parse_number(buffer, idx, amt)?;
     if n == -1 {
         return Ok((new_idx, RespNullArray));
     }
     if n < 0 {
-        return Err(INVALID_COMMAND_ERROR);
+        return Err(Invalid);
     }
     let mut result = Vec::new();
     for _i in 0..n {
@@ -242,44 +509,68 @@ fn parse_array(buffer: &[u8], idx: usize, amt: usize) -> Result<(usize, RespToke
     Ok((new_idx, RespArray(result)))
 }
 
-fn parse_number(buffer: &[u8], idx: usize, amt: usize) -> Result<(usize, isize), &'static str> {
+fn parse_number(buffer: &[u8], idx: usize, amt: usize) -> Result<(usize, isize), ParseError> {
     let mut result = 0;
     let mut sign = 1;
     let mut new_idx = idx;
     loop {
         if new_idx >= amt {
-            break
+            return Err(Incomplete);
         }
         let c = buffer[new_idx];
-        match c as char {
-            '-' => sign = -sign,
-            '0'..='9' => result = result * 10 + (c - '0' as u8) as isize,
-            '\r' => {
+        match c {
+            b'-' => sign = -sign,
+            b'0'..=b'9' => result = result * 10 + (c - b'0') as isize,
+            b'\r' => {
                 if idx == new_idx {
                     break;
                 }
+                if new_idx + 2 > amt {
+                    return Err(Incomplete);
+                }
                 return Ok((new_idx + 2, result * sign));
             }
             _ => break
         }
         new_idx += 1;
     }
-    Err(INVALID_COMMAND_ERROR)
+    Err(Invalid)
 }
 
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
-    use crate::build_common_data;
+    use std::time::Instant;
+    use crate::common_data::{build_common_data, CommonData};
     use crate::hash_builders::create_hash_builder;
-    use crate::resp_parser::{parse_tokens, resp_parse};
+    use crate::resp_codec::Decoder;
+    use crate::resp_parser::{resp_run, RespToken, INVALID_COMMAND_ERROR};
+    use crate::values::ValueSettings;
     use crate::resp_parser::RespToken::{RespArray, RespBinaryString, RespInteger, RespString};
 
+    fn parse_tokens(buffer: &[u8]) -> Result<Vec<RespToken>, &'static str> {
+        let mut decoder = Decoder::new();
+        decoder.feed(buffer);
+        let mut tokens = Vec::new();
+        while let Some(token) = decoder.next_frame()? {
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    fn build_test_data() -> Arc<CommonData> {
+        Arc::new(build_common_data(false,
+                                   1000, 1,
+                                   create_hash_builder("sum".to_string(), 1).unwrap(), false,
+                                   ValueSettings{ compression_threshold: 0, integer_encoding: true,
+                                       intern_threshold: 0 }))
+    }
+
     const BUFFER: &[u8] = "PING\r\n*5\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\nex\r\n:10\r\n*3\r\n$6\r\nconfig\r\n$3\r\nget\r\n$4\r\nsave\r\n".as_bytes();
 
     #[test]
     fn test_parse_tokens() -> Result<(), &'static str> {
-        let result = parse_tokens(BUFFER, BUFFER.len())?;
+        let result = parse_tokens(BUFFER)?;
         assert_eq!(result.len(), 3);
         match &result[0] {
             RespString(s) => {
@@ -312,10 +603,379 @@ mod tests {
 
     #[test]
     fn test_parse() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed(&BUFFER[..20]);
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+PONG\r\n".as_bytes());
+        decoder.feed(&BUFFER[20..]);
+        let result = resp_run(&mut decoder, common_data, None);
+        assert_eq!(result.as_slice(), "+OK\r\n*2\r\n$4\r\nsave\r\n$0\r\n\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_info_commandstats() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed(BUFFER);
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+PONG\r\n+OK\r\n*2\r\n$4\r\nsave\r\n$0\r\n\r\n".as_bytes());
+        decoder.feed("*1\r\n$6\r\nconfig\r\n*2\r\n$4\r\ninfo\r\n$12\r\ncommandstats\r\n".as_bytes());
+        let result = String::from_utf8(resp_run(&mut decoder, common_data.clone(), None)).unwrap();
+        assert!(result.contains("cmdstat_ping:calls=1,"));
+        assert!(result.contains("cmdstat_set:calls=1,"));
+        assert!(result.contains("cmdstat_config:calls=2,"));
+        assert!(result.contains("failed_calls=1\r\n"));
+        assert!(!result.contains("# Keyspace"));
+        decoder.feed("*2\r\n$6\r\nconfig\r\n$9\r\nresetstat\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(), "+OK\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_info_sections() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed(BUFFER);
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+PONG\r\n+OK\r\n*2\r\n$4\r\nsave\r\n$0\r\n\r\n".as_bytes());
+        decoder.feed("*2\r\n$4\r\ninfo\r\n$8\r\nkeyspace\r\n*1\r\n$4\r\ninfo\r\n".as_bytes());
+        let result = String::from_utf8(resp_run(&mut decoder, common_data, None)).unwrap();
+        assert!(result.starts_with("$44\r\n# Keyspace\r\ndb0:keys=1,expires=1,avg_ttl=0\r\n\r\n$"));
+        assert!(result.contains("# Server\r\nredis_version:"));
+        assert!(result.contains("uptime_in_seconds:"));
+        assert!(result.contains("# Clients\r\nconnected_clients:0\r\n"));
+        assert!(!result.contains("cmdstat_"));
+    }
+
+    #[test]
+    fn test_command_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*2\r\n$7\r\ncommand\r\n$5\r\ncount\r\n*4\r\n$7\r\ncommand\r\n$4\r\ninfo\r\n$4\r\nMSET\r\n$1\r\nx\r\n*3\r\n$7\r\ncommand\r\n$4\r\ndocs\r\n$3\r\nget\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(),
+                   ":75\r\n*2\r\n*10\r\n$4\r\nmset\r\n:-3\r\n*2\r\n+write\r\n+denyoom\r\n:1\r\n:-1\r\n:2\r\n*0\r\n*0\r\n*0\r\n*0\r\n*-1\r\n\
+*2\r\n$3\r\nget\r\n*4\r\n$7\r\nsummary\r\n$34\r\nReturns the string value of a key.\r\n$5\r\ngroup\r\n$6\r\nstring\r\n".as_bytes());
+        decoder.feed("*1\r\n$7\r\ncommand\r\n".as_bytes());
+        assert!(resp_run(&mut decoder, common_data, None).starts_with(b"*75\r\n*10\r\n$8\r\nbitcount\r\n:-2\r\n"));
+    }
+
+    #[test]
+    fn test_select_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*2\r\n$6\r\nselect\r\n$2\r\n15\r\n*2\r\n$6\r\nselect\r\n$2\r\n16\r\n*2\r\n$6\r\nselect\r\n$1\r\nx\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "+OK\r\n-ERR DB index is out of range\r\n-ERR value is not an integer or out of range\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_latency_histogram() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("PING\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+PONG\r\n".as_bytes());
+        decoder.feed("*3\r\n$7\r\nlatency\r\n$9\r\nhistogram\r\n$4\r\nping\r\n".as_bytes());
+        let result = String::from_utf8(resp_run(&mut decoder, common_data, None)).unwrap();
+        assert!(result.starts_with("*2\r\n$4\r\nping\r\n*4\r\n$5\r\ncalls\r\n:1\r\n$14\r\nhistogram_usec\r\n*2\r\n"));
+    }
+
+    #[test]
+    fn test_expire_commands() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+OK\r\n".as_bytes());
+        decoder.feed("*3\r\n$6\r\nexpire\r\n$1\r\na\r\n$3\r\n100\r\n*2\r\n$3\r\nttl\r\n$1\r\na\r\n*2\r\n$4\r\npttl\r\n$1\r\nb\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), ":1\r\n:100\r\n:-2\r\n".as_bytes());
+        decoder.feed("*3\r\n$7\r\npexpire\r\n$1\r\na\r\n$1\r\n0\r\n*2\r\n$3\r\nttl\r\n$1\r\na\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(), ":1\r\n:-2\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_exists_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+OK\r\n".as_bytes());
+        decoder.feed("*4\r\n$6\r\nexists\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\na\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(), ":2\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_incrbyfloat_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+OK\r\n".as_bytes());
+        decoder.feed("*3\r\n$11\r\nincrbyfloat\r\n$1\r\nf\r\n$4\r\n10.5\r\n*3\r\n$11\r\nincrbyfloat\r\n$1\r\nf\r\n$3\r\n0.1\r\n*3\r\n$11\r\nincrbyfloat\r\n$1\r\na\r\n$1\r\n1\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "$4\r\n10.5\r\n$4\r\n10.6\r\n-ERR value is not a valid float\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_mset_commands() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*5\r\n$4\r\nmset\r\n$1\r\nx\r\n$1\r\n1\r\n$1\r\ny\r\n$1\r\n2\r\n*3\r\n$4\r\nmget\r\n$1\r\nx\r\n$1\r\nz\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+OK\r\n*2\r\n$1\r\n1\r\n$-1\r\n".as_bytes());
+        decoder.feed("*5\r\n$6\r\nmsetnx\r\n$1\r\nz\r\n$1\r\n3\r\n$1\r\nx\r\n$1\r\n3\r\n*2\r\n$3\r\nget\r\n$1\r\nz\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), ":0\r\n$-1\r\n".as_bytes());
+        decoder.feed("*3\r\n$6\r\nmsetnx\r\n$1\r\nz\r\n$1\r\n3\r\n*2\r\n$4\r\nmset\r\n$1\r\nz\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(), format!(":1\r\n{}", INVALID_COMMAND_ERROR).as_bytes());
+    }
+
+    #[test]
+    fn test_getex_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*4\r\n$6\r\npsetex\r\n$2\r\nex\r\n$5\r\n10000\r\n$1\r\n1\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+OK\r\n".as_bytes());
+        decoder.feed("*3\r\n$5\r\ngetex\r\n$2\r\nex\r\n$7\r\npersist\r\n*2\r\n$3\r\nttl\r\n$2\r\nex\r\n*4\r\n$5\r\ngetex\r\n$2\r\nex\r\n$2\r\npx\r\n$5\r\n50000\r\n*2\r\n$3\r\nttl\r\n$2\r\nex\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "$1\r\n1\r\n:-1\r\n$1\r\n1\r\n:50\r\n".as_bytes());
+        decoder.feed("*4\r\n$5\r\ngetex\r\n$2\r\nex\r\n$4\r\nexat\r\n$1\r\n1\r\n*2\r\n$6\r\nexists\r\n$2\r\nex\r\n*4\r\n$5\r\ngetex\r\n$2\r\npx\r\n$2\r\nex\r\n$1\r\n0\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "$1\r\n1\r\n:0\r\n-ERR invalid expire time in 'getex' command\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_getrange_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*4\r\n$8\r\nsetrange\r\n$2\r\nsr\r\n$1\r\n2\r\n$2\r\nab\r\n*4\r\n$8\r\ngetrange\r\n$2\r\nsr\r\n$2\r\n-2\r\n$2\r\n-1\r\n*4\r\n$8\r\nsetrange\r\n$2\r\nsr\r\n$2\r\n-1\r\n$1\r\na\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), ":4\r\n$2\r\nab\r\n-ERR offset is out of range\r\n".as_bytes());
+        decoder.feed("*4\r\n$8\r\ngetrange\r\n$2\r\nsr\r\n$3\r\n1-2\r\n$1\r\n3\r\n*4\r\n$8\r\ngetrange\r\n$2\r\nsr\r\n$1\r\n0\r\n$19\r\n9223372036854775808\r\n*4\r\n$8\r\ngetrange\r\n$2\r\nsr\r\n$20\r\n-9223372036854775808\r\n$1\r\n1\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "-ERR value is not an integer or out of range\r\n-ERR value is not an integer or out of range\r\n$2\r\n\0\0\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_persist_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*4\r\n$6\r\npsetex\r\n$2\r\npx\r\n$4\r\n1000\r\n$1\r\n1\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+OK\r\n".as_bytes());
+        decoder.feed("*2\r\n$7\r\npersist\r\n$2\r\npx\r\n*2\r\n$7\r\npersist\r\n$2\r\npx\r\n*2\r\n$4\r\npttl\r\n$2\r\npx\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(), ":1\r\n:0\r\n:-1\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_expireat_commands() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*3\r\n$3\r\nset\r\n$2\r\npx\r\n$1\r\n1\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+OK\r\n".as_bytes());
+        decoder.feed("*3\r\n$8\r\nexpireat\r\n$2\r\npx\r\n$10\r\n9999999999\r\n*3\r\n$9\r\npexpireat\r\n$2\r\npx\r\n$1\r\n1\r\n*2\r\n$6\r\nexists\r\n$2\r\npx\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(), ":1\r\n:1\r\n:0\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_touch_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*3\r\n$3\r\nset\r\n$1\r\nx\r\n$1\r\n1\r\n*3\r\n$3\r\nset\r\n$1\r\ny\r\n$1\r\n2\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+OK\r\n+OK\r\n".as_bytes());
+        decoder.feed("*4\r\n$5\r\ntouch\r\n$1\r\nx\r\n$1\r\ny\r\n$1\r\nq\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(), ":2\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_set_options() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*5\r\n$3\r\nset\r\n$2\r\nso\r\n$1\r\n1\r\n$2\r\nxx\r\n$3\r\nget\r\n*4\r\n$3\r\nset\r\n$2\r\nso\r\n$1\r\n1\r\n$2\r\nnx\r\n*6\r\n$3\r\nset\r\n$2\r\nso\r\n$1\r\n2\r\n$3\r\nget\r\n$2\r\npx\r\n$5\r\n50000\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "$-1\r\n+OK\r\n$1\r\n1\r\n".as_bytes());
+        decoder.feed("*5\r\n$3\r\nset\r\n$2\r\nso\r\n$1\r\n3\r\n$7\r\nkeepttl\r\n$2\r\nxx\r\n*2\r\n$3\r\nttl\r\n$2\r\nso\r\n*5\r\n$3\r\nset\r\n$2\r\nso\r\n$1\r\n4\r\n$2\r\nnx\r\n$2\r\nxx\r\n*5\r\n$3\r\nset\r\n$2\r\nso\r\n$1\r\n4\r\n$4\r\nexat\r\n$10\r\n9999999999\r\n*2\r\n$3\r\nttl\r\n$2\r\nso\r\n".as_bytes());
+        let result = String::from_utf8(resp_run(&mut decoder, common_data, None)).unwrap();
+        assert!(result.starts_with("+OK\r\n:50\r\n-ERR syntax error\r\n+OK\r\n:"), "{}", result);
+        assert!(!result.ends_with(":-1\r\n"));
+    }
+
+    #[test]
+    fn test_set_commands() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*3\r\n$3\r\nset\r\n$1\r\nx\r\n$1\r\n1\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+OK\r\n".as_bytes());
+        decoder.feed("*5\r\n$4\r\nsadd\r\n$1\r\ns\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\na\r\n*3\r\n$9\r\nsismember\r\n$1\r\ns\r\n$1\r\nb\r\n*2\r\n$5\r\nscard\r\n$1\r\ns\r\n*2\r\n$4\r\ntype\r\n$1\r\ns\r\n*2\r\n$3\r\nget\r\n$1\r\ns\r\n*3\r\n$4\r\nsadd\r\n$1\r\nx\r\n$1\r\na\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(),
+                   ":2\r\n:1\r\n:2\r\n+set\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n\
+-WRONGTYPE Operation against a key holding the wrong kind of value\r\n".as_bytes());
+        decoder.feed("*4\r\n$4\r\nsrem\r\n$1\r\ns\r\n$1\r\na\r\n$1\r\nc\r\n*2\r\n$8\r\nsmembers\r\n$1\r\ns\r\n*3\r\n$5\r\nsscan\r\n$1\r\ns\r\n$1\r\n0\r\n*3\r\n$4\r\nsrem\r\n$1\r\ns\r\n$1\r\nb\r\n*2\r\n$6\r\nexists\r\n$1\r\ns\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(),
+                   ":1\r\n*1\r\n$1\r\nb\r\n*2\r\n$1\r\n0\r\n*1\r\n$1\r\nb\r\n:1\r\n:0\r\n".as_bytes());
+        decoder.feed("*4\r\n$4\r\nsadd\r\n$2\r\ns1\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), ":2\r\n".as_bytes());
+        // string commands do not change sets
+        decoder.feed("*4\r\n$5\r\ngetex\r\n$2\r\ns1\r\n$4\r\nexat\r\n$1\r\n1\r\n*2\r\n$6\r\nexists\r\n$2\r\ns1\r\n*3\r\n$4\r\nmget\r\n$2\r\ns1\r\n$2\r\nno\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n:1\r\n*2\r\n$-1\r\n$-1\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_set_operation_commands() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*4\r\n$4\r\nsadd\r\n$2\r\ns1\r\n$1\r\na\r\n$1\r\nb\r\n*4\r\n$4\r\nsadd\r\n$2\r\ns2\r\n$1\r\nb\r\n$1\r\nc\r\n*3\r\n$6\r\nsinter\r\n$2\r\ns1\r\n$2\r\ns2\r\n*4\r\n$5\r\nsdiff\r\n$2\r\ns1\r\n$2\r\ns2\r\n$2\r\nno\r\n*4\r\n$11\r\nsunionstore\r\n$2\r\ns3\r\n$2\r\ns1\r\n$2\r\ns2\r\n*3\r\n$6\r\nsinter\r\n$2\r\ns1\r\n$2\r\nno\r\n*4\r\n$10\r\nsdiffstore\r\n$2\r\ns3\r\n$2\r\ns1\r\n$2\r\ns3\r\n*2\r\n$6\r\nexists\r\n$2\r\ns3\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   ":2\r\n:2\r\n*1\r\n$1\r\nb\r\n*1\r\n$1\r\na\r\n:3\r\n*0\r\n:0\r\n:0\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_spop_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*4\r\n$4\r\nsadd\r\n$2\r\ns1\r\n$1\r\na\r\n$1\r\nb\r\n*4\r\n$4\r\nsadd\r\n$2\r\ns2\r\n$1\r\nb\r\n$1\r\nc\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), ":2\r\n:2\r\n".as_bytes());
+        decoder.feed("*3\r\n$11\r\nsrandmember\r\n$2\r\ns1\r\n$2\r\n-3\r\n*2\r\n$4\r\nspop\r\n$2\r\ns2\r\n*3\r\n$4\r\nspop\r\n$2\r\ns2\r\n$1\r\n5\r\n*2\r\n$4\r\nspop\r\n$2\r\ns2\r\n".as_bytes());
+        let result = String::from_utf8(resp_run(&mut decoder, common_data.clone(), None)).unwrap();
+        assert!(result.starts_with("*3\r\n$1\r\n"));
+        // srandmember with repetition, then both members of s2 are popped one by one
+        assert!(result.contains("\r\n*1\r\n$1\r\n") && result.ends_with("\r\n$-1\r\n"), "{}", result);
+        decoder.feed("*3\r\n$11\r\nsrandmember\r\n$2\r\ns1\r\n$20\r\n-9223372036854775807\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(), "-ERR value is out of range\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_smismember_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*4\r\n$4\r\nsadd\r\n$2\r\ns1\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), ":2\r\n".as_bytes());
+        decoder.feed("*5\r\n$10\r\nsmismember\r\n$2\r\ns1\r\n$1\r\na\r\n$1\r\nc\r\n$1\r\nb\r\n*3\r\n$10\r\nsmismember\r\n$2\r\nno\r\n$1\r\na\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(), "*3\r\n:1\r\n:0\r\n:1\r\n*1\r\n:0\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_sorted_set_commands() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*8\r\n$4\r\nzadd\r\n$2\r\nzs\r\n$1\r\n1\r\n$1\r\na\r\n$1\r\n2\r\n$1\r\nb\r\n$3\r\n1.5\r\n$1\r\nc\r\n*8\r\n$4\r\nzadd\r\n$2\r\nzs\r\n$2\r\nxx\r\n$2\r\nch\r\n$1\r\n3\r\n$1\r\na\r\n$1\r\n0\r\n$1\r\nx\r\n*6\r\n$4\r\nzadd\r\n$2\r\nzs\r\n$2\r\nnx\r\n$2\r\nxx\r\n$1\r\n1\r\n$1\r\na\r\n*4\r\n$4\r\nzadd\r\n$2\r\nzs\r\n$1\r\nx\r\n$1\r\na\r\n*3\r\n$6\r\nzscore\r\n$2\r\nzs\r\n$1\r\nc\r\n*3\r\n$6\r\nzscore\r\n$2\r\nzs\r\n$1\r\nx\r\n*2\r\n$5\r\nzcard\r\n$2\r\nzs\r\n*2\r\n$4\r\ntype\r\n$2\r\nzs\r\n*3\r\n$4\r\nsadd\r\n$2\r\nzs\r\n$1\r\na\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(),
+                   ":3\r\n:1\r\n-ERR XX and NX options at the same time are not compatible\r\n-ERR value is not a valid float\r\n$3\r\n1.5\r\n$-1\r\n:3\r\n+zset\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n".as_bytes());
+        decoder.feed("*4\r\n$6\r\nzrange\r\n$2\r\nzs\r\n$1\r\n0\r\n$2\r\n-1\r\n*5\r\n$6\r\nzrange\r\n$2\r\nzs\r\n$2\r\n-1\r\n$2\r\n-1\r\n$10\r\nwithscores\r\n*5\r\n$13\r\nzrangebyscore\r\n$2\r\nzs\r\n$4\r\n(1.5\r\n$4\r\n+inf\r\n$10\r\nwithscores\r\n*7\r\n$13\r\nzrangebyscore\r\n$2\r\nzs\r\n$4\r\n-inf\r\n$3\r\ninf\r\n$5\r\nlimit\r\n$1\r\n1\r\n$1\r\n1\r\n*4\r\n$6\r\nzrange\r\n$2\r\nno\r\n$1\r\n0\r\n$2\r\n-1\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "*3\r\n$1\r\nc\r\n$1\r\nb\r\n$1\r\na\r\n*2\r\n$1\r\na\r\n$1\r\n3\r\n*4\r\n$1\r\nb\r\n$1\r\n2\r\n$1\r\na\r\n$1\r\n3\r\n*1\r\n$1\r\nb\r\n*0\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_zincrby_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*8\r\n$4\r\nzadd\r\n$2\r\nzs\r\n$1\r\n3\r\n$1\r\na\r\n$1\r\n2\r\n$1\r\nb\r\n$3\r\n1.5\r\n$1\r\nc\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), ":3\r\n".as_bytes());
+        decoder.feed("*4\r\n$7\r\nzincrby\r\n$2\r\nzs\r\n$4\r\n-2.5\r\n$1\r\na\r\n*3\r\n$5\r\nzrank\r\n$2\r\nzs\r\n$1\r\na\r\n*3\r\n$8\r\nzrevrank\r\n$2\r\nzs\r\n$1\r\na\r\n*3\r\n$5\r\nzrank\r\n$2\r\nzs\r\n$1\r\nx\r\n*4\r\n$7\r\nzincrby\r\n$2\r\nzs\r\n$1\r\n1\r\n$3\r\nnew\r\n*5\r\n$4\r\nzrem\r\n$2\r\nzs\r\n$1\r\na\r\n$1\r\nx\r\n$3\r\nnew\r\n*4\r\n$7\r\nzincrby\r\n$2\r\nzi\r\n$3\r\ninf\r\n$1\r\na\r\n*4\r\n$7\r\nzincrby\r\n$2\r\nzi\r\n$4\r\n-inf\r\n$1\r\na\r\n*3\r\n$4\r\nzrem\r\n$2\r\nzi\r\n$1\r\na\r\n*2\r\n$6\r\nexists\r\n$2\r\nzi\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "$3\r\n0.5\r\n:0\r\n:2\r\n$-1\r\n$1\r\n1\r\n:2\r\n$3\r\ninf\r\n-ERR resulting score is not a number (NaN)\r\n:1\r\n:0\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_zpop_commands() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*6\r\n$4\r\nzadd\r\n$2\r\nzs\r\n$1\r\n2\r\n$1\r\nb\r\n$3\r\n1.5\r\n$1\r\nc\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), ":2\r\n".as_bytes());
+        decoder.feed("*2\r\n$7\r\nzpopmax\r\n$2\r\nzs\r\n*3\r\n$7\r\nzpopmin\r\n$2\r\nzs\r\n$1\r\n5\r\n*2\r\n$7\r\nzpopmin\r\n$2\r\nzs\r\n*3\r\n$7\r\nzpopmin\r\n$2\r\nzs\r\n$2\r\n-1\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "*2\r\n$1\r\nb\r\n$1\r\n2\r\n*2\r\n$1\r\nc\r\n$3\r\n1.5\r\n*0\r\n-ERR value is out of range, must be positive\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_bit_commands() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*4\r\n$6\r\nsetbit\r\n$4\r\nbits\r\n$1\r\n7\r\n$1\r\n1\r\n*4\r\n$6\r\nsetbit\r\n$4\r\nbits\r\n$1\r\n7\r\n$1\r\n0\r\n*4\r\n$6\r\nsetbit\r\n$4\r\nbits\r\n$1\r\n9\r\n$1\r\n1\r\n*3\r\n$6\r\ngetbit\r\n$4\r\nbits\r\n$1\r\n9\r\n*3\r\n$6\r\ngetbit\r\n$4\r\nbits\r\n$3\r\n100\r\n*4\r\n$6\r\nsetbit\r\n$4\r\nbits\r\n$1\r\n1\r\n$1\r\n2\r\n*2\r\n$8\r\nbitcount\r\n$4\r\nbits\r\n*4\r\n$8\r\nbitcount\r\n$4\r\nbits\r\n$1\r\n0\r\n$1\r\n0\r\n*5\r\n$8\r\nbitcount\r\n$4\r\nbits\r\n$1\r\n8\r\n$2\r\n15\r\n$3\r\nbit\r\n*3\r\n$6\r\ngetbit\r\n$2\r\nzi\r\n$1\r\n0\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(),
+                   ":0\r\n:1\r\n:0\r\n:1\r\n:0\r\n-ERR bit is not an integer or out of range\r\n:1\r\n:0\r\n:1\r\n:0\r\n".as_bytes());
+        decoder.feed("*3\r\n$3\r\nset\r\n$2\r\nb1\r\n$3\r\nabc\r\n*5\r\n$5\r\nbitop\r\n$3\r\nand\r\n$2\r\nb3\r\n$2\r\nb1\r\n$4\r\nbits\r\n*2\r\n$3\r\nget\r\n$2\r\nb3\r\n*5\r\n$5\r\nbitop\r\n$3\r\nnot\r\n$2\r\nb3\r\n$2\r\nb1\r\n$4\r\nbits\r\n*4\r\n$5\r\nbitop\r\n$2\r\nor\r\n$2\r\nb3\r\n$5\r\nnokey\r\n*2\r\n$6\r\nexists\r\n$2\r\nb3\r\n*5\r\n$5\r\nbitop\r\n$3\r\nxor\r\n$2\r\nb3\r\n$2\r\nb1\r\n$2\r\nb1\r\n*2\r\n$3\r\nget\r\n$2\r\nb3\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "+OK\r\n:3\r\n$3\r\n\x00@\x00\r\n-ERR BITOP NOT must be called with a single source key.\r\n:0\r\n:0\r\n:3\r\n$3\r\n\x00\x00\x00\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_geo_commands() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*8\r\n$6\r\ngeoadd\r\n$6\r\nSicily\r\n$9\r\n13.361389\r\n$9\r\n38.115556\r\n$7\r\nPalermo\r\n$9\r\n15.087269\r\n$9\r\n37.502669\r\n$7\r\nCatania\r\n*5\r\n$6\r\ngeoadd\r\n$6\r\nSicily\r\n$1\r\n1\r\n$3\r\n100\r\n$1\r\nx\r\n*5\r\n$7\r\ngeodist\r\n$6\r\nSicily\r\n$7\r\nPalermo\r\n$7\r\nCatania\r\n$2\r\nkm\r\n*4\r\n$7\r\ngeodist\r\n$6\r\nSicily\r\n$7\r\nPalermo\r\n$1\r\nx\r\n*10\r\n$9\r\ngeosearch\r\n$6\r\nSicily\r\n$10\r\nfromlonlat\r\n$2\r\n15\r\n$2\r\n37\r\n$8\r\nbyradius\r\n$3\r\n200\r\n$2\r\nkm\r\n$3\r\nasc\r\n$8\r\nwithdist\r\n*8\r\n$9\r\ngeosearch\r\n$6\r\nSicily\r\n$10\r\nfrommember\r\n$7\r\nPalermo\r\n$5\r\nbybox\r\n$3\r\n100\r\n$3\r\n100\r\n$2\r\nkm\r\n*6\r\n$9\r\ngeosearch\r\n$6\r\nSicily\r\n$5\r\nbybox\r\n$1\r\n1\r\n$1\r\n1\r\n$1\r\nm\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   ":2\r\n-ERR invalid longitude,latitude pair 1.000000,100.000000\r\n$8\r\n166.2742\r\n$-1\r\n*2\r\n*2\r\n$7\r\nCatania\r\n$7\r\n56.4413\r\n*2\r\n$7\r\nPalermo\r\n$8\r\n190.4424\r\n*1\r\n$7\r\nPalermo\r\n-ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_subscribe_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*3\r\n$9\r\nsubscribe\r\n$2\r\nc1\r\n$2\r\nc2\r\n*2\r\n$3\r\nget\r\n$1\r\na\r\n*2\r\n$11\r\nunsubscribe\r\n$2\r\nc2\r\n*1\r\n$11\r\nunsubscribe\r\n*1\r\n$11\r\nunsubscribe\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), Some(1)).as_slice(),
+                   "*3\r\n$9\r\nsubscribe\r\n$2\r\nc1\r\n:1\r\n*3\r\n$9\r\nsubscribe\r\n$2\r\nc2\r\n:2\r\n-ERR only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT are allowed in this context\r\n*3\r\n$11\r\nunsubscribe\r\n$2\r\nc2\r\n:1\r\n*3\r\n$11\r\nunsubscribe\r\n$2\r\nc1\r\n:0\r\n*3\r\n$11\r\nunsubscribe\r\n$-1\r\n:0\r\n".as_bytes());
+        decoder.feed("*2\r\n$9\r\nsubscribe\r\n$2\r\nc1\r\n*3\r\n$7\r\npublish\r\n$2\r\nc1\r\n$2\r\nhi\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "-ERR pub/sub needs a client connection\r\n:0\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_psubscribe_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*3\r\n$10\r\npsubscribe\r\n$2\r\nc*\r\n$2\r\nd?\r\n*2\r\n$9\r\nsubscribe\r\n$2\r\nc1\r\n*2\r\n$12\r\npunsubscribe\r\n$2\r\nd?\r\n*1\r\n$12\r\npunsubscribe\r\n*1\r\n$12\r\npunsubscribe\r\n*1\r\n$11\r\nunsubscribe\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, Some(1)).as_slice(),
+                   "*3\r\n$10\r\npsubscribe\r\n$2\r\nc*\r\n:1\r\n*3\r\n$10\r\npsubscribe\r\n$2\r\nd?\r\n:2\r\n*3\r\n$9\r\nsubscribe\r\n$2\r\nc1\r\n:3\r\n*3\r\n$12\r\npunsubscribe\r\n$2\r\nd?\r\n:2\r\n*3\r\n$12\r\npunsubscribe\r\n$2\r\nc*\r\n:1\r\n*3\r\n$12\r\npunsubscribe\r\n$-1\r\n:1\r\n*3\r\n$11\r\nunsubscribe\r\n$2\r\nc1\r\n:0\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_latency_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*4\r\n$6\r\nconfig\r\n$3\r\nset\r\n$25\r\nlatency-monitor-threshold\r\n$1\r\nx\r\n*2\r\n$7\r\nlatency\r\n$6\r\nlatest\r\n*3\r\n$7\r\nlatency\r\n$7\r\nhistory\r\n$7\r\ncommand\r\n*3\r\n$7\r\nlatency\r\n$5\r\nreset\r\n$7\r\ncommand\r\n*2\r\n$7\r\nlatency\r\n$5\r\nreset\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "-invalid latency-monitor-threshold value\r\n*0\r\n*0\r\n:0\r\n:0\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_debug_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*3\r\n$3\r\nset\r\n$3\r\ndbg\r\n$2\r\n12\r\n*3\r\n$5\r\ndebug\r\n$5\r\nsleep\r\n$3\r\n0.1\r\n*3\r\n$5\r\ndebug\r\n$5\r\nsleep\r\n$2\r\n-1\r\n*3\r\n$5\r\ndebug\r\n$5\r\nsleep\r\n$5\r\n1e300\r\n*3\r\n$5\r\ndebug\r\n$17\r\nset-active-expire\r\n$1\r\n0\r\n*3\r\n$5\r\ndebug\r\n$6\r\nobject\r\n$3\r\ndbg\r\n*3\r\n$5\r\ndebug\r\n$6\r\nobject\r\n$5\r\nnokey\r\n".as_bytes());
+        let now = Instant::now();
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "+OK\r\n+OK\r\n-ERR value is not a valid float\r\n-ERR timeout is out of range\r\n+OK\r\n+Value encoding:int serializedlength:8\r\n-ERR no such key\r\n".as_bytes());
+        assert!(now.elapsed().as_millis() >= 100);
+    }
+
+    #[test]
+    fn test_setex_command() {
+        let common_data = build_test_data();
+        let mut decoder = Decoder::new();
+        decoder.feed("*3\r\n$3\r\nset\r\n$1\r\nx\r\n$1\r\n1\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+OK\r\n".as_bytes());
+        decoder.feed("*3\r\n$5\r\nsetnx\r\n$1\r\nx\r\n$1\r\n5\r\n*3\r\n$5\r\nsetnx\r\n$2\r\nnx\r\n$1\r\n5\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), ":0\r\n:1\r\n".as_bytes());
+        decoder.feed("*4\r\n$5\r\nsetex\r\n$2\r\nex\r\n$2\r\n10\r\n$1\r\n1\r\n*4\r\n$6\r\npsetex\r\n$2\r\npx\r\n$4\r\n1000\r\n$1\r\n1\r\n*2\r\n$3\r\nttl\r\n$2\r\nex\r\n*2\r\n$3\r\nttl\r\n$2\r\npx\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(), "+OK\r\n+OK\r\n:10\r\n:1\r\n".as_bytes());
+        decoder.feed("*4\r\n$5\r\nsetex\r\n$1\r\nk\r\n$1\r\n0\r\n$1\r\n1\r\n*4\r\n$5\r\nsetex\r\n$1\r\nk\r\n$2\r\n-5\r\n$1\r\n1\r\n*4\r\n$6\r\npsetex\r\n$1\r\nk\r\n$1\r\n0\r\n$1\r\n1\r\n*4\r\n$6\r\npsetex\r\n$1\r\nk\r\n$20\r\n-9223372036854775808\r\n$1\r\n1\r\n*2\r\n$6\r\nexists\r\n$1\r\nk\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data, None).as_slice(),
+                   "-ERR invalid expire time in 'setex' command\r\n-ERR invalid expire time in 'setex' command\r\n\
+-ERR invalid expire time in 'psetex' command\r\n-ERR invalid expire time in 'psetex' command\r\n:0\r\n".as_bytes());
+    }
+
+    #[test]
+    fn test_memory_command() {
         let common_data = Arc::new(build_common_data(false,
-                                                     1000, 1,
-                                                     create_hash_builder("sum".to_string(), 1).unwrap()));
-        let result = resp_parse(BUFFER, BUFFER.len(), common_data);
-        assert_eq!(result.as_slice(), "+PONG\r\n+OK\r\n*2\r\n$4\r\nsave\r\n$0\r\n\r\n".as_bytes());
+                                                     1000, 2,
+                                                     create_hash_builder("sum".to_string(), 2).unwrap(), false,
+                                                     ValueSettings{ compression_threshold: 0, integer_encoding: false,
+                                                         intern_threshold: 0 }));
+        let mut decoder = Decoder::new();
+        decoder.feed("*2\r\n$6\r\nmemory\r\n$6\r\ndoctor\r\n".as_bytes());
+        assert_eq!(resp_run(&mut decoder, common_data.clone(), None).as_slice(),
+                   "$54\r\nThe instance is empty, there is nothing to diagnose.\r\n\r\n".as_bytes());
+        decoder.feed("*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\nb\r\n*2\r\n$6\r\nmemory\r\n$5\r\nstats\r\n".as_bytes());
+        let stats = String::from_utf8(resp_run(&mut decoder, common_data.clone(), None)).unwrap();
+        assert!(stats.starts_with("+OK\r\n*"));
+        assert!(stats.contains("$10\r\nkeys.count\r\n:1\r\n"));
+        assert!(stats.contains("$4\r\ndb.0\r\n*4\r\n$4\r\nkeys\r\n:1\r\n$7\r\nexpires\r\n:0\r\n$6\r\nshards\r\n*2\r\n"));
+
+        let value = "x".repeat(950);
+        decoder.feed(format!("*4\r\n$6\r\nconfig\r\n$3\r\nset\r\n$16\r\nmaxmemory-policy\r\n$10\r\nnoeviction\r\n*3\r\n$3\r\nset\r\n$1\r\nc\r\n$950\r\n{}\r\n*2\r\n$6\r\nmemory\r\n$6\r\ndoctor\r\n", value).as_bytes());
+        let report = String::from_utf8(resp_run(&mut decoder, common_data, None)).unwrap();
+        assert!(report.starts_with("+OK\r\n+OK\r\n$"));
+        assert!(report.contains("is over 90% of maxmemory (1000 bytes), keys are evicted by the noeviction policy"));
+        assert!(report.contains("Key maps are unbalanced: shard"));
     }
 }
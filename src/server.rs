@@ -1,14 +1,22 @@
 use std::io::{Error, Read, Write};
-use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
 use std::ops::DerefMut;
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
+use std::time::{Duration, Instant};
+use log::{debug, error, info, warn};
 use crate::common_data::CommonData;
-use crate::resp_parser::resp_parse;
+use crate::connections::Connection;
+use crate::resp_codec::Decoder;
+use crate::resp_parser::resp_run;
 
 pub fn work_handler<'a>(idx: usize, stream: Arc<Mutex<TcpStream>>, common_data: Arc<CommonData>) {
     let mut buffer = [0; 1000000];
+    let mut decoder = Decoder::new();
+    // replies are written through the registered handle, PUBLISH writes messages for subscribers through it too,
+    // so a reply and a message are never interleaved
+    let writer = common_data.connection(idx);
     loop {
         let mut guard = stream.lock().unwrap();
         let s = guard.deref_mut();
@@ -18,26 +26,113 @@ pub fn work_handler<'a>(idx: usize, stream: Arc<Mutex<TcpStream>>, common_data:
                 if amt == 0 {
                     break;
                 }
-                let _ = s.write_all(resp_parse(&buffer, amt, common_data.clone()).as_slice());
+                common_data.network_stats.add_input(amt);
+                decoder.feed(&buffer[..amt]);
+                let output = resp_run(&mut decoder, common_data.clone(), Some(idx));
+                common_data.network_stats.add_output(output.len());
+                let _ = match &writer {
+                    Some(w) => w.write(output.as_slice()),
+                    None => s.write_all(output.as_slice())
+                };
             },
             Err(e) => {
                 if common_data.exit_flag.load(Ordering::Relaxed) {
-                    if common_data.verbose {
-                        println!("Stopping thread...");
+                    if common_data.verbose() {
+                        debug!("Stopping thread...");
                     }
                 } else {
-                    println!("Stream read error {}", e);
+                    warn!("Stream read error {}", e);
                 }
                 break;
             }
         }
     }
+    common_data.pubsub.remove_client(idx);
     common_data.threads.write().unwrap().remove(&idx);
 }
 
-pub fn server_start(port: u16, common_data: Arc<CommonData>) -> Result<(), Error> {
-    let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port)))?;
-    println!("Server listening on port {}", port);
+// periodically shrinks maps that have a lot of unused capacity
+pub fn defrag_job(interval: u64, common_data: Arc<CommonData>) {
+    thread::spawn(move || {
+        let d = Duration::from_secs(interval);
+        while !common_data.exit_flag.load(Ordering::Relaxed) {
+            thread::sleep(d);
+            let count = common_data.defrag(false);
+            if count > 0 && common_data.verbose() {
+                info!("{} maps defragmented", count);
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn sighup_handler(_signal: libc::c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+// reloads the configuration file on SIGHUP, has to be called after ctrlc::set_handler because ctrlc
+// handles SIGHUP as a termination signal
+#[cfg(unix)]
+pub fn reload_on_sighup(common_data: Arc<CommonData>) {
+    unsafe {
+        libc::signal(libc::SIGHUP, sighup_handler as *const () as libc::sighandler_t);
+    }
+    thread::spawn(move || {
+        let d = Duration::from_millis(100);
+        while !common_data.exit_flag.load(Ordering::Relaxed) {
+            thread::sleep(d);
+            if RELOAD_REQUESTED.swap(false, Ordering::Relaxed) {
+                match common_data.reload_configuration() {
+                    Ok(()) => info!("Configuration reloaded"),
+                    Err(e) => error!("Configuration reload error {}", e)
+                }
+            }
+        }
+    });
+}
+
+// forks, detaches from the terminal and redirects the standard streams to /dev/null,
+// has to be called before any threads are started
+#[cfg(unix)]
+pub fn daemonize() -> Result<(), Error> {
+    unsafe {
+        match libc::fork() {
+            -1 => return Err(Error::last_os_error()),
+            0 => {}
+            _ => libc::_exit(0)
+        }
+        if libc::setsid() == -1 {
+            return Err(Error::last_os_error());
+        }
+        let fd = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+        if fd == -1 {
+            return Err(Error::last_os_error());
+        }
+        for target in 0..3 {
+            libc::dup2(fd, target);
+        }
+        if fd > 2 {
+            libc::close(fd);
+        }
+    }
+    Ok(())
+}
+
+// samples the number of processed commands for instantaneous_ops_per_sec
+pub fn stats_job(common_data: Arc<CommonData>) {
+    thread::spawn(move || {
+        let d = Duration::from_millis(100);
+        while !common_data.exit_flag.load(Ordering::Relaxed) {
+            common_data.network_stats.sample(Instant::now());
+            thread::sleep(d);
+        }
+    });
+}
+
+pub fn server_start(listener: TcpListener, common_data: Arc<CommonData>) -> Result<(), Error> {
     let mut idx = 0;
     for stream in listener.incoming() {
         match stream {
@@ -45,22 +140,98 @@ pub fn server_start(port: u16, common_data: Arc<CommonData>) -> Result<(), Error
                 if common_data.exit_flag.load(Ordering::Relaxed) {
                     break;
                 }
+                common_data.network_stats.connection_received();
                 let c = common_data.clone();
-                let ss = Arc::new(Mutex::new(s));
-                let cloned = ss.clone();
+                // the worker holds its stream lock while waiting for data, so a separate handle is kept for shutdown
+                let ss = match s.try_clone() {
+                    Ok(ss) => Arc::new(Connection::new(ss)),
+                    Err(e) => {
+                        common_data.network_stats.connection_rejected();
+                        warn!("Connection rejected: {}", e);
+                        continue;
+                    }
+                };
+                let worker_stream = Arc::new(Mutex::new(s));
+                common_data.threads.write().unwrap().insert(idx, ss);
                 thread::spawn(move ||{
-                    work_handler(idx, cloned, c);
+                    work_handler(idx, worker_stream, c);
                 });
-                common_data.threads.write().unwrap().insert(idx, ss);
                 idx += 1;
             }
             Err(e) => {
                 if common_data.exit_flag.load(Ordering::Relaxed) {
                     break;
                 }
-                println!("Connection error {}", e);
+                error!("Connection error {}", e);
             }
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+// sets the exit flag and wakes up the listener by connecting to its address,
+// a listener bound to all interfaces is connected through the loopback interface
+pub fn server_stop(address: SocketAddr, common_data: &CommonData) {
+    common_data.exit_flag.store(true, Ordering::Relaxed);
+    let mut address = address;
+    if address.ip().is_unspecified() {
+        address.set_ip(if address.is_ipv4() { IpAddr::V4(Ipv4Addr::LOCALHOST) } else { IpAddr::V6(Ipv6Addr::LOCALHOST) });
+    }
+    let _ = TcpStream::connect_timeout(&address, Duration::from_secs(1));
+}
+
+// sends a state (READY=1, STOPPING=1) to systemd when the server is started by a Type=notify service,
+// does nothing when NOTIFY_SOCKET is not set
+#[cfg(unix)]
+pub fn sd_notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+    let path = match std::env::var("NOTIFY_SOCKET") {
+        Ok(path) if !path.is_empty() => path,
+        _ => return
+    };
+    let result = UnixDatagram::unbound().and_then(|socket| {
+        match path.strip_prefix('@') {
+            // abstract socket
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.send_to_addr(state.as_bytes(), &address)
+            }
+            _ => socket.send_to(state.as_bytes(), &path)
+        }
+    });
+    if let Err(e) = result {
+        warn!("sd_notify error {}", e);
+    }
+}
+
+// drains client connections: reading is stopped, so commands that are being executed are finished and their
+// replies are sent, connections that are still open after the grace period are closed, then waits for all
+// client threads to be finished
+pub fn server_shutdown(common_data: &CommonData, grace_period: Duration) {
+    info!("Waiting for all threads to be finished...");
+    shutdown_connections(common_data, Shutdown::Read);
+    let d = Duration::from_millis(100);
+    let deadline = Instant::now() + grace_period;
+    while !common_data.threads.read().unwrap().is_empty() && Instant::now() < deadline {
+        thread::sleep(d);
+    }
+    let remaining = common_data.threads.read().unwrap().len();
+    if remaining > 0 {
+        warn!("Closing {} connections after the grace period", remaining);
+        shutdown_connections(common_data, Shutdown::Both);
+    }
+    while !common_data.threads.read().unwrap().is_empty() {
+        thread::sleep(d);
+    }
+}
+
+fn shutdown_connections(common_data: &CommonData, how: Shutdown) {
+    let v: Vec<usize> = common_data.threads.read().unwrap().keys().copied().collect();
+    for idx in v  {
+        if let Some(c) = common_data.connection(idx) {
+            c.shutdown(how);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use log::info;
+use crate::allocator::allocated_memory;
+use crate::common_data::{build_common_data_with_backend, CommonData};
+use crate::common_maps::{common_maps_factory, EvictionPolicy};
+use crate::hash_builders::{create_consistent_hash_builder, create_hash_builder};
+use crate::otlp::SpanExporter;
+use crate::server::{defrag_job, server_shutdown, server_start, server_stop, stats_job};
+use crate::storage_backend::StorageBackendFactory;
+use crate::values::ValueSettings;
+
+// server configuration for programmatic use, defaults are the same as for the command line
+pub struct CacheServerBuilder {
+    bind_address: IpAddr,
+    port: u16,
+    max_memory: usize,
+    // 0 - next power of two >= 4 * number of CPUs
+    key_maps: usize,
+    hash_builder: String,
+    consistent_hashing: bool,
+    eviction_policy: EvictionPolicy,
+    verbose: bool,
+    use_allocator_stats: bool,
+    value_settings: ValueSettings,
+    // 0 - defragmentation job is disabled
+    defrag_interval: u64,
+    storage_backend: StorageBackendFactory,
+    // OTLP/HTTP collector for command spans, None - spans are not exported
+    otlp_endpoint: Option<String>,
+    config_file: Option<String>,
+    // time for client connections to finish their commands on shutdown
+    shutdown_timeout: Duration,
+}
+
+// running server, stopped by stop() or when the handle is dropped without detach()
+pub struct CacheServer {
+    address: SocketAddr,
+    shutdown_timeout: Duration,
+    common_data: Arc<CommonData>,
+    handle: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl CacheServerBuilder {
+    pub fn new() -> CacheServerBuilder {
+        CacheServerBuilder {
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 6379,
+            max_memory: 1024 * 1024 * 1024,
+            key_maps: 0,
+            hash_builder: "fnv1a".to_string(),
+            consistent_hashing: false,
+            eviction_policy: EvictionPolicy::AllKeysLru,
+            verbose: false,
+            use_allocator_stats: false,
+            value_settings: ValueSettings {
+                compression_threshold: 0,
+                integer_encoding: true,
+                intern_threshold: 0,
+            },
+            defrag_interval: 10,
+            storage_backend: common_maps_factory(),
+            otlp_endpoint: None,
+            config_file: None,
+            shutdown_timeout: Duration::from_secs(10),
+        }
+    }
+
+    // default - all interfaces
+    pub fn bind(mut self, address: IpAddr) -> CacheServerBuilder {
+        self.bind_address = address;
+        self
+    }
+
+    // 0 - any free port, use CacheServer::port() to get it
+    pub fn port(mut self, port: u16) -> CacheServerBuilder {
+        self.port = port;
+        self
+    }
+
+    pub fn max_memory(mut self, max_memory: usize) -> CacheServerBuilder {
+        self.max_memory = max_memory;
+        self
+    }
+
+    pub fn key_maps(mut self, key_maps: usize) -> CacheServerBuilder {
+        self.key_maps = key_maps;
+        self
+    }
+
+    pub fn hash_builder(mut self, name: &str) -> CacheServerBuilder {
+        self.hash_builder = name.to_string();
+        self
+    }
+
+    pub fn consistent_hashing(mut self, consistent_hashing: bool) -> CacheServerBuilder {
+        self.consistent_hashing = consistent_hashing;
+        self
+    }
+
+    pub fn eviction(mut self, eviction_policy: EvictionPolicy) -> CacheServerBuilder {
+        self.eviction_policy = eviction_policy;
+        self
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> CacheServerBuilder {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn allocator_stats(mut self, use_allocator_stats: bool) -> CacheServerBuilder {
+        self.use_allocator_stats = use_allocator_stats;
+        self
+    }
+
+    pub fn compression_threshold(mut self, compression_threshold: usize) -> CacheServerBuilder {
+        self.value_settings.compression_threshold = compression_threshold;
+        self
+    }
+
+    pub fn integer_encoding(mut self, integer_encoding: bool) -> CacheServerBuilder {
+        self.value_settings.integer_encoding = integer_encoding;
+        self
+    }
+
+    pub fn intern_threshold(mut self, intern_threshold: usize) -> CacheServerBuilder {
+        self.value_settings.intern_threshold = intern_threshold;
+        self
+    }
+
+    pub fn defrag_interval(mut self, defrag_interval: u64) -> CacheServerBuilder {
+        self.defrag_interval = defrag_interval;
+        self
+    }
+
+    pub fn storage_backend(mut self, storage_backend: StorageBackendFactory) -> CacheServerBuilder {
+        self.storage_backend = storage_backend;
+        self
+    }
+
+    // http://host:port[/path], the default port is 4318 and the default path is /v1/traces
+    pub fn otlp_endpoint(mut self, endpoint: &str) -> CacheServerBuilder {
+        self.otlp_endpoint = Some(endpoint.to_string());
+        self
+    }
+
+    // changeable settings from this file override the builder settings, CONFIG RELOAD applies the file again
+    pub fn config_file(mut self, file_name: &str) -> CacheServerBuilder {
+        self.config_file = Some(file_name.to_string());
+        self
+    }
+
+    pub fn shutdown_timeout(mut self, shutdown_timeout: Duration) -> CacheServerBuilder {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    // builds the server data without starting the server
+    pub fn build(&self) -> Result<Arc<CommonData>, Error> {
+        if self.max_memory == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid maximum memory value"));
+        }
+        if self.use_allocator_stats && allocated_memory().is_none() {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "allocator statistics are not available, build with allocator_stats feature"));
+        }
+        let key_maps = if self.key_maps == 0 {
+            let cpus = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+            let key_maps = (4 * cpus).next_power_of_two();
+            info!("Number of key maps = {} ({} CPUs)", key_maps, cpus);
+            key_maps
+        } else {
+            self.key_maps
+        };
+        let hash_builder = if self.consistent_hashing {
+            create_consistent_hash_builder(self.hash_builder.clone(), key_maps)?
+        } else {
+            create_hash_builder(self.hash_builder.clone(), key_maps)?
+        };
+        if self.verbose {
+            info!("Port = {}, Maximum memory = {}, Vector size = {}, Hash builder = {}, Allocator statistics = {}, Compression threshold = {}, Integer encoding = {}, Intern threshold = {}, Defragmentation interval = {}",
+                  self.port, self.max_memory, key_maps, hash_builder.get_name(), self.use_allocator_stats,
+                  self.value_settings.compression_threshold, self.value_settings.integer_encoding,
+                  self.value_settings.intern_threshold, self.defrag_interval);
+        }
+        let common_data = build_common_data_with_backend(self.verbose, self.max_memory, key_maps, hash_builder,
+                                                         self.use_allocator_stats, self.value_settings.clone(),
+                                                         self.storage_backend.clone());
+        common_data.set_eviction_policy(self.eviction_policy);
+        if let Some(file_name) = &self.config_file {
+            common_data.set_config_file(file_name);
+            common_data.reload_configuration().map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        }
+        if let Some(endpoint) = &self.otlp_endpoint {
+            common_data.set_span_exporter(SpanExporter::start(endpoint)?);
+            info!("Exporting command spans to {}", endpoint);
+        }
+        Ok(Arc::new(common_data))
+    }
+
+    // binds the port and starts the server in a background thread
+    pub fn start(self) -> Result<CacheServer, Error> {
+        let common_data = self.build()?;
+        let listener = TcpListener::bind(SocketAddr::new(self.bind_address, self.port))?;
+        let address = listener.local_addr()?;
+        info!("Server listening on port {}", address.port());
+        if self.defrag_interval > 0 {
+            defrag_job(self.defrag_interval, common_data.clone());
+        }
+        stats_job(common_data.clone());
+        let c = common_data.clone();
+        let handle = thread::spawn(move || server_start(listener, c));
+        Ok(CacheServer { address, shutdown_timeout: self.shutdown_timeout, common_data, handle: Some(handle) })
+    }
+}
+
+impl Default for CacheServerBuilder {
+    fn default() -> Self {
+        CacheServerBuilder::new()
+    }
+}
+
+impl CacheServer {
+    pub fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn common_data(&self) -> Arc<CommonData> {
+        self.common_data.clone()
+    }
+
+    // waits until the server is stopped by server_stop, then closes all client connections
+    pub fn wait(mut self) -> Result<(), Error> {
+        self.join()
+    }
+
+    pub fn stop(mut self) -> Result<(), Error> {
+        server_stop(self.address, &self.common_data);
+        self.join()
+    }
+
+    // the server keeps running after the handle is dropped
+    pub fn detach(mut self) {
+        self.handle = None;
+    }
+
+    fn join(&mut self) -> Result<(), Error> {
+        if let Some(handle) = self.handle.take() {
+            let result = handle.join().unwrap_or_else(|_e| Err(Error::other("server thread panicked")));
+            server_shutdown(&self.common_data, self.shutdown_timeout);
+            return result;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CacheServer {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            server_stop(self.address, &self.common_data);
+            let _ = self.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{IpAddr, Ipv4Addr, TcpStream};
+    use crate::common_maps::EvictionPolicy;
+    use crate::server_builder::CacheServerBuilder;
+
+    #[test]
+    fn test_start_stop() {
+        let server = CacheServerBuilder::new()
+            .port(0)
+            .max_memory(1024 * 1024)
+            .key_maps(4)
+            .hash_builder("xxhash")
+            .eviction(EvictionPolicy::VolatileTtl)
+            .defrag_interval(0)
+            .start()
+            .unwrap();
+        assert_eq!(server.common_data().eviction_policy(), EvictionPolicy::VolatileTtl);
+        let mut connection = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+        connection.write_all("*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\nb\r\n".as_bytes()).unwrap();
+        let mut buffer = [0; 100];
+        let amt = connection.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..amt], "+OK\r\n".as_bytes());
+        assert_eq!(server.common_data().size(), 1);
+        server.stop().unwrap();
+        assert_eq!(connection.read(&mut buffer).unwrap(), 0);
+
+        let server = CacheServerBuilder::new().bind(IpAddr::V4(Ipv4Addr::LOCALHOST)).port(0).key_maps(4).start().unwrap();
+        assert!(server.address().ip().is_loopback());
+        server.stop().unwrap();
+
+        assert!(CacheServerBuilder::new().hash_builder("unknown").key_maps(4).build().is_err());
+    }
+
+    #[test]
+    fn test_pubsub() {
+        let server = CacheServerBuilder::new().port(0).key_maps(4).defrag_interval(0).start().unwrap();
+        let mut subscriber = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+        subscriber.write_all("*2\r\n$9\r\nsubscribe\r\n$2\r\nch\r\n".as_bytes()).unwrap();
+        let mut buffer = [0; 100];
+        let amt = subscriber.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..amt], "*3\r\n$9\r\nsubscribe\r\n$2\r\nch\r\n:1\r\n".as_bytes());
+        let mut publisher = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+        publisher.write_all("*3\r\n$7\r\npublish\r\n$2\r\nch\r\n$2\r\nhi\r\n".as_bytes()).unwrap();
+        let amt = publisher.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..amt], ":1\r\n".as_bytes());
+        let amt = subscriber.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..amt], "*3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$2\r\nhi\r\n".as_bytes());
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_client() {
+        let server = CacheServerBuilder::new().port(0).key_maps(4).defrag_interval(0).start().unwrap();
+        let mut first = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+        let mut second = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+        let mut buffer = [0; 1000];
+        first.write_all("*3\r\n$6\r\nclient\r\n$7\r\nsetname\r\n$5\r\nfirst\r\n*2\r\n$6\r\nclient\r\n$7\r\ngetname\r\n".as_bytes()).unwrap();
+        let amt = first.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..amt], "+OK\r\n$5\r\nfirst\r\n".as_bytes());
+        second.write_all("*2\r\n$6\r\nclient\r\n$4\r\nlist\r\n".as_bytes()).unwrap();
+        let amt = second.read(&mut buffer).unwrap();
+        let list = String::from_utf8_lossy(&buffer[..amt]).to_string();
+        let first_address = first.local_addr().unwrap().to_string();
+        assert!(list.contains(&format!("id=0 addr={} name=first ", first_address)), "{}", list);
+        assert!(list.contains("cmd=client\n"), "{}", list);
+        assert_eq!(list.matches("id=").count(), 2);
+        // the old form closes the connection with the address
+        let kill = format!("*3\r\n$6\r\nclient\r\n$4\r\nkill\r\n${}\r\n{}\r\n", first_address.len(), first_address);
+        second.write_all(kill.as_bytes()).unwrap();
+        let amt = second.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..amt], "+OK\r\n".as_bytes());
+        assert_eq!(first.read(&mut buffer).unwrap(), 0);
+        second.write_all("*4\r\n$6\r\nclient\r\n$4\r\nkill\r\n$2\r\nid\r\n$1\r\n1\r\n".as_bytes()).unwrap();
+        let amt = second.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..amt], ":0\r\n".as_bytes());
+        second.write_all("*2\r\n$6\r\nclient\r\n$2\r\nid\r\n".as_bytes()).unwrap();
+        let amt = second.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..amt], ":1\r\n".as_bytes());
+        server.stop().unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const POOL_SIZE: usize = 64;
+
+// pool of interned values, identical values are stored once and shared between keys
+pub struct SharedValues {
+    sets: Vec<Mutex<HashSet<Arc<Vec<u8>>>>>,
+    memory: AtomicUsize,
+}
+
+pub struct SharedBytes {
+    value: Arc<Vec<u8>>,
+    idx: usize,
+    pool: Arc<SharedValues>,
+}
+
+impl SharedValues {
+    pub fn new() -> Arc<SharedValues> {
+        Arc::new(SharedValues {
+            sets: (0..POOL_SIZE).map(|_i| Mutex::new(HashSet::new())).collect(),
+            memory: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn share(self: &Arc<Self>, value: &Vec<u8>) -> SharedBytes {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let idx = (hasher.finish() as usize) % POOL_SIZE;
+        let mut set = self.sets[idx].lock().unwrap();
+        let v = match set.get(value) {
+            Some(v) => v.clone(),
+            None => {
+                let v = Arc::new(value.clone());
+                set.insert(v.clone());
+                self.memory.fetch_add(value.len(), Ordering::Relaxed);
+                v
+            }
+        };
+        SharedBytes { value: v, idx, pool: self.clone() }
+    }
+
+    // memory used by interned values
+    pub fn memory(&self) -> usize {
+        self.memory.load(Ordering::Relaxed)
+    }
+
+    // number of distinct interned values
+    pub fn count(&self) -> usize {
+        self.sets.iter().map(|s| s.lock().unwrap().len()).sum()
+    }
+
+    pub fn shrink(&self) {
+        self.sets.iter().for_each(|s| s.lock().unwrap().shrink_to_fit());
+    }
+}
+
+impl SharedBytes {
+    pub fn get(&self) -> &Vec<u8> {
+        &self.value
+    }
+}
+
+impl Drop for SharedBytes {
+    fn drop(&mut self) {
+        let mut set = self.pool.sets[self.idx].lock().unwrap();
+        //the pool and this value are the last owners
+        if Arc::strong_count(&self.value) == 2 {
+            set.remove(self.value.as_ref());
+            self.pool.memory.fetch_sub(self.value.len(), Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::shared_values::SharedValues;
+
+    #[test]
+    fn test_share_release() {
+        let pool = SharedValues::new();
+        let value = "shared value".to_string().into_bytes();
+        let v1 = pool.share(&value);
+        let v2 = pool.share(&value.clone());
+        let v3 = pool.share(&"other value".to_string().into_bytes());
+        assert!(Arc::ptr_eq(&v1.value, &v2.value));
+        assert_eq!(v1.get(), &value);
+        assert_eq!(pool.count(), 2);
+        assert_eq!(pool.memory(), 23);
+        drop(v1);
+        assert_eq!(pool.count(), 2);
+        drop(v2);
+        assert_eq!(pool.count(), 1);
+        assert_eq!(pool.memory(), 11);
+        drop(v3);
+        assert_eq!(pool.count(), 0);
+        assert_eq!(pool.memory(), 0);
+    }
+}
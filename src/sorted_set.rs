@@ -0,0 +1,243 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+
+// two copies of the member (score map and order tree), hash table entry and tree node
+const MEMBER_OVERHEAD: usize = 48;
+
+// score as a tree key, scores are never NaN, so the total order is the numeric one
+#[derive(Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// ZRANGEBYSCORE min/max: 1.5, (1.5 (exclusive), -inf, +inf
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+impl ScoreBound {
+    fn below(&self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(b) => score < *b,
+            ScoreBound::Exclusive(b) => score <= *b
+        }
+    }
+
+    fn above(&self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(b) => score > *b,
+            ScoreBound::Exclusive(b) => score >= *b
+        }
+    }
+}
+
+// member score, unlike INCRBYFLOAT values scores can be infinite, NaN is not accepted
+pub fn parse_score(value: &[u8]) -> Option<f64> {
+    let f = std::str::from_utf8(value).ok()?.parse::<f64>().ok()?;
+    if f.is_nan() {
+        None
+    } else {
+        // -0 and 0 are the same score
+        Some(f + 0.0)
+    }
+}
+
+pub fn parse_score_bound(value: &[u8]) -> Option<ScoreBound> {
+    match value.strip_prefix(b"(") {
+        Some(v) => parse_score(v).map(ScoreBound::Exclusive),
+        None => parse_score(value).map(ScoreBound::Inclusive)
+    }
+}
+
+// members ordered by score, members with the same score are ordered lexicographically
+#[derive(Default)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    order: BTreeSet<(Score, Vec<u8>)>,
+    size: usize,
+}
+
+impl SortedSet {
+    pub fn new() -> SortedSet {
+        SortedSet::default()
+    }
+
+    // adds the member or changes its score, returns the previous score
+    pub fn insert(&mut self, member: &[u8], score: f64) -> Option<f64> {
+        let old = self.scores.insert(member.to_vec(), score);
+        match old {
+            Some(old) => {
+                if old != score {
+                    self.order.remove(&(Score(old), member.to_vec()));
+                    self.order.insert((Score(score), member.to_vec()));
+                }
+            }
+            None => {
+                self.order.insert((Score(score), member.to_vec()));
+                self.size += 2 * member.len() + MEMBER_OVERHEAD;
+            }
+        }
+        old
+    }
+
+    // returns true when the member was removed
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => {
+                self.order.remove(&(Score(score), member.to_vec()));
+                self.size -= 2 * member.len() + MEMBER_OVERHEAD;
+                true
+            }
+            None => false
+        }
+    }
+
+    // removes up to count members with the lowest scores, or with the highest scores when max is set
+    pub fn pop(&mut self, count: usize, max: bool) -> Vec<(Vec<u8>, f64)> {
+        let mut popped = Vec::new();
+        while popped.len() < count {
+            let entry = if max { self.order.pop_last() } else { self.order.pop_first() };
+            match entry {
+                Some((score, member)) => {
+                    self.scores.remove(&member);
+                    self.size -= 2 * member.len() + MEMBER_OVERHEAD;
+                    popped.push((member, score.0));
+                }
+                None => break
+            }
+        }
+        popped
+    }
+
+    // position of the member in score order, the tree has no rank index, so members before it are counted
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.order.range(..(Score(score), member.to_vec())).count())
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // members with their scores in score order
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, f64)> {
+        self.order.iter().map(|(s, m)| (m, s.0))
+    }
+
+    // ZRANGE, inclusive range of positions, negative positions are counted from the end
+    pub fn range(&self, start: isize, stop: isize) -> Vec<(&Vec<u8>, f64)> {
+        let len = self.len() as isize;
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+        if start > stop {
+            return Vec::new();
+        }
+        self.order.iter()
+            .skip(start as usize)
+            .take((stop - start + 1) as usize)
+            .map(|(s, m)| (m, s.0))
+            .collect()
+    }
+
+    // ZRANGEBYSCORE, offset and count select a part of the members in the score range
+    pub fn range_by_score(&self, min: ScoreBound, max: ScoreBound, offset: usize, count: Option<usize>)
+        -> Vec<(&Vec<u8>, f64)> {
+        let start = match min {
+            ScoreBound::Inclusive(s) | ScoreBound::Exclusive(s) => (Score(s), Vec::new())
+        };
+        self.order.range(start..)
+            .skip_while(|(s, _m)| min.below(s.0))
+            .take_while(|(s, _m)| !max.above(s.0))
+            .skip(offset)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(s, m)| (m, s.0))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sorted_set::{parse_score, parse_score_bound, ScoreBound, SortedSet, MEMBER_OVERHEAD};
+
+    #[test]
+    fn test_sorted_set() {
+        assert_eq!(parse_score(b"1.5"), Some(1.5));
+        assert_eq!(parse_score(b"-inf"), Some(f64::NEG_INFINITY));
+        assert_eq!(parse_score(b"nan"), None);
+        assert_eq!(parse_score(b"x"), None);
+        assert_eq!(parse_score_bound(b"(2"), Some(ScoreBound::Exclusive(2.0)));
+        assert_eq!(parse_score_bound(b"+inf"), Some(ScoreBound::Inclusive(f64::INFINITY)));
+        assert_eq!(parse_score_bound(b"(x"), None);
+
+        let mut set = SortedSet::new();
+        assert_eq!(set.insert(b"c", 3.0), None);
+        assert_eq!(set.insert(b"b", 2.0), None);
+        assert_eq!(set.insert(b"a", 2.0), None);
+        assert_eq!(set.insert(b"d", 0.0), None);
+        assert_eq!(set.insert(b"d", 4.0), Some(0.0));
+        assert_eq!(set.len(), 4);
+        assert_eq!(set.size(), 4 * (2 + MEMBER_OVERHEAD));
+        assert_eq!(set.score(b"d"), Some(4.0));
+        assert_eq!(set.score(b"x"), None);
+        assert_eq!(set.rank(b"a"), Some(0));
+        assert_eq!(set.rank(b"b"), Some(1));
+        assert_eq!(set.rank(b"d"), Some(3));
+        assert_eq!(set.rank(b"x"), None);
+
+        let members = |r: Vec<(&Vec<u8>, f64)>| r.into_iter().map(|(m, _s)| m.clone()).collect::<Vec<_>>();
+        assert_eq!(members(set.range(0, -1)), vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()]);
+        assert_eq!(set.range(-2, 100), vec![(&b"c".to_vec(), 3.0), (&b"d".to_vec(), 4.0)]);
+        assert!(set.range(3, 1).is_empty());
+        assert!(set.range(10, 20).is_empty());
+
+        assert_eq!(members(set.range_by_score(ScoreBound::Inclusive(2.0), ScoreBound::Exclusive(4.0), 0, None)),
+                   vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(members(set.range_by_score(ScoreBound::Exclusive(2.0), ScoreBound::Inclusive(f64::INFINITY), 0, None)),
+                   vec![b"c".to_vec(), b"d".to_vec()]);
+        assert_eq!(members(set.range_by_score(ScoreBound::Inclusive(f64::NEG_INFINITY), ScoreBound::Inclusive(3.0), 1, Some(1))),
+                   vec![b"b".to_vec()]);
+        assert!(set.range_by_score(ScoreBound::Inclusive(5.0), ScoreBound::Inclusive(1.0), 0, None).is_empty());
+
+        assert!(set.remove(b"b"));
+        assert!(!set.remove(b"b"));
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.size(), 3 * (2 + MEMBER_OVERHEAD));
+        assert_eq!(set.rank(b"c"), Some(1));
+        assert_eq!(set.pop(1, true), vec![(b"d".to_vec(), 4.0)]);
+        assert_eq!(set.pop(5, false), vec![(b"a".to_vec(), 2.0), (b"c".to_vec(), 3.0)]);
+        assert!(set.is_empty());
+        assert_eq!(set.size(), 0);
+    }
+}
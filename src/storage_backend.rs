@@ -0,0 +1,217 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::time::SystemTime;
+use crate::common_maps::EvictionPolicy;
+use crate::values::ValueHolder;
+
+#[derive(PartialEq, Debug)]
+pub enum GetResult {
+    NotFound,
+    Found,
+    Expired,
+}
+
+// keys removed by one evict call
+pub struct EvictedKeys {
+    pub keys: Vec<Vec<u8>>,
+    // the keys were removed because they are expired
+    pub expired: bool,
+}
+
+pub struct ShardStats {
+    pub keys: usize,
+    // keys with expiration time
+    pub expires: usize,
+    pub memory: usize,
+    pub reads: usize,
+    pub writes: usize,
+    pub contended: usize,
+}
+
+pub type UpdateFunction<'a> = &'a mut dyn FnMut(&mut Option<ValueHolder>, &mut Option<u64>) -> Result<(), &'static str>;
+
+// storage of one key map, methods are called concurrently from all connection threads
+// a backend adds the memory of its records to the memory counter passed to the factory,
+// this counter is checked against maximum memory
+pub trait StorageBackend: Send + Sync {
+    // encodes the value into result when the key is found and not expired
+    fn get(&self, key: &[u8], result: &mut Vec<u8>, start_time: SystemTime) -> GetResult;
+    fn set_value(&self, key: &[u8], value: ValueHolder, expiry: Option<u64>, start_time: SystemTime);
+    // sets several keys of this key map, a backend should take its lock only once
+    fn set_values(&self, values: Vec<(&Vec<u8>, ValueHolder)>, expiry: Option<u64>, start_time: SystemTime) {
+        values.into_iter().for_each(|(k, v)| self.set_value(k, v, expiry, start_time));
+    }
+    // sets the expiration time of an existing key to now + expiry ms, None - the key does not expire,
+    // returns false when the key is not found or expired
+    fn set_expiry(&self, key: &[u8], expiry: Option<u64>, start_time: SystemTime) -> bool;
+    // None - the key is not found or expired, Some(None) - the key does not expire,
+    // Some(Some(ms)) - remaining time to live
+    fn ttl(&self, key: &[u8], start_time: SystemTime) -> Option<Option<u64>>;
+    // calls f with the value when the key is found and not expired, returns false otherwise,
+    // an expired record is not removed, so there is no lazy expiration to report
+    fn read_value(&self, key: &[u8], start_time: SystemTime, f: &mut dyn FnMut(&ValueHolder)) -> bool {
+        let mut found = false;
+        let _ = self.update(key, start_time, &mut |v, _ttl| {
+            let value = v.as_ref().ok_or("not found")?;
+            f(value);
+            found = true;
+            Ok(())
+        });
+        found
+    }
+    // read-modify-write of one key under the key map lock, f gets the current value (None - the key is not found
+    // or expired) and its time to live in ms (None - the key does not expire) and can change them in place,
+    // create or remove the value, the value must not be changed when f returns an error,
+    // returns true when an expired record of the key was removed
+    fn update(&self, key: &[u8], start_time: SystemTime, f: UpdateFunction<'_>) -> Result<bool, &'static str>;
+    // memory used by the key and its value, None - the key is not found or expired
+    fn memory_usage(&self, key: &[u8], start_time: SystemTime) -> Option<usize>;
+    // returns the removed keys
+    fn removekeys<'a>(&self, keys: Vec<&'a Vec<u8>>) -> Vec<&'a Vec<u8>>;
+    // the key could be updated after the expiration check, so it should be checked again
+    // returns true when the key was removed
+    fn remove_if_expired(&self, key: &[u8], start_time: SystemTime) -> bool;
+    fn flush(&self);
+    // adds not expired keys from at least count entries (when there are enough) starting at cursor (0 - the first call) to keys,
+    // returns the cursor of the next call (0 - there are no more entries) and the number of visited entries
+    fn scan(&self, cursor: usize, count: usize, start_time: SystemTime, keys: &mut Vec<Vec<u8>>) -> (usize, usize);
+    // removes expired keys, when there are no expired keys - up to eviction_batch keys selected by
+    // the eviction policy
+    fn evict(&self, start_time: SystemTime) -> EvictedKeys;
+    // the key map with the lowest score is selected for eviction, None - nothing to evict
+    fn eviction_score(&self) -> Option<u64>;
+    fn set_eviction_parameters(&self, eviction_policy: EvictionPolicy, eviction_batch: usize);
+    fn is_fragmented(&self) -> bool;
+    // releases unused memory
+    fn shrink(&self);
+    fn size(&self) -> usize;
+    // number of expired and evicted keys
+    fn removal_stats(&self) -> (usize, usize);
+    // number of compressed values, their original and compressed sizes
+    fn compression_stats(&self) -> (usize, usize, usize);
+    fn shard_stats(&self) -> ShardStats;
+}
+
+// creates a backend for one key map, the argument is the memory counter shared by all key maps
+pub type StorageBackendFactory = Arc<dyn Fn(Arc<AtomicUsize>) -> Box<dyn StorageBackend> + Send + Sync>;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::SystemTime;
+    use crate::common_data::build_common_data_with_backend;
+    use crate::common_maps::{common_maps_factory, EvictionPolicy};
+    use crate::hash_builders::create_hash_builder;
+    use crate::storage_backend::{EvictedKeys, GetResult, ShardStats, StorageBackend, StorageBackendFactory, UpdateFunction};
+    use crate::values::{ValueHolder, ValueSettings};
+
+    // counts set calls and forwards everything to the default backend
+    struct CountingBackend {
+        sets: Arc<AtomicUsize>,
+        backend: Box<dyn StorageBackend>,
+    }
+
+    impl StorageBackend for CountingBackend {
+        fn get(&self, key: &[u8], result: &mut Vec<u8>, start_time: SystemTime) -> GetResult {
+            self.backend.get(key, result, start_time)
+        }
+
+        fn set_value(&self, key: &[u8], value: ValueHolder, expiry: Option<u64>, start_time: SystemTime) {
+            self.sets.fetch_add(1, Ordering::Relaxed);
+            self.backend.set_value(key, value, expiry, start_time)
+        }
+
+        fn set_expiry(&self, key: &[u8], expiry: Option<u64>, start_time: SystemTime) -> bool {
+            self.backend.set_expiry(key, expiry, start_time)
+        }
+
+        fn ttl(&self, key: &[u8], start_time: SystemTime) -> Option<Option<u64>> {
+            self.backend.ttl(key, start_time)
+        }
+
+        fn update(&self, key: &[u8], start_time: SystemTime, f: UpdateFunction<'_>) -> Result<bool, &'static str> {
+            self.sets.fetch_add(1, Ordering::Relaxed);
+            self.backend.update(key, start_time, f)
+        }
+
+        fn memory_usage(&self, key: &[u8], start_time: SystemTime) -> Option<usize> {
+            self.backend.memory_usage(key, start_time)
+        }
+
+        fn removekeys<'a>(&self, keys: Vec<&'a Vec<u8>>) -> Vec<&'a Vec<u8>> {
+            self.backend.removekeys(keys)
+        }
+
+        fn remove_if_expired(&self, key: &[u8], start_time: SystemTime) -> bool {
+            self.backend.remove_if_expired(key, start_time)
+        }
+
+        fn flush(&self) {
+            self.backend.flush()
+        }
+
+        fn scan(&self, cursor: usize, count: usize, start_time: SystemTime, keys: &mut Vec<Vec<u8>>) -> (usize, usize) {
+            self.backend.scan(cursor, count, start_time, keys)
+        }
+
+        fn evict(&self, start_time: SystemTime) -> EvictedKeys {
+            self.backend.evict(start_time)
+        }
+
+        fn eviction_score(&self) -> Option<u64> {
+            self.backend.eviction_score()
+        }
+
+        fn set_eviction_parameters(&self, eviction_policy: EvictionPolicy, eviction_batch: usize) {
+            self.backend.set_eviction_parameters(eviction_policy, eviction_batch)
+        }
+
+        fn is_fragmented(&self) -> bool {
+            self.backend.is_fragmented()
+        }
+
+        fn shrink(&self) {
+            self.backend.shrink()
+        }
+
+        fn size(&self) -> usize {
+            self.backend.size()
+        }
+
+        fn removal_stats(&self) -> (usize, usize) {
+            self.backend.removal_stats()
+        }
+
+        fn compression_stats(&self) -> (usize, usize, usize) {
+            self.backend.compression_stats()
+        }
+
+        fn shard_stats(&self) -> ShardStats {
+            self.backend.shard_stats()
+        }
+    }
+
+    #[test]
+    fn test_custom_backend() {
+        let sets = Arc::new(AtomicUsize::new(0));
+        let s = sets.clone();
+        let default_factory = common_maps_factory();
+        let factory: StorageBackendFactory = Arc::new(move |used_memory| {
+            Box::new(CountingBackend { sets: s.clone(), backend: default_factory(used_memory) })
+        });
+        let data = build_common_data_with_backend(false, 100000, 4,
+                                                  create_hash_builder("fnv1a".to_string(), 4).unwrap(), false,
+                                                  ValueSettings{ compression_threshold: 0, integer_encoding: true, intern_threshold: 0 },
+                                                  factory);
+        for i in 0..10 {
+            let key = i.to_string().into_bytes();
+            data.set(&key, &key, None);
+        }
+        let mut result = Vec::new();
+        assert!(data.get(&"5".to_string().into_bytes(), &mut result));
+        assert_eq!(result, "$1\r\n5\r\n".to_string().into_bytes());
+        assert_eq!(sets.load(Ordering::Relaxed), 10);
+        assert_eq!(data.size(), 10);
+    }
+}
@@ -0,0 +1,318 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+use lz4_flex::block::{compress, decompress};
+use crate::resp_encoder::{resp_encode_binary_string, resp_encode_error, resp_encode_string};
+use crate::member_set::MemberSet;
+use crate::shared_values::{SharedBytes, SharedValues};
+use crate::sorted_set::SortedSet;
+use crate::values::ValueHolder::{CompressedValue, FloatValue, HashSetValue, IntValue, SharedValue, SortedSetValue,
+                                 StringValue};
+
+#[derive(Clone)]
+pub struct ValueSettings {
+    // 0 - compression is disabled
+    pub compression_threshold: usize,
+    pub integer_encoding: bool,
+    // 0 - interning is disabled
+    pub intern_threshold: usize,
+}
+
+pub enum ValueHolder {
+    StringValue(Vec<u8>),
+    // lz4 compressed data and original value size
+    CompressedValue(Vec<u8>, usize),
+    IntValue(i64),
+    // result of INCRBYFLOAT, always finite
+    FloatValue(f64),
+    // interned value, memory is accounted in SharedValues
+    SharedValue(SharedBytes),
+    HashSetValue(MemberSet),
+    SortedSetValue(SortedSet),
+}
+
+pub const WRONGTYPE_ERROR: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+// returns a number only when its decimal representation gives back exactly the same bytes
+fn parse_integer(value: &Vec<u8>) -> Option<i64> {
+    if value.is_empty() || value.len() > 20 || !(value[0] == b'-' || value[0].is_ascii_digit()) {
+        return None;
+    }
+    let i = std::str::from_utf8(value).ok()?.parse::<i64>().ok()?;
+    if i.to_string().as_bytes() == value.as_slice() {
+        Some(i)
+    } else {
+        None
+    }
+}
+
+// INCRBYFLOAT operand or stored value, infinity and NaN are not accepted
+pub fn parse_float(value: &[u8]) -> Option<f64> {
+    let f = std::str::from_utf8(value).ok()?.parse::<f64>().ok()?;
+    if f.is_finite() {
+        Some(f)
+    } else {
+        None
+    }
+}
+
+// significant digits of INCRBYFLOAT results, Redis prints 17 digits of a long double, f64 has 15 reliable ones
+const FLOAT_DIGITS: i32 = 15;
+
+// INCRBYFLOAT result like in Redis: fixed notation without trailing zeros, rounded to FLOAT_DIGITS significant digits,
+// so 0.1 + 0.2 is 0.3 and not 0.30000000000000004
+pub fn format_float(value: f64) -> String {
+    let digits = if value == 0.0 { 0 } else { value.abs().log10().floor() as i32 + 1 };
+    let formatted = format!("{:.*}", (FLOAT_DIGITS - digits).max(0) as usize, value);
+    let formatted = if formatted.contains('.') {
+        formatted.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        formatted.as_str()
+    };
+    if formatted == "-0" { "0".to_string() } else { formatted.to_string() }
+}
+
+// sorted set scores and coordinates: shortest representation that gives back the same number, as in Redis replies
+pub fn format_score(value: f64) -> String {
+    value.to_string()
+}
+
+impl ValueHolder {
+    pub fn new(value: &Vec<u8>, settings: &ValueSettings, shared_values: &Arc<SharedValues>) -> ValueHolder {
+        if settings.integer_encoding {
+            if let Some(i) = parse_integer(value) {
+                return IntValue(i);
+            }
+        }
+        if settings.intern_threshold > 0 && value.len() >= settings.intern_threshold {
+            return SharedValue(shared_values.share(value));
+        }
+        let compression_threshold = settings.compression_threshold;
+        if compression_threshold > 0 && value.len() >= compression_threshold {
+            let compressed = compress(value);
+            if compressed.len() < value.len() {
+                return CompressedValue(compressed, value.len());
+            }
+        }
+        StringValue(value.clone())
+    }
+
+    // size of the stored data
+    pub fn size(&self) -> usize {
+        match self {
+            StringValue(v) => v.len(),
+            CompressedValue(v, _) => v.len(),
+            IntValue(_) => 8,
+            FloatValue(_) => 8,
+            SharedValue(_) => 8,
+            HashSetValue(s) => s.size(),
+            SortedSetValue(s) => s.size()
+        }
+    }
+
+    // DEBUG OBJECT encoding, Redis names are used where the representation is the same
+    pub fn encoding_name(&self) -> &'static str {
+        match self {
+            StringValue(_) => "raw",
+            CompressedValue(_, _) => "lz4",
+            IntValue(_) => "int",
+            FloatValue(_) => "float",
+            SharedValue(_) => "shared",
+            HashSetValue(_) => "hashtable",
+            SortedSetValue(_) => "skiplist"
+        }
+    }
+
+    pub fn is_string(&self) -> bool {
+        !matches!(self, HashSetValue(_) | SortedSetValue(_))
+    }
+
+    // TYPE command reply
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            HashSetValue(_) => "set",
+            SortedSetValue(_) => "zset",
+            _ => "string"
+        }
+    }
+
+    // value as it is returned to the client, compressed values are decompressed, empty for sets and sorted sets
+    pub fn get_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            StringValue(v) => Cow::Borrowed(v),
+            CompressedValue(v, size) => Cow::Owned(decompress(v, *size).expect("corrupted compressed value")),
+            IntValue(i) => Cow::Owned(i.to_string().into_bytes()),
+            FloatValue(f) => Cow::Owned(format_float(*f).into_bytes()),
+            SharedValue(v) => Cow::Borrowed(v.get()),
+            HashSetValue(_) | SortedSetValue(_) => Cow::Borrowed(&[])
+        }
+    }
+
+    // GETRANGE, inclusive range, negative indexes are counted from the end and clamped to 0 like in Redis
+    pub fn get_range(&self, start: isize, end: isize) -> Vec<u8> {
+        let bytes = self.get_bytes();
+        let len = bytes.len() as isize;
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let end = if end < 0 { (len + end).max(0) } else { end }.min(len - 1);
+        if start > end || len == 0 {
+            return Vec::new();
+        }
+        bytes[start as usize..=end as usize].to_vec()
+    }
+
+    // SETRANGE, the value is padded with zero bytes up to offset
+    pub fn set_range(self, offset: usize, value: &[u8]) -> ValueHolder {
+        let mut bytes = match self {
+            StringValue(v) => v,
+            other => other.get_bytes().into_owned()
+        };
+        if bytes.len() < offset + value.len() {
+            bytes.resize(offset + value.len(), 0);
+        }
+        bytes[offset..offset + value.len()].copy_from_slice(value);
+        StringValue(bytes)
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            IntValue(i) => Some(*i as f64),
+            FloatValue(f) => Some(*f),
+            HashSetValue(_) | SortedSetValue(_) => None,
+            _ => parse_float(&self.get_bytes())
+        }
+    }
+
+    pub fn encode(&self, result: &mut Vec<u8>) {
+        match self {
+            StringValue(v) => resp_encode_binary_string(v, result),
+            CompressedValue(v, size) => {
+                let value = decompress(v, *size).expect("corrupted compressed value");
+                resp_encode_binary_string(&value, result);
+            }
+            IntValue(i) => resp_encode_string(&i.to_string(), result),
+            FloatValue(f) => resp_encode_string(&format_float(*f), result),
+            SharedValue(v) => resp_encode_binary_string(v.get(), result),
+            // GET of a set or a sorted set
+            HashSetValue(_) | SortedSetValue(_) => resp_encode_error(WRONGTYPE_ERROR, result)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shared_values::SharedValues;
+    use crate::values::{format_float, format_score, parse_float, ValueHolder, ValueSettings};
+    use crate::values::ValueHolder::{CompressedValue, FloatValue, IntValue, SharedValue, StringValue};
+
+    fn build_settings(compression_threshold: usize, integer_encoding: bool) -> ValueSettings {
+        ValueSettings{ compression_threshold, integer_encoding, intern_threshold: 0 }
+    }
+
+    #[test]
+    fn test_compression() {
+        let value = "{\"name\":\"value\"}".repeat(100).into_bytes();
+        let shared_values = SharedValues::new();
+        let holder = ValueHolder::new(&value, &build_settings(100, true), &shared_values);
+        assert!(matches!(holder, CompressedValue(_, 1600)));
+        assert!(holder.size() < value.len());
+        let mut result = Vec::new();
+        holder.encode(&mut result);
+        let mut expected = "$1600\r\n".to_string().into_bytes();
+        expected.extend(&value);
+        expected.extend_from_slice("\r\n".as_bytes());
+        assert_eq!(result, expected);
+
+        assert!(matches!(ValueHolder::new(&value, &build_settings(0, true), &shared_values), StringValue(_)));
+        assert!(matches!(ValueHolder::new(&value, &build_settings(2000, true), &shared_values), StringValue(_)));
+    }
+
+    #[test]
+    fn test_integer_encoding() {
+        let settings = build_settings(0, true);
+        let shared_values = SharedValues::new();
+        for (s, expected) in [("123", Some(123)), ("0", Some(0)), ("-42", Some(-42)),
+                              ("-9223372036854775808", Some(i64::MIN)), ("9223372036854775808", None),
+                              ("0123", None), ("-0", None), ("+1", None), ("1.5", None), ("", None)] {
+            let holder = ValueHolder::new(&s.to_string().into_bytes(), &settings, &shared_values);
+            match expected {
+                Some(e) => assert!(matches!(holder, IntValue(i) if i == e), "{}", s),
+                None => assert!(matches!(holder, StringValue(_)), "{}", s)
+            }
+            let mut result = Vec::new();
+            holder.encode(&mut result);
+            assert_eq!(result, format!("${}\r\n{}\r\n", s.len(), s).into_bytes());
+        }
+        assert!(matches!(ValueHolder::new(&"123".to_string().into_bytes(), &build_settings(0, false), &shared_values),
+                         StringValue(_)));
+    }
+
+    #[test]
+    fn test_float_values() {
+        assert_eq!(parse_float(b"10.5"), Some(10.5));
+        assert_eq!(parse_float(b"5.0e3"), Some(5000.0));
+        assert_eq!(parse_float(b"-3"), Some(-3.0));
+        assert_eq!(parse_float(b"inf"), None);
+        assert_eq!(parse_float(b"nan"), None);
+        assert_eq!(parse_float(b" 1"), None);
+        assert_eq!(parse_float(b"abc"), None);
+        assert_eq!(format_float(10.5 + 0.1), "10.6");
+        assert_eq!(format_float(5000.0), "5000");
+        assert_eq!(format_float(0.1 + 0.2), "0.3");
+        assert_eq!(format_float(0.7 + 0.1), "0.8");
+        assert_eq!(format_float(-1.5e-7), "-0.00000015");
+        assert_eq!(format_float(1e20), "100000000000000000000");
+        assert_eq!(format_float(-0.0), "0");
+        assert_eq!(format_score(0.1 + 0.2), "0.30000000000000004");
+
+        let holder = FloatValue(3.25);
+        assert_eq!(holder.size(), 8);
+        assert_eq!(holder.as_float(), Some(3.25));
+        let mut result = Vec::new();
+        holder.encode(&mut result);
+        assert_eq!(result, "$4\r\n3.25\r\n".as_bytes());
+        assert_eq!(IntValue(-2).as_float(), Some(-2.0));
+        assert_eq!(StringValue(b"1.5".to_vec()).as_float(), Some(1.5));
+        assert_eq!(StringValue(b"x".to_vec()).as_float(), None);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let holder = StringValue(b"Hello World".to_vec());
+        assert_eq!(holder.get_range(0, 4), b"Hello");
+        assert_eq!(holder.get_range(-3, -1), b"rld");
+        assert_eq!(holder.get_range(0, -1), b"Hello World");
+        assert_eq!(holder.get_range(10, 100), b"d");
+        assert_eq!(holder.get_range(5, 3), b"");
+        assert_eq!(holder.get_range(-100, 1), b"He");
+        assert_eq!(holder.get_range(0, -100), b"H");
+        assert_eq!(holder.get_range(1, -100), b"");
+        assert_eq!(StringValue(Vec::new()).get_range(0, -100), b"");
+        assert_eq!(IntValue(12345).get_range(1, 2), b"23");
+
+        let holder = holder.set_range(6, b"Redis");
+        assert!(matches!(&holder, StringValue(v) if v == b"Hello Redis"));
+        let holder = StringValue(Vec::new()).set_range(3, b"ab");
+        assert!(matches!(&holder, StringValue(v) if v == b"\0\0\0ab"));
+        assert!(matches!(IntValue(100).set_range(1, b"5"), StringValue(v) if v == b"150"));
+    }
+
+    #[test]
+    fn test_interning() {
+        let mut settings = build_settings(10, true);
+        settings.intern_threshold = 20;
+        let shared_values = SharedValues::new();
+        let value = "{\"name\":\"value\"}".repeat(10).into_bytes();
+        let v1 = ValueHolder::new(&value, &settings, &shared_values);
+        let v2 = ValueHolder::new(&value, &settings, &shared_values);
+        assert!(matches!(v1, SharedValue(_)));
+        assert!(matches!(ValueHolder::new(&"short".to_string().into_bytes(), &settings, &shared_values),
+                         StringValue(_)));
+        assert_eq!(shared_values.count(), 1);
+        assert_eq!(shared_values.memory(), value.len());
+        let mut result = Vec::new();
+        v2.encode(&mut result);
+        assert_eq!(result, format!("$160\r\n{}\r\n", String::from_utf8(value).unwrap()).into_bytes());
+        drop(v1);
+        drop(v2);
+        assert_eq!(shared_values.count(), 0);
+    }
+}